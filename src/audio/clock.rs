@@ -0,0 +1,295 @@
+//! Keeps track of where the music is, as heard by the player
+
+use std::{collections::VecDeque, time};
+
+use crate::audio::Audio;
+
+/// How many playhead replies the clock fits a line through
+const WINDOW: usize = 32;
+
+/// A playhead reply this many seconds off from the clock's estimate means the music jumped, e.g.
+/// because it was sought or replaced, so the older replies are thrown away.
+const RESYNC_THRESHOLD: f64 = 0.1;
+
+/// How much faster or slower than the system clock the audio device's clock is allowed to run
+const MAX_DRIFT: f64 = 0.01;
+
+/// Seconds from `origin` to `instant`, which is negative if `instant` is earlier
+fn seconds_between(origin: time::Instant, instant: time::Instant) -> f64 {
+    if instant >= origin {
+        (instant - origin).as_secs_f64()
+    } else {
+        -(origin - instant).as_secs_f64()
+    }
+}
+
+/// Estimates the music's position at any instant from the audio thread's playhead replies.
+///
+/// Each reply says which frame the audio thread was about to mix when a buffer was requested.
+/// Those replies jitter with the buffer callbacks, so instead of trusting any one of them, the
+/// clock fits a line through the recent ones. The slope of the line follows the audio device's
+/// clock, so the estimate doesn't drift away from the music between replies.
+#[derive(Debug)]
+pub struct Clock {
+    /// Seconds between a sample being mixed and it coming out of the speakers
+    latency: f64,
+
+    /// What the times in `replies` are measured from
+    origin: Option<time::Instant>,
+
+    /// Recent playhead replies, as seconds after `origin` and the music time heard at that moment
+    replies: VecDeque<(f64, f64)>,
+
+    /// Music time = `intercept` + `slope` * seconds after `origin`
+    slope: f64,
+    intercept: f64,
+
+    /// Whether a playhead was requested from the audio thread and hasn't arrived yet
+    requested: bool,
+
+    /// Whether the next playhead reply was requested before the clock was reset
+    stale: bool,
+}
+
+impl Clock {
+    /// Create a clock for an output with `latency` seconds of latency. See `Audio::latency`.
+    pub fn new(latency: f64) -> Self {
+        Clock {
+            latency,
+            origin: None,
+            replies: VecDeque::with_capacity(WINDOW),
+            slope: 1.0,
+            intercept: 0.0,
+            requested: false,
+            stale: false,
+        }
+    }
+
+    /// Forget every playhead reply, e.g. after the music was paused or replaced. `time` returns
+    /// `None` until a reply to a request made after this arrives.
+    pub fn reset(&mut self) {
+        self.stale = self.requested;
+        self.origin = None;
+        self.replies.clear();
+        self.slope = 1.0;
+        self.intercept = 0.0;
+    }
+
+    /// Receive any playhead replies from the audio thread, and ask for another one if there isn't
+    /// one on the way. Should be called regularly, e.g. on every event.
+    pub fn update<S: cpal::Sample>(&mut self, audio: &Audio<S>) {
+        while let Some((instant, playhead)) = audio.get_playhead() {
+            self.requested = false;
+            if self.stale {
+                self.stale = false;
+            } else {
+                self.add_playhead(instant, playhead);
+            }
+        }
+        if !self.requested {
+            match audio.request_playhead() {
+                Ok(()) => self.requested = true,
+                Err(e) => remani_warn!("Error requesting audio playhead: {}", e),
+            }
+        }
+    }
+
+    /// Add a playhead reply: the audio thread was about to mix `playhead` seconds into the music
+    /// at `instant`.
+    pub fn add_playhead(&mut self, instant: time::Instant, playhead: f64) {
+        let heard = playhead - self.latency;
+        if let Some(estimate) = self.time_at(instant) {
+            if (heard - estimate).abs() > RESYNC_THRESHOLD {
+                self.origin = None;
+                self.replies.clear();
+            }
+        }
+
+        let origin = *self.origin.get_or_insert(instant);
+        if self.replies.len() == WINDOW {
+            self.replies.pop_front();
+        }
+        self.replies.push_back((seconds_between(origin, instant), heard));
+        self.fit();
+    }
+
+    /// Fit a line through the replies with least squares
+    fn fit(&mut self) {
+        let count = self.replies.len() as f64;
+        let (x_sum, y_sum) = self.replies.iter().fold((0.0, 0.0), |(xs, ys), &(x, y)| (xs + x, ys + y));
+        let (x_mean, y_mean) = (x_sum / count, y_sum / count);
+        let (covariance, variance) = self.replies.iter().fold((0.0, 0.0), |(c, v), &(x, y)| {
+            (c + (x - x_mean) * (y - y_mean), v + (x - x_mean).powi(2))
+        });
+
+        // Not enough of a spread to tell the speed of the audio device's clock yet
+        self.slope = if variance > 1e-6 {
+            (covariance / variance).max(1.0 - MAX_DRIFT).min(1.0 + MAX_DRIFT)
+        } else {
+            1.0
+        };
+        self.intercept = y_mean - self.slope * x_mean;
+    }
+
+    /// The music time being heard at `instant`, in seconds, or `None` if no playhead replies have
+    /// arrived since the clock was created or reset
+    pub fn time_at(&self, instant: time::Instant) -> Option<f64> {
+        self.origin.map(|origin| self.intercept + self.slope * seconds_between(origin, instant))
+    }
+
+    /// The music time being heard right now. See `time_at`.
+    pub fn time(&self) -> Option<f64> {
+        self.time_at(time::Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    /// Pretends to be an audio device that asks for `buffer_frames` frames at a time, with its
+    /// callbacks arriving up to `jitter` seconds late and its clock running `drift` times as fast
+    /// as the system clock
+    struct SimulatedDevice {
+        start: time::Instant,
+        sample_rate: f64,
+        buffer_frames: u64,
+        jitter: f64,
+        drift: f64,
+        frames: u64,
+        /// For the jitter, so the tests are repeatable
+        rng: u64,
+    }
+
+    impl SimulatedDevice {
+        fn new(buffer_frames: u64, jitter: f64, drift: f64) -> Self {
+            SimulatedDevice {
+                start: time::Instant::now(),
+                sample_rate: 48000.0,
+                buffer_frames,
+                jitter,
+                drift,
+                frames: 0,
+                rng: 0x2545_f491_4f6c_dd1d,
+            }
+        }
+
+        /// The system time that the music is at `seconds`, according to the device
+        fn instant(&self, seconds: f64) -> time::Instant {
+            self.start + Duration::from_secs_f64(seconds / self.drift)
+        }
+
+        /// The next buffer callback's instant and playhead
+        fn callback(&mut self) -> (time::Instant, f64) {
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 7;
+            self.rng ^= self.rng << 17;
+            let lateness = (self.rng % 1000) as f64 / 1000.0 * self.jitter;
+
+            let playhead = self.frames as f64 / self.sample_rate;
+            self.frames += self.buffer_frames;
+            (self.instant(playhead + lateness), playhead)
+        }
+    }
+
+    #[test]
+    fn no_time_before_first_playhead() {
+        let mut clock = Clock::new(0.0);
+        assert_eq!(clock.time(), None);
+        clock.add_playhead(time::Instant::now(), 1.0);
+        assert!(clock.time().is_some());
+        clock.reset();
+        assert_eq!(clock.time(), None);
+    }
+
+    #[test]
+    fn follows_jittery_callbacks() {
+        let mut device = SimulatedDevice::new(1024, 0.005, 1.0);
+        let mut clock = Clock::new(0.0);
+        for _ in 0..200 {
+            let (instant, playhead) = device.callback();
+            clock.add_playhead(instant, playhead);
+        }
+        let now = 200.0 * 1024.0 / 48000.0;
+        let error = clock.time_at(device.instant(now)).unwrap() - now;
+        // The callbacks are 2.5 ms late on average, anything much worse than that is from jitter
+        assert!(error.abs() < 0.0035, "error of {} seconds", error);
+    }
+
+    #[test]
+    fn follows_drifting_device() {
+        let mut device = SimulatedDevice::new(512, 0.0, 1.005);
+        let mut clock = Clock::new(0.0);
+        for _ in 0..200 {
+            let (instant, playhead) = device.callback();
+            clock.add_playhead(instant, playhead);
+        }
+        // A second after the last reply, the clock should still be in sync with the device
+        let now = 200.0 * 512.0 / 48000.0 + 1.0;
+        let error = clock.time_at(device.instant(now)).unwrap() - now;
+        assert!(error.abs() < 0.0005, "error of {} seconds", error);
+    }
+
+    #[test]
+    fn subtracts_latency() {
+        let mut device = SimulatedDevice::new(256, 0.0, 1.0);
+        let mut clock = Clock::new(0.02);
+        for _ in 0..10 {
+            let (instant, playhead) = device.callback();
+            clock.add_playhead(instant, playhead);
+        }
+        let error = clock.time_at(device.instant(1.0)).unwrap() - (1.0 - 0.02);
+        assert!(error.abs() < 1e-6, "error of {} seconds", error);
+    }
+
+    #[test]
+    fn resyncs_after_jump() {
+        let mut device = SimulatedDevice::new(256, 0.0, 1.0);
+        let mut clock = Clock::new(0.0);
+        for _ in 0..20 {
+            let (instant, playhead) = device.callback();
+            clock.add_playhead(instant, playhead);
+        }
+        // The music got sought 30 seconds ahead
+        let (instant, playhead) = device.callback();
+        clock.add_playhead(instant, playhead + 30.0);
+        let error = clock.time_at(instant).unwrap() - (playhead + 30.0);
+        assert!(error.abs() < 1e-6, "error of {} seconds", error);
+    }
+
+    #[test]
+    fn updates_from_audio_thread() {
+        let (audio, mut mixer) = crate::audio::mixer(&cpal::Format {
+            channels: 2,
+            sample_rate: cpal::SampleRate(48000),
+            data_type: cpal::SampleFormat::F32,
+        });
+        let mut clock = Clock::new(0.0);
+        let mut buffer = vec![0.0; 2 * 480];
+
+        clock.update(&audio);
+        assert_eq!(clock.time(), None);
+        mixer.mix(&mut buffer);
+        mixer.mix(&mut buffer);
+        clock.update(&audio);
+        let time = clock.time().unwrap();
+        assert!(time >= 0.0 && time < 0.1, "time of {} seconds", time);
+
+        // The last update asked for another playhead
+        mixer.mix(&mut buffer);
+        clock.update(&audio);
+        let time = clock.time().unwrap();
+        assert!(time > 0.005 && time < 0.1, "time of {} seconds", time);
+
+        // The reply to the request from before resetting is thrown away
+        clock.reset();
+        mixer.mix(&mut buffer);
+        clock.update(&audio);
+        assert_eq!(clock.time(), None);
+        mixer.mix(&mut buffer);
+        clock.update(&audio);
+        assert!(clock.time().is_some());
+    }
+}
@@ -0,0 +1,488 @@
+//! Mixes the music and effects sent through an `Audio` handle into output buffers
+
+use std::{
+    collections::VecDeque,
+    sync::mpsc,
+    time,
+};
+
+use cpal::Sample;
+
+use crate::audio::{ArcIter, AudioStatus, Bus, EffectStream, MusicStream, VoiceStealing};
+
+/// Mixed samples louder than this get gradually compressed by `soft_limit`.
+const LIMITER_THRESHOLD: f32 = 0.8;
+
+/// Keep a sample between -1.0 and 1.0 without clipping harshly. Samples quieter than
+/// `LIMITER_THRESHOLD` pass through unchanged, and louder ones smoothly approach 1.0.
+fn soft_limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= LIMITER_THRESHOLD {
+        sample
+    } else {
+        let headroom = 1.0 - LIMITER_THRESHOLD;
+        let limited = LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh();
+        limited.copysign(sample)
+    }
+}
+
+/// How much of a panned sound goes into output channel `channel`. Uses balance panning, so a
+/// centered sound plays at full volume in both speakers, and channels past the first two (center,
+/// surround, etc.) aren't affected.
+fn pan_gain(pan: f32, channel: usize) -> f32 {
+    match channel {
+        0 => (1.0 - pan).min(1.0),
+        1 => (1.0 + pan).min(1.0),
+        _ => 1.0,
+    }
+}
+
+/// The volume of every bus, as set by `Audio::set_volume`
+struct BusVolumes {
+    master: f32,
+    music: f32,
+    keysound: f32,
+    autoplay: f32,
+}
+
+impl BusVolumes {
+    /// The volume applied to sounds on `bus`. `Master` is applied to the whole mix separately, so
+    /// sounds played directly on it aren't scaled here.
+    fn get(&self, bus: Bus) -> f32 {
+        match bus {
+            Bus::Master => 1.0,
+            Bus::Music => self.music,
+            Bus::Keysound => self.keysound,
+            Bus::Autoplay => self.autoplay,
+        }
+    }
+}
+
+/// An effect that's currently being played by the mixer
+pub(super) struct Voice<S: cpal::Sample = f32> {
+    samples: ArcIter<S>,
+    volume: f32,
+    pan: f32,
+    bus: Bus,
+    choke_group: Option<usize>,
+}
+
+impl<S: cpal::Sample> Voice<S> {
+    pub(super) fn new(effect: EffectStream<S>, bus: Bus) -> Self {
+        Voice {
+            samples: ArcIter::new(effect.samples),
+            volume: effect.volume,
+            pan: effect.pan,
+            bus,
+            choke_group: effect.choke_group,
+        }
+    }
+}
+
+/// How many effects can play at once until `Audio::set_polyphony` is called
+const DEFAULT_MAX_VOICES: usize = 64;
+
+/// Stop one of the voices that are playing to make room for a new one. `voices` is in the order
+/// the voices started playing in.
+fn steal_voice(voices: &mut VecDeque<Voice>, stealing: VoiceStealing, volumes: &BusVolumes) {
+    let index = match stealing {
+        VoiceStealing::Oldest => 0,
+        VoiceStealing::Quietest => voices
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, v.volume * volumes.get(v.bus)))
+            .fold(None, |quietest: Option<(usize, f32)>, (i, loudness)| match quietest {
+                Some((_, l)) if l <= loudness => quietest,
+                _ => Some((i, loudness)),
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(0),
+    };
+    voices.remove(index);
+}
+
+/// The mixer's end of the channels an `Audio` handle sends on
+pub(super) struct Receivers {
+    pub(super) music: mpsc::Receiver<MusicStream>,
+    pub(super) effect: mpsc::Receiver<Voice>,
+    pub(super) schedule: mpsc::Receiver<(u64, f64, Voice)>,
+    pub(super) volume: mpsc::Receiver<(Bus, f32)>,
+    pub(super) pause: mpsc::Receiver<bool>,
+    pub(super) seek: mpsc::Receiver<f64>,
+    pub(super) polyphony: mpsc::Receiver<(usize, VoiceStealing)>,
+    pub(super) request_playhead: mpsc::Receiver<()>,
+    pub(super) request_status: mpsc::Receiver<()>,
+    pub(super) send_playhead: mpsc::SyncSender<(time::Instant, f64)>,
+    pub(super) send_status: mpsc::SyncSender<AudioStatus>,
+}
+
+/// Everything the audio thread keeps track of. An `Output` pulls samples out of it whenever it
+/// needs more.
+pub struct Mixer {
+    receivers: Receivers,
+    channel_count: usize,
+    sample_rate: u32,
+
+    effects: VecDeque<Voice>,
+    /// Effects waiting to start and the music frame they start on, sorted by frame
+    scheduled: VecDeque<(u64, Voice)>,
+    max_voices: usize,
+    stealing: VoiceStealing,
+    volumes: BusVolumes,
+    music: Option<MusicStream>,
+    music_paused: bool,
+
+    // hopefully u64 is big enough and no one tries to play a 3 million year 192kHz audio file
+    current_music_frame_index: u64,
+
+    /// How many times the music has been replaced or sought, counted the same way as
+    /// `Audio::music_generation`
+    music_generation: u64,
+
+    /// Whether the `Audio` handle still exists
+    connected: bool,
+}
+
+impl Mixer {
+    pub(super) fn new(receivers: Receivers, format: &cpal::Format) -> Self {
+        Mixer {
+            receivers,
+            channel_count: format.channels as usize,
+            sample_rate: format.sample_rate.0,
+            effects: VecDeque::with_capacity(128),
+            scheduled: VecDeque::with_capacity(256),
+            max_voices: DEFAULT_MAX_VOICES,
+            stealing: VoiceStealing::Oldest,
+            volumes: BusVolumes {
+                master: 1.0,
+                music: 1.0,
+                keysound: 1.0,
+                autoplay: 1.0,
+            },
+            music: None,
+            music_paused: false,
+            current_music_frame_index: 0,
+            music_generation: 0,
+            connected: true,
+        }
+    }
+
+    /// Whether the `Audio` handle this mixer gets sounds from still exists. Once it's gone,
+    /// nothing new will ever be played.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Handle everything sent by the `Audio` handle since the last buffer. `instant` is when the
+    /// buffer was asked for.
+    fn receive(&mut self, instant: time::Instant) {
+        while let Ok((bus, volume)) = self.receivers.volume.try_recv() {
+            match bus {
+                Bus::Master => self.volumes.master = volume,
+                Bus::Music => self.volumes.music = volume,
+                Bus::Keysound => self.volumes.keysound = volume,
+                Bus::Autoplay => self.volumes.autoplay = volume,
+            }
+        }
+        while let Ok((max, s)) = self.receivers.polyphony.try_recv() {
+            self.max_voices = max;
+            self.stealing = s;
+        }
+        loop {
+            match self.receivers.effect.try_recv() {
+                Ok(voice) => self.start_voice(voice),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    break;
+                }
+            }
+        }
+        while let Ok(m) = self.receivers.music.try_recv() {
+            self.current_music_frame_index = (m.start_position * self.sample_rate as f64).round() as u64;
+            self.music = Some(m);
+            self.music_paused = false;
+            self.music_changed();
+        }
+        while let Ok(seconds) = self.receivers.seek.try_recv() {
+            self.music_changed();
+            if let Some(ref mut m) = self.music {
+                match m.seek(seconds) {
+                    Ok(()) => self.current_music_frame_index = (m.start_position * self.sample_rate as f64).round() as u64,
+                    Err(e) => remani_warn!("Error seeking music: {}", e),
+                }
+            }
+        }
+        // Received after the music, so effects scheduled right after changing the music aren't
+        // mistaken for ones scheduled on the old music
+        while let Ok((generation, time, voice)) = self.receivers.schedule.try_recv() {
+            if generation == self.music_generation {
+                let frame = (time.max(0.0) * self.sample_rate as f64).round() as u64;
+                let index = self.scheduled.iter().position(|&(f, _)| f > frame).unwrap_or(self.scheduled.len());
+                self.scheduled.insert(index, (frame, voice));
+            }
+        }
+        while let Ok(p) = self.receivers.pause.try_recv() {
+            self.music_paused = p;
+        }
+        // If a playhead request was sent, send the playhead
+        if self.receivers.request_playhead.try_recv().is_ok() {
+            let playhead = self.current_music_frame_index as f64 / self.sample_rate as f64;
+            if self.receivers.send_playhead.try_send((instant, playhead)).is_err() {
+                remani_warn!("Failed to send audio playhead"); // this isn't supposed to happen, but just in case?
+            }
+        }
+        // If a status request was sent, send the status
+        if self.receivers.request_status.try_recv().is_ok() {
+            let status = AudioStatus { is_playing_music: self.music.is_some() };
+            if self.receivers.send_status.try_send(status).is_err() {
+                remani_warn!("Failed to send audio status"); // this isn't supposed to happen, but just in case?
+            }
+        }
+    }
+
+    /// Start playing an effect, cutting off its choke group and stealing a voice if needed
+    fn start_voice(&mut self, voice: Voice) {
+        if let Some(group) = voice.choke_group {
+            self.effects.retain(|v| v.choke_group != Some(group));
+        }
+        while self.effects.len() >= self.max_voices {
+            steal_voice(&mut self.effects, self.stealing, &self.volumes);
+        }
+        self.effects.push_back(voice);
+    }
+
+    /// The music was replaced or sought, so the scheduled effects are on the wrong timeline
+    fn music_changed(&mut self) {
+        self.music_generation += 1;
+        self.scheduled.clear();
+    }
+
+    /// Get the next sample for `channel` and mix it
+    // TODO use SIMD
+    fn next_sample(&mut self, channel: usize) -> f32 {
+        let music_sample = match self.music {
+            Some(_) if self.music_paused => 0.0,
+            Some(ref mut m) => match m.next() {
+                Some(n) => n,
+                None => {
+                    self.music = None;
+                    0.0
+                }
+            }
+            None => 0.0
+        };
+        let mut s = music_sample * self.volumes.music;
+        for voice in self.effects.iter_mut() {
+            if let Some(sample) = voice.samples.next() {
+                s += sample.to_f32() * voice.volume * self.volumes.get(voice.bus) * pan_gain(voice.pan, channel);
+            }
+        }
+        soft_limit(s * self.volumes.master)
+    }
+
+    /// Fill `buffer` with interleaved samples. Anything sent by the `Audio` handle before this is
+    /// called starts at the beginning of the buffer.
+    pub fn mix(&mut self, buffer: &mut [f32]) {
+        // Taken first, so the playhead is timed against the buffer callback and not however long
+        // receiving took
+        self.receive(time::Instant::now());
+
+        for frame in buffer.chunks_mut(self.channel_count) {
+            while self.scheduled.front().map_or(false, |&(f, _)| f <= self.current_music_frame_index) {
+                let (_, voice) = self.scheduled.pop_front().unwrap();
+                self.start_voice(voice);
+            }
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = self.next_sample(channel);
+            }
+            if !self.music_paused {
+                self.current_music_frame_index += 1;
+            }
+        }
+
+        // Remove any effects that are finished playing. This keeps the rest in the order they
+        // started playing in, which `VoiceStealing::Oldest` relies on.
+        self.effects.retain(|v| !v.samples.finished());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{self, Audio};
+
+    use std::{iter, sync::Arc};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn format() -> cpal::Format {
+        cpal::Format {
+            channels: 2,
+            sample_rate: cpal::SampleRate(SAMPLE_RATE),
+            data_type: cpal::SampleFormat::F32,
+        }
+    }
+
+    fn effect(samples: Vec<f32>) -> EffectStream {
+        EffectStream::from((1.0, Arc::new(samples)))
+    }
+
+    fn mix_frames(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0; frames * 2];
+        mixer.mix(&mut buffer);
+        buffer
+    }
+
+    fn setup() -> (Audio, Mixer) {
+        audio::mixer(&format())
+    }
+
+    #[test]
+    fn effects_add_together() {
+        let (audio, mut mixer) = setup();
+        audio.play_effect(effect(vec![0.25; 4]), Bus::Keysound);
+        audio.play_effect(effect(vec![0.125; 2]), Bus::Autoplay);
+        let buffer = mix_frames(&mut mixer, 3);
+        assert_eq!(buffer, vec![0.375, 0.375, 0.25, 0.25, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn bus_volumes_apply() {
+        let (audio, mut mixer) = setup();
+        audio.set_volume(Bus::Keysound, 0.5);
+        audio.set_volume(Bus::Autoplay, 0.0);
+        audio.play_effect(effect(vec![0.5; 2]), Bus::Keysound);
+        audio.play_effect(effect(vec![0.5; 2]), Bus::Autoplay);
+        assert_eq!(mix_frames(&mut mixer, 1), vec![0.25, 0.25]);
+    }
+
+    #[test]
+    fn pan_moves_effect_between_channels() {
+        let (audio, mut mixer) = setup();
+        audio.play_effect(effect(vec![0.5; 2]).with_pan(-1.0), Bus::Keysound);
+        assert_eq!(mix_frames(&mut mixer, 1), vec![0.5, 0.0]);
+        audio.play_effect(effect(vec![0.5; 2]).with_pan(0.5), Bus::Keysound);
+        assert_eq!(mix_frames(&mut mixer, 1), vec![0.25, 0.5]);
+    }
+
+    #[test]
+    fn limiter_keeps_samples_in_range() {
+        let (audio, mut mixer) = setup();
+        for _ in 0..8 {
+            audio.play_effect(effect(vec![1.0; 2]), Bus::Keysound);
+        }
+        let buffer = mix_frames(&mut mixer, 1);
+        assert!(buffer.iter().all(|&s| s > LIMITER_THRESHOLD && s <= 1.0));
+        assert_eq!(soft_limit(0.5), 0.5);
+        assert_eq!(soft_limit(-0.5), -0.5);
+    }
+
+    #[test]
+    fn effect_starts_at_next_buffer() {
+        let (audio, mut mixer) = setup();
+        assert!(mix_frames(&mut mixer, 256).iter().all(|&s| s == 0.0));
+        audio.play_effect(effect(vec![0.5; 2]), Bus::Keysound);
+        let buffer = mix_frames(&mut mixer, 256);
+        assert_eq!(&buffer[..4], &[0.5, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn choke_group_cuts_off_previous_instance() {
+        let (audio, mut mixer) = setup();
+        audio.play_effect(effect(vec![0.25; 8]).with_choke_group(Some(3)), Bus::Keysound);
+        mix_frames(&mut mixer, 1);
+        audio.play_effect(effect(vec![0.125; 8]).with_choke_group(Some(3)), Bus::Keysound);
+        assert_eq!(mix_frames(&mut mixer, 1), vec![0.125, 0.125]);
+    }
+
+    #[test]
+    fn voice_stealing() {
+        let (audio, mut mixer) = setup();
+        audio.set_polyphony(2, VoiceStealing::Oldest);
+        audio.play_effect(effect(vec![0.25; 8]), Bus::Keysound);
+        audio.play_effect(effect(vec![0.125; 8]), Bus::Keysound);
+        audio.play_effect(effect(vec![0.0625; 8]), Bus::Keysound);
+        assert_eq!(mix_frames(&mut mixer, 1), vec![0.1875, 0.1875]);
+
+        let (audio, mut mixer) = setup();
+        audio.set_polyphony(2, VoiceStealing::Quietest);
+        audio.play_effect(effect(vec![0.5; 8]).with_volume(0.5), Bus::Keysound);
+        audio.play_effect(effect(vec![0.5; 8]).with_volume(0.125), Bus::Keysound);
+        audio.play_effect(effect(vec![0.5; 8]).with_volume(0.25), Bus::Keysound);
+        assert_eq!(mix_frames(&mut mixer, 1), vec![0.375, 0.375]);
+    }
+
+    #[test]
+    fn playhead_follows_music() {
+        let (audio, mut mixer) = setup();
+        audio.play_music(MusicStream::new(iter::repeat(0.0)));
+        mix_frames(&mut mixer, 480);
+        mix_frames(&mut mixer, 480);
+        audio.request_playhead().unwrap();
+        mix_frames(&mut mixer, 480);
+        assert_eq!(audio.get_playhead().map(|(_, p)| p), Some(960.0 / SAMPLE_RATE as f64));
+
+        audio.pause_music();
+        mix_frames(&mut mixer, 480);
+        audio.request_playhead().unwrap();
+        mix_frames(&mut mixer, 480);
+        assert_eq!(audio.get_playhead().map(|(_, p)| p), Some(1440.0 / SAMPLE_RATE as f64));
+    }
+
+    #[test]
+    fn playhead_starts_at_seek_position() {
+        let (audio, mut mixer) = setup();
+        let mut music = MusicStream::new(iter::repeat(0.0));
+        music.seek(2.0).unwrap();
+        audio.play_music(music);
+        mix_frames(&mut mixer, 480);
+        audio.request_playhead().unwrap();
+        mix_frames(&mut mixer, 480);
+        assert_eq!(audio.get_playhead().map(|(_, p)| p), Some((2.0 * SAMPLE_RATE as f64 + 480.0) / SAMPLE_RATE as f64));
+    }
+
+    #[test]
+    fn scheduled_effect_starts_on_exact_frame() {
+        let (audio, mut mixer) = setup();
+        audio.play_music(MusicStream::new(iter::repeat(0.0)));
+        audio.schedule_effect(effect(vec![0.5; 2]), Bus::Autoplay, 300.0 / SAMPLE_RATE as f64);
+        audio.schedule_effect(effect(vec![0.25; 2]), Bus::Autoplay, 100.0 / SAMPLE_RATE as f64);
+        let buffer = mix_frames(&mut mixer, 256);
+        assert!(buffer.iter().enumerate().all(|(i, &s)| s == if i / 2 == 100 { 0.25 } else { 0.0 }));
+        let buffer = mix_frames(&mut mixer, 256);
+        assert!(buffer.iter().enumerate().all(|(i, &s)| s == if i / 2 == 300 - 256 { 0.5 } else { 0.0 }));
+    }
+
+    #[test]
+    fn late_scheduled_effect_starts_at_next_buffer() {
+        let (audio, mut mixer) = setup();
+        audio.play_music(MusicStream::new(iter::repeat(0.0)));
+        mix_frames(&mut mixer, 256);
+        audio.schedule_effect(effect(vec![0.5; 2]), Bus::Autoplay, 0.0);
+        assert_eq!(&mix_frames(&mut mixer, 2)[..], &[0.5, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn scheduled_effects_dropped_when_music_changes() {
+        let (audio, mut mixer) = setup();
+        audio.play_music(MusicStream::new(iter::repeat(0.0)));
+        audio.schedule_effect(effect(vec![0.5; 2]), Bus::Autoplay, 2.0 / SAMPLE_RATE as f64);
+        audio.schedule_effect(effect(vec![0.5; 2]), Bus::Autoplay, 4.0 / SAMPLE_RATE as f64);
+        mix_frames(&mut mixer, 1);
+        audio.seek_music(0.0);
+        // Scheduled after seeking, so this one still plays
+        audio.schedule_effect(effect(vec![0.25; 2]), Bus::Autoplay, 3.0 / SAMPLE_RATE as f64);
+        assert_eq!(mix_frames(&mut mixer, 5), vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.25, 0.25, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn disconnects_when_handle_dropped() {
+        let (audio, mut mixer) = setup();
+        mix_frames(&mut mixer, 1);
+        assert!(mixer.is_connected());
+        drop(audio);
+        mix_frames(&mut mixer, 1);
+        assert!(!mixer.is_connected());
+    }
+}
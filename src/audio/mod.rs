@@ -9,20 +9,32 @@ mod wav;
 #[cfg(feature = "ogg")]
 mod ogg;
 
+mod clock;
+mod mixer;
+mod output;
 mod resample;
+mod stretch;
+
+pub use self::{
+    clock::Clock,
+    mixer::Mixer,
+    output::{CpalOutput, HeadlessOutput, Output},
+    resample::ResampleQuality,
+};
+use self::mixer::Voice;
 
 use std::{
-    collections::VecDeque,
+    cell::Cell,
     error,
     fmt,
-    iter::{self, Peekable},
+    iter,
     sync::mpsc,
     sync::Arc,
     thread,
     time,
 };
 
-use cpal::{self, Sample};
+use serde_derive::{Deserialize, Serialize};
 
 fn mix<I1, I2>(i1: I1, i2: I2) -> impl Iterator<Item = f32>
 where
@@ -44,8 +56,69 @@ pub struct EffectStream<S: cpal::Sample = f32> {
     samples: Arc<Vec<S>>,
     /// A number that the samples are multiplied by. i.e. 0.0 is 0% volume, 1.0 is 100% volume.
     volume: f32,
+    /// Where the effect sits between the speakers. -1.0 is fully left, 0.0 is centered and 1.0 is
+    /// fully right.
+    pan: f32,
+    /// Starting an effect stops any other effect in the same choke group that's still playing
+    choke_group: Option<usize>,
+}
+
+/// The error type from seeking a `MusicStream`
+#[derive(Debug)]
+pub enum SeekError {
+    /// The stream can't jump around
+    Unsupported,
+    Decode(String),
+}
+
+impl fmt::Display for SeekError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SeekError::Unsupported => write!(f, "Seeking isn't supported by this stream"),
+            SeekError::Decode(ref s) => write!(f, "Decode error while seeking: {}", s),
+        }
+    }
 }
 
+impl error::Error for SeekError {
+    fn description(&self) -> &str {
+        match *self {
+            SeekError::Unsupported => "Seeking unsupported",
+            SeekError::Decode(_) => "Decode error while seeking",
+        }
+    }
+}
+
+/// An interleaved sample iterator that might be able to jump to a different position.
+pub(crate) trait MusicSource<S: cpal::Sample = f32>: Iterator<Item = S> + Send {
+    /// Jump to `seconds` from the start of the audio. By default, seeking isn't supported.
+    fn seek(&mut self, _seconds: f64) -> Result<(), SeekError> {
+        Err(SeekError::Unsupported)
+    }
+}
+
+impl<S: cpal::Sample, T: MusicSource<S> + ?Sized> MusicSource<S> for Box<T> {
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        (**self).seek(seconds)
+    }
+}
+
+/// Silence sounds the same everywhere
+impl<S: cpal::Sample + Send> MusicSource<S> for iter::Repeat<S> {
+    fn seek(&mut self, _seconds: f64) -> Result<(), SeekError> {
+        Ok(())
+    }
+}
+
+impl<S: cpal::Sample> MusicSource<S> for iter::Empty<S> {
+    fn seek(&mut self, _seconds: f64) -> Result<(), SeekError> {
+        Ok(())
+    }
+}
+
+/// Doesn't know the sample rate, so it can't seek
+impl<S: cpal::Sample + Send + Sync> MusicSource<S> for ArcIter<S> {}
+
 impl EffectStream {
     /// Mix two `EffectStream`s together into a new EffectStream
     pub fn mix(&self, other: &EffectStream) -> EffectStream {
@@ -57,6 +130,8 @@ impl EffectStream {
                 ).collect()
             ),
             volume: 1.0,
+            pan: 0.0,
+            choke_group: None,
         }
     }
     /// Returns a zero length `EffectStream` for when you need an `EffectStream` but you don't
@@ -65,9 +140,16 @@ impl EffectStream {
         EffectStream {
             samples: Arc::new(vec![]),
             volume: 1.0,
+            pan: 0.0,
+            choke_group: None,
         }
     }
 
+    /// How many seconds the effect plays for in `format`
+    pub fn duration(&self, format: &cpal::Format) -> f64 {
+        self.samples.len() as f64 / f64::from(format.channels) / f64::from(format.sample_rate.0)
+    }
+
     pub fn set_volume(&mut self, volume: f32) {
         self.volume = volume;
     }
@@ -80,6 +162,45 @@ impl EffectStream {
     pub fn volume(&mut self, volume: f32) {
         self.volume = volume;
     }
+
+    /// Set where the effect sits between the speakers, from -1.0 (left) to 1.0 (right)
+    pub fn set_pan(&mut self, pan: f32) {
+        self.pan = pan.max(-1.0).min(1.0);
+    }
+
+    pub fn with_pan(mut self, pan: f32) -> Self {
+        self.set_pan(pan);
+        self
+    }
+
+    /// Put the effect in a choke group. Playing it cuts off every effect in the same group that's
+    /// still playing, e.g. so re-triggering a keysound stops the previous instance of it.
+    pub fn set_choke_group(&mut self, group: Option<usize>) {
+        self.choke_group = group;
+    }
+
+    pub fn with_choke_group(mut self, group: Option<usize>) -> Self {
+        self.set_choke_group(group);
+        self
+    }
+
+    /// Returns a copy of this effect played back `rate` times as fast. See
+    /// `MusicStream::with_rate`.
+    pub fn with_rate(
+        &self,
+        rate: f64,
+        format: &cpal::Format,
+        preserve_pitch: bool,
+        quality: ResampleQuality,
+    ) -> EffectStream {
+        let music = MusicStream::new(ArcIter::new(Arc::clone(&self.samples)));
+        EffectStream {
+            samples: Arc::new(music.with_rate(rate, format, preserve_pitch, quality).collect()),
+            volume: self.volume,
+            pan: self.pan,
+            choke_group: self.choke_group,
+        }
+    }
 }
 
 impl<S: cpal::Sample> From<(f32, Arc<Vec<S>>)> for EffectStream<S> {
@@ -87,6 +208,8 @@ impl<S: cpal::Sample> From<(f32, Arc<Vec<S>>)> for EffectStream<S> {
         EffectStream {
             samples: t.1,
             volume: t.0,
+            pan: 0.0,
+            choke_group: None,
         }
     }
 }
@@ -96,15 +219,28 @@ impl<S: cpal::Sample> From<MusicStream<S>> for EffectStream<S> {
         EffectStream {
             samples: Arc::new(a.samples.collect()),
             volume: 1.0,
+            pan: 0.0,
+            choke_group: None,
         }
     }
 }
 
+/// Plays the effect's samples as music. The effect's volume, pan and choke group aren't kept.
+impl<S: cpal::Sample + Send + Sync + 'static> From<EffectStream<S>> for MusicStream<S> {
+    fn from(e: EffectStream<S>) -> Self {
+        MusicStream::new(ArcIter::new(e.samples))
+    }
+}
+
 /// A struct that encapsulates a lazy iterator over audio samples with metadata. Channels and
 /// sample rate aren't stored because they are expected to match the output device.
 pub struct MusicStream<S: cpal::Sample = f32> {
     /// An interleaved iterator of samples
-    samples: Box<dyn Iterator<Item = S> + Send>,
+    samples: Box<dyn MusicSource<S>>,
+
+    /// Where the stream was last sought to, in seconds. The audio thread starts its playhead here
+    /// when it starts playing the stream.
+    start_position: f64,
 }
 
 /// Gets converted into a MusicStream after resampling. Used to avoid
@@ -122,10 +258,50 @@ impl<S: cpal::Sample> Iterator for MusicStream<S> {
     }
 }
 
+impl<S: cpal::Sample> MusicStream<S> {
+    fn new<I: MusicSource<S> + 'static>(samples: I) -> Self {
+        MusicStream {
+            samples: Box::new(samples),
+            start_position: 0.0,
+        }
+    }
+
+    /// Jump to `seconds` from the start of the music.
+    pub fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        let seconds = seconds.max(0.0);
+        self.samples.seek(seconds)?;
+        self.start_position = seconds;
+        Ok(())
+    }
+}
+
 impl MusicStream {
     pub fn zero() -> Self {
-        MusicStream {
-            samples: Box::new(iter::repeat(0.0)),
+        MusicStream::new(iter::repeat(0.0))
+    }
+
+    /// Change the playback rate, e.g. 2.0 plays twice as fast. If `preserve_pitch` is true, the
+    /// audio is time stretched instead of resampled with `quality`, so the pitch stays the same.
+    pub fn with_rate(
+        self,
+        rate: f64,
+        format: &cpal::Format,
+        preserve_pitch: bool,
+        quality: ResampleQuality,
+    ) -> MusicStream {
+        if rate == 1.0 {
+            self
+        } else if preserve_pitch {
+            MusicStream::new(stretch::TimeStretch::new(self.samples, format.channels as usize, rate))
+        } else {
+            // Pretend the audio was recorded at a different sample rate
+            let stream = GenericMusicStream {
+                samples: self.samples,
+                channel_count: format.channels as u8,
+                sample_rate: (format.sample_rate.0 as f64 * rate).round() as u32,
+            };
+            // One second of the result is `rate` seconds of the original
+            resample::from_music_stream_scaled(stream, format.sample_rate.0, rate, quality)
         }
     }
 }
@@ -147,6 +323,10 @@ impl<T: Copy> ArcIter<T> {
     pub fn inner(self) -> Arc<Vec<T>> {
         self.inner
     }
+    /// Whether every item has been iterated over
+    pub fn finished(&self) -> bool {
+        self.index >= self.inner.len()
+    }
 }
 
 impl<T: Copy> Iterator for ArcIter<T> {
@@ -163,6 +343,27 @@ impl<T: Copy> Iterator for ArcIter<T> {
     }
 }
 
+/// A group of sounds whose volume is controlled together. Everything also goes through `Master`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    Master,
+    Music,
+    /// Sounds played when the player hits a note
+    Keysound,
+    /// Sounds the chart plays by itself, like background keysounds
+    Autoplay,
+}
+
+/// Which voice to stop when an effect is played while `max_voices` effects are already playing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VoiceStealing {
+    /// The voice that started playing first
+    Oldest,
+    /// The voice with the lowest volume after its bus volume is applied
+    Quietest,
+}
+
 /// Contains information such as whether the audio thread is currently playing music or not.
 #[derive(Debug, Clone, Copy)]
 pub struct AudioStatus {
@@ -172,7 +373,28 @@ pub struct AudioStatus {
 /// A handle to the audio thread that lets you send music and effects to play.
 pub struct Audio<S: cpal::Sample = f32> {
     music_sender: mpsc::SyncSender<MusicStream<S>>,
-    effect_sender: mpsc::SyncSender<(f32, ArcIter<S>)>,
+    effect_sender: mpsc::SyncSender<Voice<S>>,
+
+    /// Sends effects to start at a position in the music, along with the `music_generation` they
+    /// were scheduled in
+    schedule_sender: mpsc::SyncSender<(u64, f64, Voice<S>)>,
+
+    /// How many times the music has been replaced or sought. Scheduled effects are only played
+    /// if the music hasn't changed since they were scheduled.
+    music_generation: Cell<u64>,
+
+    /// Sets the volume of a bus in the audio thread
+    volume_sender: mpsc::SyncSender<(Bus, f32)>,
+
+    /// Tells the audio thread to pause (`true`) or resume (`false`) the music
+    pause_sender: mpsc::SyncSender<bool>,
+
+    /// Tells the audio thread to seek the music to a position in seconds
+    seek_sender: mpsc::SyncSender<f64>,
+
+    /// Sets the maximum number of effects that can play at once and how to choose which one to
+    /// stop when there are too many
+    polyphony_sender: mpsc::SyncSender<(usize, VoiceStealing)>,
 
     /// Used by `request_playhead()` to ask the audio thread to send the playback time to playhead_rcv.
     request_playhead_sender: mpsc::SyncSender<()>,
@@ -190,17 +412,76 @@ pub struct Audio<S: cpal::Sample = f32> {
 
     /// The format of the audio device being used
     format: cpal::Format,
+
+    /// See `Output::latency`
+    latency: f64,
 }
 
 impl<S: cpal::Sample> Audio<S> {
     /// Start playing music, returning a `bool` indicating whether it was successful or not
     pub fn play_music(&self, music: MusicStream<S>) -> bool {
-        self.music_sender.try_send(music).is_ok()
+        self.music_changed(self.music_sender.try_send(music).is_ok())
+    }
+
+    /// Stop the music that's currently playing, returning a `bool` indicating whether it was
+    /// successful or not
+    pub fn stop_music(&self) -> bool
+    where
+        S: 'static,
+    {
+        self.music_changed(self.music_sender.try_send(MusicStream::new(iter::empty())).is_ok())
+    }
+
+    /// Jump to `seconds` from the start of the music that's currently playing, returning a `bool`
+    /// indicating whether the request was sent. The playhead follows the new position.
+    pub fn seek_music(&self, seconds: f64) -> bool {
+        self.music_changed(self.seek_sender.try_send(seconds).is_ok())
     }
 
-    /// Play a sound effect/hitsound, returning the passed in effect stream if there was an error
-    pub fn play_effect(&self, effect: EffectStream<S>) -> bool {
-        self.effect_sender.try_send((effect.volume, ArcIter::new(effect.samples))).is_ok()
+    /// Called after trying to change the music, so that effects scheduled on the old music don't
+    /// play on the new music
+    fn music_changed(&self, sent: bool) -> bool {
+        if sent {
+            self.music_generation.set(self.music_generation.get() + 1);
+        }
+        sent
+    }
+
+    /// Pause the music without losing its position. The playhead stops advancing until
+    /// `resume_music` is called.
+    pub fn pause_music(&self) -> bool {
+        self.pause_sender.try_send(true).is_ok()
+    }
+
+    /// Resume music paused by `pause_music`
+    pub fn resume_music(&self) -> bool {
+        self.pause_sender.try_send(false).is_ok()
+    }
+
+    /// Play a sound effect/hitsound on a bus, returning a `bool` indicating whether it was
+    /// successful or not
+    pub fn play_effect(&self, effect: EffectStream<S>, bus: Bus) -> bool {
+        self.effect_sender.try_send(Voice::new(effect, bus)).is_ok()
+    }
+
+    /// Play a sound effect on a bus when the music's playhead reaches `time` seconds, starting on
+    /// that exact frame. Effects scheduled for a time that's already passed play at the start of
+    /// the next buffer. If the music is replaced or sought before then, the effect is dropped.
+    /// Returns a `bool` indicating whether it was successful or not.
+    pub fn schedule_effect(&self, effect: EffectStream<S>, bus: Bus, time: f64) -> bool {
+        let generation = self.music_generation.get();
+        self.schedule_sender.try_send((generation, time, Voice::new(effect, bus))).is_ok()
+    }
+
+    /// Set the volume of a bus, where 0.0 is silent and 1.0 is unchanged. Takes effect right away.
+    pub fn set_volume(&self, bus: Bus, volume: f32) -> bool {
+        self.volume_sender.try_send((bus, volume.max(0.0))).is_ok()
+    }
+
+    /// Set how many effects can play at once, and which one gets cut off when another one starts
+    /// playing past the limit. Takes effect right away.
+    pub fn set_polyphony(&self, max_voices: usize, stealing: VoiceStealing) -> bool {
+        self.polyphony_sender.try_send((max_voices.max(1), stealing)).is_ok()
     }
 
     /// Sends a request to the audio thread for the current playhead of the music. `get_playhead`
@@ -226,6 +507,12 @@ impl<S: cpal::Sample> Audio<S> {
     pub fn format(&self) -> &cpal::Format {
         &self.format
     }
+
+    /// Roughly how many seconds it takes for a sample to be heard after it's mixed, or 0.0 if
+    /// it isn't known
+    pub fn latency(&self) -> f64 {
+        self.latency
+    }
 }
 
 #[derive(Debug)]
@@ -276,145 +563,68 @@ impl error::Error for AudioThreadError {
     }
 }
 
-/// Starts the audio thread and returns an object that can be used to communicate with the audio
-/// thread.
-pub fn start_audio_thread(mut audio_buffer_size: cpal::BufferSize) -> Result<Audio, AudioThreadError> {
-    let device = cpal::default_output_device().ok_or(AudioThreadError::NoOutputDevice)?;
-
-    println!("Using device {}", device.name());
-
-    let format = device.default_output_format()?;
-
-    let event_loop = cpal::EventLoop::new();
-    let stream_id = event_loop.build_output_stream(&device, &format, &mut audio_buffer_size)?;
-    println!("Using audio buffer size {:?}", audio_buffer_size);
-    event_loop.play_stream(stream_id.clone());
-
+/// Create an `Audio` handle and the `Mixer` that plays what's sent to it. Normally the mixer is
+/// given to an `Output` by `start_audio_thread_with`, but it can also be driven directly, e.g. in
+/// tests or for rendering audio offline.
+pub fn mixer(format: &cpal::Format) -> (Audio, Mixer) {
     let (request_playhead_tx, request_playhead_rx) = mpsc::sync_channel(1);
     let (request_status_tx, request_status_rx) = mpsc::sync_channel(1);
     let (send_playhead_tx, send_playhead_rx) = mpsc::sync_channel(4);
     let (send_status_tx, send_status_rx) = mpsc::sync_channel(4);
-    let (music_tx, music_rx) = mpsc::sync_channel(2);
-    let (effect_tx, effect_rx) = mpsc::sync_channel::<(f32, ArcIter<f32>)>(128);
-
-    let channel_count = format.channels as usize;
-    let sample_rate = format.sample_rate.0;
-
-    // Spawn the audio thread
-    thread::spawn(move || {
-        // (f64, ArcIter<f32>) = (volume, effect_stream_samples_iterator)
-        let mut effects: VecDeque<(f32, Peekable<ArcIter<f32>>)> = VecDeque::with_capacity(128);
-        let mut music: Option<MusicStream> = None;
-
-        // hopefully u64 is big enough and no one tries to play a 3 million year 192kHz audio file
-        let mut current_music_frame_index: u64 = 0;
-
-        // Audio loop
-        event_loop.run(move |_, data| {
-            while let Ok((volume, effect)) = effect_rx.try_recv() {
-                effects.push_back((volume, effect.peekable()));
-                // TODO do things (i forgot what this was supposed to do maybe I already did them?)
-            }
-            while let Ok(m) = music_rx.try_recv() {
-                music = Some(m);
-                current_music_frame_index = 0;
-            }
-            // If a playhead request was sent, send the playhead
-            if request_playhead_rx.try_recv().is_ok() {
-                if send_playhead_tx
-                    .try_send((time::Instant::now(), current_music_frame_index as f64 / sample_rate as f64))
-                    .is_err()
-                {
-                    remani_warn!("Failed to send audio playhead"); // this isn't supposed to happen, but just in case?
-                }
-
-            }
-            // If a status request was sent, send the status
-            if request_status_rx.try_recv().is_ok() {
-                if send_status_tx
-                    .try_send(AudioStatus { is_playing_music: music.is_some() })
-                    .is_err()
-                {
-                    remani_warn!("Failed to send audio status"); // this isn't supposed to happen, but just in case?
-                }
-            }
-
-            // Get samples and mix them
-            // TODO use SIMD
-            let mut s = |effects: &mut VecDeque<(f32, Peekable<ArcIter<f32>>)>| {
-                let mut s = match music {
-                    Some(ref mut m) => match m.next() {
-                        Some(n) => n,
-                        None => {
-                            music = None;
-                            0.0
-                        }
-                    }
-                    None => 0.0
-                };
-                for (volume, effect) in effects.iter_mut() {
-                    if let Some(sample) = effect.next() {
-                        s += sample.to_f32() * *volume;
-                    }
-                }
-                s / 2.0 // TODO don't do this and maybe have a volume setting control this? ¯\_(ツ)_/¯
-            };
-
-            match data { // TODO vectorize?
-                cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::U16(mut buffer) } => {
-                    for frame in buffer.chunks_mut(channel_count) {
-                        for sample in frame.iter_mut() {
-                            *sample = s(&mut effects).to_u16();
-                        }
-                        current_music_frame_index += 1;
-                    }
-                },
-
-                cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::I16(mut buffer) } => {
-                    for frame in buffer.chunks_mut(channel_count) {
-                        for sample in frame.iter_mut() {
-                            *sample = s(&mut effects).to_i16();
-                        }
-                        current_music_frame_index += 1;
-                    }
-                },
-
-                cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::F32(mut buffer) } => {
-                    for frame in buffer.chunks_mut(channel_count) {
-                        for sample in frame.iter_mut() {
-                            *sample = s(&mut effects);
-                        }
-                        current_music_frame_index += 1;
-                    }
-                },
-                _ => (),
-            }
-
-            // Remove any effects that are finished playing
-            let mut i = 0;
-            while i < effects.len() {
-
-                // Check if there are any samples left in the effect
-                if effects[i].1.peek().is_none() {
-                    effects.swap_remove_back(i);
-                } else {
-                    i += 1;
-                }
-            }
-        });
-    });
-
-    Ok(Audio {
+    let (music_tx, music_rx) = mpsc::sync_channel::<MusicStream>(2);
+    let (effect_tx, effect_rx) = mpsc::sync_channel(128);
+    let (schedule_tx, schedule_rx) = mpsc::sync_channel(256);
+    let (volume_tx, volume_rx) = mpsc::sync_channel(64);
+    let (pause_tx, pause_rx) = mpsc::sync_channel(4);
+    let (seek_tx, seek_rx) = mpsc::sync_channel(4);
+    let (polyphony_tx, polyphony_rx) = mpsc::sync_channel(4);
+
+    let audio = Audio {
         effect_sender: effect_tx,
+        schedule_sender: schedule_tx,
+        music_generation: Cell::new(0),
         music_sender: music_tx,
+        volume_sender: volume_tx,
+        pause_sender: pause_tx,
+        seek_sender: seek_tx,
+        polyphony_sender: polyphony_tx,
 
         request_playhead_sender: request_playhead_tx,
         playhead_rcv: send_playhead_rx,
         request_status_sender: request_status_tx,
         status_rcv: send_status_rx,
 
-        format,
-    })
+        format: format.clone(),
+        latency: 0.0,
+    };
+    let receivers = mixer::Receivers {
+        music: music_rx,
+        effect: effect_rx,
+        schedule: schedule_rx,
+        volume: volume_rx,
+        pause: pause_rx,
+        seek: seek_rx,
+        polyphony: polyphony_rx,
+        request_playhead: request_playhead_rx,
+        request_status: request_status_rx,
+        send_playhead: send_playhead_tx,
+        send_status: send_status_tx,
+    };
+    (audio, Mixer::new(receivers, format))
+}
+
+/// Starts the audio thread on the default output device and returns an object that can be used to
+/// communicate with the audio thread.
+pub fn start_audio_thread(audio_buffer_size: cpal::BufferSize) -> Result<Audio, AudioThreadError> {
+    Ok(start_audio_thread_with(CpalOutput::new(audio_buffer_size)?))
+}
+
+/// Starts the audio thread on any `Output`
+pub fn start_audio_thread_with<O: Output>(output: O) -> Audio {
+    let (mut audio, mixer) = mixer(&output.format());
+    audio.latency = output.latency();
+    thread::spawn(move || output.run(mixer));
+    audio
 }
 
 use std::ffi;
@@ -493,23 +703,39 @@ impl From<MusicFormat> for String {
 
 fn maybe_resample<I>(
     stream: GenericMusicStream<I>,
-    format: &cpal::Format
+    format: &cpal::Format,
+    quality: ResampleQuality,
 ) -> MusicStream
 where
-    I: Iterator<Item = f32> + Send + 'static
+    I: MusicSource + 'static
 {
     if stream.sample_rate == format.sample_rate.0 {
-        MusicStream {
-            samples: Box::new(stream.samples),
-        }
+        MusicStream::new(stream.samples)
     } else {
-        resample::from_music_stream(stream, format.sample_rate.0)
+        resample::from_music_stream(stream, format.sample_rate.0, quality)
     }
 }
 
+/// Resample interleaved `samples` from one sample rate to another
+pub fn resample(
+    samples: Arc<Vec<f32>>,
+    channel_count: u8,
+    from_sample_rate: u32,
+    to_sample_rate: u32,
+    quality: ResampleQuality,
+) -> MusicStream {
+    let stream = GenericMusicStream {
+        samples: ArcIter::new(samples),
+        channel_count,
+        sample_rate: from_sample_rate,
+    };
+    resample::from_music_stream(stream, to_sample_rate, quality)
+}
+
 pub fn music_from_path<P: AsRef<Path>>(
     path: P,
     format: &cpal::Format,
+    quality: ResampleQuality,
 ) -> Result<MusicStream, AudioLoadError> {
     let file = File::open(&path)?;
     let extension = path
@@ -520,13 +746,13 @@ pub fn music_from_path<P: AsRef<Path>>(
 
     let stream = match extension.as_ref().map(String::as_str) {
         #[cfg(feature = "mp3")]
-        Some("mp3") => maybe_resample(mp3::decode(file).map_err(AudioLoadError::from)?, format),
+        Some("mp3") => maybe_resample(mp3::decode(file).map_err(AudioLoadError::from)?, format, quality),
 
         #[cfg(feature = "wav")]
-        Some("wav") => maybe_resample(wav::decode(file).map_err(AudioLoadError::from)?, format),
+        Some("wav") => maybe_resample(wav::decode(file).map_err(AudioLoadError::from)?, format, quality),
 
         #[cfg(feature = "ogg")]
-        Some("ogg") => maybe_resample(ogg::decode(file).map_err(AudioLoadError::from)?, format),
+        Some("ogg") => maybe_resample(ogg::decode(file).map_err(AudioLoadError::from)?, format, quality),
 
         Some(s) => return Err(AudioLoadError::UnsupportedFormat(s.into())),
         None => return Err(AudioLoadError::UnsupportedFormat("No extension".into())),
@@ -538,16 +764,17 @@ pub fn music_from_reader<R: io::Read + io::Seek + Send + 'static>(
     reader: R,
     cpal_format: &cpal::Format,
     music_format: MusicFormat,
+    quality: ResampleQuality,
 ) -> Result<MusicStream, AudioLoadError> {
     Ok(match music_format {
         #[cfg(feature = "mp3")]
-        MusicFormat::Mp3 => maybe_resample(mp3::decode(reader).map_err(AudioLoadError::from)?, cpal_format),
+        MusicFormat::Mp3 => maybe_resample(mp3::decode(reader).map_err(AudioLoadError::from)?, cpal_format, quality),
 
         #[cfg(feature = "wav")]
-        MusicFormat::Wav => maybe_resample(wav::decode(reader).map_err(AudioLoadError::from)?, cpal_format),
+        MusicFormat::Wav => maybe_resample(wav::decode(reader).map_err(AudioLoadError::from)?, cpal_format, quality),
 
         #[cfg(feature = "ogg")]
-        MusicFormat::Ogg => maybe_resample(ogg::decode(reader).map_err(AudioLoadError::from)?, cpal_format),
+        MusicFormat::Ogg => maybe_resample(ogg::decode(reader).map_err(AudioLoadError::from)?, cpal_format, quality),
 
         f => return Err(AudioLoadError::UnsupportedFormat(f.into())),
     })
@@ -1,14 +1,30 @@
 //! Load MP3 files
 
-use crate::audio::GenericMusicStream;
+use crate::audio::{GenericMusicStream, MusicSource, SeekError};
 
-use std::{io, iter::Peekable};
+use std::{
+    cmp::Ordering,
+    io,
+    iter::Peekable,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use simplemad::{self, Decoder, MadFixed32, SimplemadError};
 
+/// Lets the decoder and `MP3Samples` share the reader, so `MP3Samples` can rewind it to seek.
+struct SharedReader<R>(Arc<Mutex<R>>);
+
+impl<R: io::Read> io::Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read(buf)
+    }
+}
+
 /// Lazy iterator over audio samples from an MP3
-struct MP3Samples<R: io::Read + Send> {
-    decoder: Peekable<simplemad::Decoder<R>>,
+struct MP3Samples<R: io::Read + io::Seek + Send> {
+    decoder: Peekable<simplemad::Decoder<SharedReader<R>>>,
+    reader: Arc<Mutex<R>>,
     current_samples: Option<Vec<Vec<MadFixed32>>>,
     current_samples_index: usize,
 
@@ -17,21 +33,33 @@ struct MP3Samples<R: io::Read + Send> {
 
     /// Whether the end of the file has been reached yet.
     eof: bool,
+
+    /// Seeking only goes to the start of an mp3 frame, so the samples between the start of the
+    /// frame and this time have to be thrown away.
+    seek_target: Option<Duration>,
+    /// Where the decoder started in the file, since the positions of its frames start at 0 there
+    start_position: Duration,
+    /// Where each frame is, read on the first seek so the file is only scanned once
+    frames: Option<Vec<(u64, f64)>>,
 }
 
-impl<R: io::Read + Send> MP3Samples<R> {
-    fn new(decoder: Peekable<Decoder<R>>) -> MP3Samples<R> {
+impl<R: io::Read + io::Seek + Send> MP3Samples<R> {
+    fn new(decoder: Peekable<Decoder<SharedReader<R>>>, reader: Arc<Mutex<R>>) -> MP3Samples<R> {
         MP3Samples {
             decoder,
+            reader,
             current_samples: None,
             current_samples_index: 0,
             current_channel: 0,
             eof: false,
+            seek_target: None,
+            start_position: Duration::from_secs(0),
+            frames: None,
         }
     }
 }
 
-impl<R: io::Read + Send> Iterator for MP3Samples<R> {
+impl<R: io::Read + io::Seek + Send> Iterator for MP3Samples<R> {
     type Item = f32;
     fn next(&mut self) -> Option<f32> {
         if self.eof {
@@ -44,11 +72,26 @@ impl<R: io::Read + Send> Iterator for MP3Samples<R> {
                 match self.decoder.next() {
                     Some(r) => match r {
                         Ok(f) => {
+                            let position = self.start_position + f.position;
+                            let skip = match self.seek_target {
+                                Some(target) if target > position => {
+                                    ((target - position).as_secs_f64() * f.sample_rate as f64) as usize
+                                }
+                                _ => 0,
+                            };
+                            // The frames decoded before the target are only there to warm up the
+                            // decoder
+                            if skip >= f.samples[0].len() {
+                                continue;
+                            }
+                            self.seek_target = None;
+                            self.current_samples_index = skip;
                             self.current_samples = Some(f.samples);
-                            self.current_samples_index = 0;
                             self.current_channel = 0;
                             break;
                         }
+                        // Warming up after a seek starts without the data from the frames before
+                        Err(SimplemadError::Mad(_)) if self.seek_target.is_some() => (),
                         Err(SimplemadError::Mad(e)) => remani_warn!("libmad err: {:?}", e),
                         Err(SimplemadError::Read(e)) => remani_warn!("mp3 read err: {}", e),
                         Err(SimplemadError::EOF) => {
@@ -80,12 +123,169 @@ impl<R: io::Read + Send> Iterator for MP3Samples<R> {
     }
 }
 
+impl<R: io::Read + io::Seek + Send> MusicSource for MP3Samples<R> {
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        // simplemad can only decode forwards, so find the frame by its header and start a new
+        // decoder a few frames before it
+        let target = Duration::from_secs_f64(seconds);
+        let (offset, start_position) = {
+            let mut reader = self.reader.lock().unwrap();
+            if self.frames.is_none() {
+                let frames = frame_index(&mut *reader).map_err(|e| SeekError::Decode(e.to_string()))?;
+                self.frames = Some(frames);
+            }
+            let (offset, start_position) = self.frames.as_ref().and_then(|f| find_frame(f, seconds))
+                .ok_or_else(|| SeekError::Decode("No mp3 frames found".to_owned()))?;
+            reader.seek(io::SeekFrom::Start(offset))
+                .map_err(|e| SeekError::Decode(e.to_string()))?;
+            (offset, start_position)
+        };
+        self.decoder = Decoder::decode(SharedReader(Arc::clone(&self.reader)))
+            .map_err(|e| SeekError::Decode(format!("Can't decode from byte {}: {:?}", offset, e)))?
+            .peekable();
+        self.current_samples = None;
+        self.current_samples_index = 0;
+        self.current_channel = 0;
+        self.eof = false;
+        self.seek_target = Some(target);
+        self.start_position = Duration::from_secs_f64(start_position);
+        Ok(())
+    }
+}
+
+/// Layer III frames can use data from up to 511 bytes of the frames before them, a few frames at
+/// low bitrates, and each frame is overlapped with the one before. Decoding starts this many
+/// frames before the seek target, so the target's frame comes out right.
+const WARM_UP_FRAMES: usize = 10;
+
+/// Bitrates in kbps, by [MPEG 1, MPEG 2 or 2.5][layer - 1][bitrate index]. Index 0 is the free
+/// format, which doesn't say its frames' lengths.
+const BITRATES: [[[u32; 15]; 3]; 2] = [
+    [
+        [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+        [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    ],
+    [
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ],
+];
+
+/// Sample rates of MPEG 1, they're halved for MPEG 2 and quartered for MPEG 2.5
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// The (length in bytes, length in frames of samples, sample rate) of the mp3 frame starting with
+/// `header`, or `None` if it isn't a frame header
+fn parse_header(header: [u8; 4]) -> Option<(u64, u64, u32)> {
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    // 0 is MPEG 2.5, 2 is MPEG 2 and 3 is MPEG 1
+    let version = (header[1] >> 3) & 0b11;
+    let layer = match (header[1] >> 1) & 0b11 {
+        0 => return None,
+        l => 4 - l as usize,
+    };
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0b11) as usize;
+    if version == 1 || bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+    let padding = ((header[2] >> 1) & 1) as u64;
+
+    let mpeg1 = version == 3;
+    let bitrate = BITRATES[!mpeg1 as usize][layer - 1][bitrate_index] as u64 * 1000;
+    let sample_rate = SAMPLE_RATES[sample_rate_index] >> (3 - version).min(2);
+    let samples = match layer {
+        1 => 384,
+        3 if !mpeg1 => 576,
+        _ => 1152,
+    };
+    let length = match layer {
+        1 => (12 * bitrate / sample_rate as u64 + padding) * 4,
+        _ => samples / 8 * bitrate / sample_rate as u64 + padding,
+    };
+    Some((length, samples, sample_rate))
+}
+
+/// The (offset in bytes, start time in seconds) of every frame in an mp3, found by reading only the
+/// frames' headers
+fn frame_index<R: io::Read + io::Seek>(reader: &mut R) -> io::Result<Vec<(u64, f64)>> {
+    let mut offset = reader.seek(io::SeekFrom::Start(0))?;
+
+    // Skip the ID3v2 tag
+    let mut tag_header = [0; 10];
+    if read_fully(reader, &mut tag_header)? && &tag_header[..3] == b"ID3" {
+        let size = tag_header[6..10].iter().fold(0, |size, &b| size << 7 | (b & 0x7F) as u64);
+        let footer = if tag_header[5] & 0x10 != 0 { 10 } else { 0 };
+        offset = 10 + size + footer;
+    }
+    reader.seek(io::SeekFrom::Start(offset))?;
+    let mut reader = io::BufReader::new(reader);
+
+    let mut frames = Vec::new();
+    let mut time = 0.0;
+    let mut header = [0; 4];
+    if !read_fully(&mut reader, &mut header)? {
+        return Ok(frames);
+    }
+    loop {
+        match parse_header(header) {
+            Some((length, samples, sample_rate)) => {
+                frames.push((offset, time));
+                time += samples as f64 / sample_rate as f64;
+                offset += length;
+                // Skip the rest of the frame
+                let body = length.saturating_sub(header.len() as u64);
+                if io::copy(&mut io::Read::take(&mut reader, body), &mut io::sink())? < body
+                    || !read_fully(&mut reader, &mut header)?
+                {
+                    break;
+                }
+            }
+            // Look for the next frame a byte further on
+            None => {
+                let mut byte = [0];
+                if !read_fully(&mut reader, &mut byte)? {
+                    break;
+                }
+                header.copy_within(1.., 0);
+                header[3] = byte[0];
+                offset += 1;
+            }
+        }
+    }
+    Ok(frames)
+}
+
+/// Find the frame `WARM_UP_FRAMES` before the one playing at `seconds`, or before the last one if
+/// `seconds` is past the end. Returns its offset in bytes and the time it starts at.
+fn find_frame(frames: &[(u64, f64)], seconds: f64) -> Option<(u64, f64)> {
+    let playing = match frames.binary_search_by(|f| f.1.partial_cmp(&seconds).unwrap_or(Ordering::Less)) {
+        Ok(i) => i,
+        Err(i) => i.saturating_sub(1),
+    };
+    frames.get(playing.saturating_sub(WARM_UP_FRAMES)).cloned()
+}
+
+/// Fill `buf`, returning false if the reader ends first
+fn read_fully<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
 // Hope nothing bad happens
-unsafe impl<R: io::Read + Send> Send for MP3Samples<R> {}
+unsafe impl<R: io::Read + io::Seek + Send> Send for MP3Samples<R> {}
 
 /// Create a stream that reads from an mp3
-pub(super) fn decode<R: io::Read + Send + 'static>(reader: R) -> Result<GenericMusicStream<impl Iterator<Item = f32> + Send, f32>, String> {
-    let mut decoder = match Decoder::decode(reader) {
+pub(super) fn decode<R: io::Read + io::Seek + Send + 'static>(reader: R) -> Result<GenericMusicStream<impl MusicSource, f32>, String> {
+    let reader = Arc::new(Mutex::new(reader));
+    let mut decoder = match Decoder::decode(SharedReader(Arc::clone(&reader))) {
         Ok(d) => d.peekable(),
         Err(e) => return Err(format!("{:?}", e)),
     };
@@ -106,8 +306,57 @@ pub(super) fn decode<R: io::Read + Send + 'static>(reader: R) -> Result<GenericM
         channel_count = frame.samples.len();
     }
     Ok(GenericMusicStream {
-        samples: MP3Samples::new(decoder),
+        samples: MP3Samples::new(decoder, reader),
         channel_count: channel_count as u8,
         sample_rate,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MPEG 1 layer III, 128 kbps, 44100 Hz
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const FRAME_TIME: f64 = 1152.0 / 44100.0;
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(parse_header(HEADER), Some((417, 1152, 44100)));
+        // padded
+        assert_eq!(parse_header([0xFF, 0xFB, 0x92, 0x00]), Some((418, 1152, 44100)));
+        // MPEG 2 layer III, 64 kbps, 22050 Hz
+        assert_eq!(parse_header([0xFF, 0xF3, 0x80, 0x00]), Some((208, 576, 22050)));
+        assert_eq!(parse_header([0x49, 0x44, 0x33, 0x04]), None);
+        // free format
+        assert_eq!(parse_header([0xFF, 0xFB, 0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn test_find_frame() {
+        let mut file = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        file.extend(&[0; 20]);
+        // junk before the first frame
+        file.extend(&[0xFF, 0x00, 0x12]);
+        let first_frame = file.len() as u64;
+        for _ in 0..40 {
+            file.extend(&HEADER);
+            file.extend(&[0; 413]);
+        }
+        // cut off in the middle of the last frame
+        file.truncate(file.len() - 100);
+        let frames = frame_index(&mut io::Cursor::new(file)).unwrap();
+        assert_eq!(frames.len(), 40);
+        let assert_frame = |seconds: f64, index: usize| {
+            let (offset, time) = find_frame(&frames, seconds).unwrap();
+            assert_eq!(offset, first_frame + index as u64 * 417);
+            assert!((time - index as f64 * FRAME_TIME).abs() < 1e-9);
+        };
+
+        assert_frame(0.0, 0);
+        assert_frame(5.5 * FRAME_TIME, 0);
+        assert_frame(19.5 * FRAME_TIME, 19 - WARM_UP_FRAMES);
+        assert_frame(100.0, 39 - WARM_UP_FRAMES);
+        assert_eq!(find_frame(&[], 1.0), None);
+    }
+}
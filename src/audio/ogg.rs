@@ -1,6 +1,6 @@
 //! Load OGG files that contain Vorbis (TODO add opus)
 
-use crate::audio::GenericMusicStream;
+use crate::audio::{GenericMusicStream, MusicSource, SeekError};
 
 use std::{io, vec};
 
@@ -10,6 +10,10 @@ use lewton::inside_ogg::OggStreamReader;
 struct OggVorbisSamples<R: io::Read + io::Seek + Send + 'static> {
     ogg_stream_reader: OggStreamReader<R>,
     buffer: Option<vec::IntoIter<i16>>,
+
+    /// Seeking in ogg only goes to the start of a page, so the samples between the start of the
+    /// page and this frame have to be thrown away.
+    seek_target: Option<u64>,
 }
 
 impl<R> Iterator for OggVorbisSamples<R>
@@ -20,7 +24,7 @@ where
     fn next(&mut self) -> Option<f32> {
         if self.buffer.as_ref().map(|i| i.len() == 0).unwrap_or(true) {
             loop { // keep asking for another buffer until we get one that isn't empty
-                match self.ogg_stream_reader.read_dec_packet_itl() {
+                match self.read_packet() {
                     Ok(o) => {
                         self.buffer = o
                             .map(|v| {
@@ -44,9 +48,63 @@ where
     }
 }
 
+impl<R> OggVorbisSamples<R>
+where
+    R: io::Read + io::Seek + Send + 'static
+{
+    /// Read the next packet's samples, throwing away the ones before `seek_target`.
+    ///
+    /// Only pages have a granule position, so right after seeking nobody knows where a packet
+    /// starts until one finishes a page. The packets until then are kept and placed by counting
+    /// back from the page's position with their lengths.
+    fn read_packet(&mut self) -> Result<Option<Vec<i16>>, lewton::VorbisError> {
+        let target = match self.seek_target {
+            Some(target) => target,
+            None => return self.ogg_stream_reader.read_dec_packet_itl(),
+        };
+        let channel_count = self.ogg_stream_reader.ident_hdr.audio_channels as usize;
+        let mut samples = Vec::new();
+        let end = loop {
+            match self.ogg_stream_reader.read_dec_packet_itl()? {
+                Some(packet) => samples.extend(packet),
+                None => {
+                    // The samples' position can't be known anymore
+                    self.seek_target = None;
+                    return Ok(None);
+                }
+            }
+            if let Some(end) = self.ogg_stream_reader.get_last_absgp() {
+                break end;
+            }
+        };
+        let frame_count = (samples.len() / channel_count) as u64;
+        let start = end.saturating_sub(frame_count);
+        let skip = target.saturating_sub(start).min(frame_count);
+        samples.drain(..skip as usize * channel_count);
+        if end >= target {
+            self.seek_target = None;
+        }
+        Ok(Some(samples))
+    }
+}
+
+impl<R> MusicSource for OggVorbisSamples<R>
+where
+    R: io::Read + io::Seek + Send + 'static
+{
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        let target = (seconds * self.ogg_stream_reader.ident_hdr.audio_sample_rate as f64) as u64;
+        self.ogg_stream_reader.seek_absgp_pg(target)
+            .map_err(|e| SeekError::Decode(e.to_string()))?;
+        self.buffer = None;
+        self.seek_target = Some(target);
+        Ok(())
+    }
+}
+
 pub(super) fn decode<R: io::Read + io::Seek + Send + 'static>(
     reader: R,
-) -> Result<GenericMusicStream<impl Iterator<Item = f32> + Send, f32>, String> {
+) -> Result<GenericMusicStream<impl MusicSource, f32>, String> {
 
     let ogg_reader = lewton::inside_ogg::OggStreamReader::new(reader)
         .map_err(|e| format!("Failed to read ogg: {}", e))?;
@@ -58,6 +116,7 @@ pub(super) fn decode<R: io::Read + io::Seek + Send + 'static>(
         samples: OggVorbisSamples {
             ogg_stream_reader: ogg_reader,
             buffer: None,
+            seek_target: None,
         },
         channel_count,
         sample_rate,
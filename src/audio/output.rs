@@ -0,0 +1,235 @@
+//! Places the audio thread can send mixed samples to
+
+use std::{thread, time};
+#[cfg(feature = "wav")]
+use std::{fs, io, path::Path};
+
+use cpal::Sample;
+
+use crate::audio::{AudioThreadError, Mixer};
+
+/// Something that plays or stores the samples from a `Mixer`
+pub trait Output: Send + 'static {
+    /// The channel count and sample rate the mixer should mix at. Samples are always mixed as
+    /// `f32`, the sample type is only used by the output itself.
+    fn format(&self) -> cpal::Format;
+
+    /// Roughly how many seconds it takes for a sample to be heard after it's mixed, or 0.0 if it
+    /// isn't known
+    fn latency(&self) -> f64 {
+        0.0
+    }
+
+    /// Keep pulling samples out of `mixer`. This is run on the audio thread, and might never
+    /// return.
+    fn run(self, mixer: Mixer);
+}
+
+/// Plays audio through the default output device
+pub struct CpalOutput {
+    event_loop: cpal::EventLoop,
+    format: cpal::Format,
+    buffer_size: cpal::BufferSize,
+}
+
+impl CpalOutput {
+    /// Open the default output device. If `buffer_size` isn't supported, the closest one that is
+    /// gets used.
+    pub fn new(mut buffer_size: cpal::BufferSize) -> Result<Self, AudioThreadError> {
+        let device = cpal::default_output_device().ok_or(AudioThreadError::NoOutputDevice)?;
+
+        println!("Using device {}", device.name());
+
+        let format = device.default_output_format()?;
+
+        let event_loop = cpal::EventLoop::new();
+        let stream_id = event_loop.build_output_stream(&device, &format, &mut buffer_size)?;
+        println!("Using audio buffer size {:?}", buffer_size);
+        event_loop.play_stream(stream_id);
+
+        Ok(CpalOutput { event_loop, format, buffer_size })
+    }
+}
+
+impl Output for CpalOutput {
+    fn format(&self) -> cpal::Format {
+        self.format.clone()
+    }
+
+    /// cpal doesn't report the device's latency, so this is only the buffer that gets mixed while
+    /// the previous one plays
+    fn latency(&self) -> f64 {
+        match self.buffer_size {
+            cpal::BufferSize::Fixed(frames) => frames as f64 / self.format.sample_rate.0 as f64,
+            _ => 0.0,
+        }
+    }
+
+    fn run(self, mut mixer: Mixer) {
+        // Integer devices get mixed into here first
+        let mut scratch = Vec::new();
+
+        self.event_loop.run(move |_, data| {
+            match data {
+                cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::U16(mut buffer) } => {
+                    scratch.resize(buffer.len(), 0.0);
+                    mixer.mix(&mut scratch);
+                    for (sample, s) in buffer.iter_mut().zip(&scratch) {
+                        *sample = s.to_u16();
+                    }
+                },
+
+                cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::I16(mut buffer) } => {
+                    scratch.resize(buffer.len(), 0.0);
+                    mixer.mix(&mut scratch);
+                    for (sample, s) in buffer.iter_mut().zip(&scratch) {
+                        *sample = s.to_i16();
+                    }
+                },
+
+                cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::F32(mut buffer) } => {
+                    mixer.mix(&mut buffer);
+                },
+                _ => (),
+            }
+        })
+    }
+}
+
+/// Pulls samples out of the mixer without a sound card, optionally writing them to a WAV file.
+/// Used for tests and rendering audio offline.
+pub struct HeadlessOutput {
+    format: cpal::Format,
+    buffer_frames: usize,
+    /// Whether to wait between buffers so that samples are pulled at the speed they'd be played
+    realtime: bool,
+    /// How many frames to mix before stopping, or `None` to keep going until the `Audio` handle
+    /// is dropped
+    length: Option<u64>,
+    /// Called with the time of each buffer, right before it's mixed
+    on_buffer: Option<Box<dyn FnMut(f64) + Send>>,
+    #[cfg(feature = "wav")]
+    wav: Option<hound::WavWriter<io::BufWriter<fs::File>>>,
+}
+
+impl HeadlessOutput {
+    /// A realtime output with no end, that throws the samples away
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        HeadlessOutput {
+            format: cpal::Format {
+                channels,
+                sample_rate: cpal::SampleRate(sample_rate),
+                data_type: cpal::SampleFormat::F32,
+            },
+            buffer_frames: 1024,
+            realtime: true,
+            length: None,
+            on_buffer: None,
+            #[cfg(feature = "wav")]
+            wav: None,
+        }
+    }
+
+    /// How many frames to mix at a time
+    pub fn buffer_frames(mut self, frames: usize) -> Self {
+        self.buffer_frames = frames.max(1);
+        self
+    }
+
+    /// If `false`, mix as fast as possible instead of at the speed the audio would be played
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Stop after mixing `seconds` of audio
+    pub fn length(mut self, seconds: f64) -> Self {
+        self.length = Some((seconds.max(0.0) * self.format.sample_rate.0 as f64).round() as u64);
+        self
+    }
+
+    /// Call `f` with the time of each buffer's first frame in seconds, right before the buffer is
+    /// mixed. Whatever `f` sends to the `Audio` handle is received in that buffer, so effects can be
+    /// scheduled just ahead of the mix.
+    pub fn on_buffer<F: FnMut(f64) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_buffer = Some(Box::new(f));
+        self
+    }
+
+    /// Write everything that gets mixed to a 32-bit float WAV file at `path`
+    #[cfg(feature = "wav")]
+    pub fn write_wav<P: AsRef<Path>>(mut self, path: P) -> Result<Self, hound::Error> {
+        let spec = hound::WavSpec {
+            channels: self.format.channels,
+            sample_rate: self.format.sample_rate.0,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        self.wav = Some(hound::WavWriter::create(path, spec)?);
+        Ok(self)
+    }
+
+    #[cfg(feature = "wav")]
+    fn write(&mut self, samples: &[f32]) {
+        if let Some(ref mut wav) = self.wav {
+            if let Err(e) = samples.iter().try_for_each(|&s| wav.write_sample(s)) {
+                remani_warn!("Error writing wav, no more audio will be written: {}", e);
+                self.wav = None;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "wav"))]
+    fn write(&mut self, _samples: &[f32]) {}
+
+    #[cfg(feature = "wav")]
+    fn finish(&mut self) {
+        if let Some(wav) = self.wav.take() {
+            if let Err(e) = wav.finalize() {
+                remani_warn!("Error finishing wav: {}", e);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "wav"))]
+    fn finish(&mut self) {}
+}
+
+impl Output for HeadlessOutput {
+    fn format(&self) -> cpal::Format {
+        self.format.clone()
+    }
+
+    fn run(mut self, mut mixer: Mixer) {
+        let channel_count = self.format.channels as usize;
+        let sample_rate = self.format.sample_rate.0 as f64;
+        let mut buffer = vec![0.0; self.buffer_frames * channel_count];
+        let start = time::Instant::now();
+        let mut frames_mixed: u64 = 0;
+
+        while mixer.is_connected() {
+            let frames = match self.length {
+                Some(length) if frames_mixed >= length => break,
+                Some(length) => (length - frames_mixed).min(self.buffer_frames as u64) as usize,
+                None => self.buffer_frames,
+            };
+            if let Some(ref mut on_buffer) = self.on_buffer {
+                on_buffer(frames_mixed as f64 / sample_rate);
+            }
+            let buffer = &mut buffer[..frames * channel_count];
+            mixer.mix(buffer);
+            self.write(buffer);
+            frames_mixed += frames as u64;
+
+            if self.realtime {
+                let target = start + time::Duration::from_secs_f64(frames_mixed as f64 / sample_rate);
+                let now = time::Instant::now();
+                if target > now {
+                    thread::sleep(target - now);
+                }
+            }
+        }
+
+        self.finish();
+    }
+}
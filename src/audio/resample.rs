@@ -1,14 +1,58 @@
-use crate::audio::{GenericMusicStream, MusicStream};
-use std::iter::Peekable;
+//! Sample rate conversion, either with linear interpolation or a windowed sinc filter
 
-// TODO maybe use higher quality resampling algorithm?
+use std::f64::consts::PI;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::audio::{GenericMusicStream, MusicSource, MusicStream, SeekError};
+
+/// How much effort to put into resampling audio that doesn't match the output device's sample
+/// rate. Higher quality means less aliasing, but more work per sample.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleQuality {
+    /// Linear interpolation. Cheapest, but aliases audibly.
+    Linear,
+    /// Windowed sinc with 4 zero crossings on each side
+    Low,
+    /// Windowed sinc with 8 zero crossings on each side
+    Medium,
+    /// Windowed sinc with 16 zero crossings on each side
+    High,
+}
+
+impl Default for ResampleQuality {
+    fn default() -> Self {
+        ResampleQuality::Medium
+    }
+}
+
+impl ResampleQuality {
+    /// How many zero crossings of the sinc function to keep on each side of the filter, or `None`
+    /// for linear interpolation
+    fn zero_crossings(self) -> Option<usize> {
+        match self {
+            ResampleQuality::Linear => None,
+            ResampleQuality::Low => Some(4),
+            ResampleQuality::Medium => Some(8),
+            ResampleQuality::High => Some(16),
+        }
+    }
+}
 
 /// Resample a MusicStream using linear interpolation
-pub struct Resample<I: Iterator<Item = f32> + Send> {
+pub struct Linear<I: Iterator<Item = f32> + Send> {
     /// The iterator that yields interleaved audio samples
     /// (e.g. an iterator for an audio stream with 3 channels would
     /// yield samples for channel 1, then 2, then 3, then 1, then 2, ...
-    samples: Peekable<I>,
+    samples: I,
+
+    /// A sample taken out of `samples` by `peek_sample` that hasn't been used yet
+    peeked: Option<f32>,
+
+    /// How many seconds of `samples` one second of output is, used when seeking. Not 1.0 when
+    /// the sample rate is faked to change the playback speed.
+    seek_scale: f64,
 
     channel_count: usize,
     from_sample_rate: u32,
@@ -29,16 +73,29 @@ pub struct Resample<I: Iterator<Item = f32> + Send> {
     next_values: Vec<f32>,
 }
 
-impl<I: Iterator<Item = f32> + Send> Iterator for Resample<I> {
+impl<I: Iterator<Item = f32> + Send> Linear<I> {
+    fn next_sample(&mut self) -> Option<f32> {
+        self.peeked.take().or_else(|| self.samples.next())
+    }
+
+    fn peek_sample(&mut self) -> Option<f32> {
+        if self.peeked.is_none() {
+            self.peeked = self.samples.next();
+        }
+        self.peeked
+    }
+}
+
+impl<I: Iterator<Item = f32> + Send> Iterator for Linear<I> {
     type Item = f32;
     fn next(&mut self) -> Option<f32> {
         let return_value = if self.previous_values.len() < self.channel_count && self.next_values.len() < self.channel_count {
-            let next_sample = match self.samples.next() {
+            let next_sample = match self.next_sample() {
                 Some(s) => s,
                 None => return None,
             };
-            let next_next_sample = match self.samples.peek() {
-                Some(s) => *s,
+            let next_next_sample = match self.peek_sample() {
+                Some(s) => s,
                 None => return None,
             };
             self.previous_values.push(next_sample);
@@ -51,7 +108,7 @@ impl<I: Iterator<Item = f32> + Send> Iterator for Resample<I> {
                     self.sampling_offset -= self.to_sample_rate;
                     for n in 0..self.channel_count {
                         self.previous_values[n] = self.next_values[n];
-                        let next_sample = match self.samples.next() {
+                        let next_sample = match self.next_sample() {
                             Some(s) => s,
                             None => return None,
                         };
@@ -73,25 +130,313 @@ impl<I: Iterator<Item = f32> + Send> Iterator for Resample<I> {
     }
 }
 
+impl<I: MusicSource> MusicSource for Linear<I> {
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        self.samples.seek(seconds * self.seek_scale)?;
+        self.peeked = None;
+        self.channel_offset = 0;
+        self.sampling_offset = 0;
+        self.previous_values.clear();
+        self.next_values.clear();
+        Ok(())
+    }
+}
+
+/// Frequencies this close to the output's nyquist frequency get filtered out, since the filter
+/// can't cut off instantly.
+const ROLLOFF: f64 = 0.95;
+
+/// The most fractional positions the filter is precomputed for. Ratios that need more than this
+/// interpolate between the two closest precomputed positions.
+const MAX_PHASES: usize = 256;
+
+/// A windowed sinc lowpass filter, precomputed for evenly spaced fractional positions between
+/// two input frames.
+struct Filter {
+    /// How many input frames go into one output frame
+    taps: usize,
+    /// How many positions the filter was computed for, not counting the extra row for a fraction
+    /// of exactly 1.0
+    phases: usize,
+    /// `phases + 1` rows of `taps` coefficients each
+    coefficients: Vec<f32>,
+}
+
+impl Filter {
+    fn new(zero_crossings: usize, cutoff: f64, phases: usize) -> Filter {
+        // When downsampling, the cutoff is lower, so the sinc is wider
+        let half_width = (zero_crossings as f64 / cutoff).ceil() as usize;
+        let taps = half_width * 2;
+        let mut coefficients = Vec::with_capacity((phases + 1) * taps);
+        for phase in 0..=phases {
+            let fraction = phase as f64 / phases as f64;
+            let row_start = coefficients.len();
+            for tap in 0..taps {
+                // Distance from the output position, where tap `half_width - 1` is the frame at
+                // or right before it.
+                let x = tap as f64 - (half_width - 1) as f64 - fraction;
+                coefficients.push((cutoff * sinc(cutoff * x) * blackman(x / half_width as f64)) as f32);
+            }
+            // Normalize so that a constant signal stays the same volume
+            let sum: f32 = coefficients[row_start..].iter().sum();
+            for c in &mut coefficients[row_start..] {
+                *c /= sum;
+            }
+        }
+        Filter { taps, phases, coefficients }
+    }
+
+    fn row(&self, phase: usize) -> &[f32] {
+        &self.coefficients[phase * self.taps..(phase + 1) * self.taps]
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window from -1.0 to 1.0
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+    }
+}
+
+/// Multiply one channel of `window` by `row` and add it all up
+fn convolve(window: &[f32], row: &[f32], channel: usize, channel_count: usize) -> f32 {
+    window[channel..]
+        .iter()
+        .step_by(channel_count)
+        .zip(row)
+        .map(|(s, c)| s * c)
+        .sum()
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Resample a MusicStream using a windowed sinc filter.
+///
+/// One output frame is `step / phase_count` input frames after the previous one. When
+/// `phase_count` is at most `MAX_PHASES`, which includes every integer ratio, each output frame
+/// lands exactly on a precomputed filter row. Otherwise, the two closest rows are interpolated.
+pub struct Sinc<I: Iterator<Item = f32> + Send> {
+    samples: I,
+
+    /// See `Linear::seek_scale`
+    seek_scale: f64,
+
+    channel_count: usize,
+    filter: Filter,
+
+    /// The input sample rate divided by the greatest common divisor of both rates
+    step: u32,
+    /// The output sample rate divided by the greatest common divisor of both rates
+    phase_count: u32,
+    /// Between 0 and `phase_count`, how far the next output frame is past the input frame at the
+    /// center of the window
+    phase: u32,
+
+    /// The last `filter.taps` input frames, interleaved, starting at `window_start`. Old frames
+    /// are only removed from the front once in a while.
+    window: Vec<f32>,
+    window_start: usize,
+    /// How many frames of silence were added after `samples` ran out, or `None` if it hasn't yet
+    silent_frames: Option<usize>,
+    /// Whether `window` has been filled yet
+    primed: bool,
+
+    /// The output frame being returned one sample at a time
+    frame: Vec<f32>,
+    /// What channel of `frame` to return next
+    channel_offset: usize,
+}
+
+impl<I: Iterator<Item = f32> + Send> Sinc<I> {
+    fn new(
+        samples: I,
+        channel_count: usize,
+        from_sample_rate: u32,
+        to_sample_rate: u32,
+        seek_scale: f64,
+        zero_crossings: usize,
+    ) -> Self {
+        let divisor = gcd(from_sample_rate, to_sample_rate).max(1);
+        let step = from_sample_rate / divisor;
+        let phase_count = to_sample_rate / divisor;
+        let cutoff = (to_sample_rate as f64 / from_sample_rate as f64).min(1.0) * ROLLOFF;
+        let filter = Filter::new(zero_crossings, cutoff, (phase_count as usize).min(MAX_PHASES));
+        Sinc {
+            samples,
+            seek_scale,
+            channel_count,
+            window: Vec::with_capacity(filter.taps * channel_count * 2),
+            filter,
+            step,
+            phase_count,
+            phase: 0,
+            window_start: 0,
+            silent_frames: None,
+            primed: false,
+            frame: vec![0.0; channel_count],
+            channel_offset: 0,
+        }
+    }
+
+    /// Add the next input frame to the end of the window and drop the first one. Returns `false`
+    /// once the center of the window has moved past the end of the input.
+    fn push_frame(&mut self) -> bool {
+        if self.silent_frames.is_none() {
+            let len = self.window.len();
+            for _ in 0..self.channel_count {
+                match self.samples.next() {
+                    Some(s) => self.window.push(s),
+                    None => {
+                        // Throw away the incomplete frame
+                        self.window.truncate(len);
+                        self.silent_frames = Some(0);
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(ref mut n) = self.silent_frames {
+            *n += 1;
+            self.window.extend((0..self.channel_count).map(|_| 0.0));
+        }
+
+        if self.window.len() - self.window_start > self.filter.taps * self.channel_count {
+            self.window_start += self.channel_count;
+        }
+        if self.window_start >= self.window.capacity() / 2 {
+            self.window.drain(..self.window_start);
+            self.window_start = 0;
+        }
+
+        self.silent_frames.map_or(true, |n| n <= self.filter.taps / 2)
+    }
+
+    /// Fill the window so the first input frame is at its center
+    fn prime(&mut self) -> bool {
+        let half = self.filter.taps / 2;
+        self.window.clear();
+        self.window_start = 0;
+        self.window.extend((0..(half - 1) * self.channel_count).map(|_| 0.0));
+        self.primed = true;
+        (0..half + 1).all(|_| self.push_frame())
+    }
+
+    /// Calculate the output frame at the current position
+    fn compute_frame(&mut self) {
+        let window = &self.window[self.window_start..];
+        let filter = &self.filter;
+        if filter.phases == self.phase_count as usize {
+            let row = filter.row(self.phase as usize);
+            for (channel, s) in self.frame.iter_mut().enumerate() {
+                *s = convolve(window, row, channel, self.channel_count);
+            }
+        } else {
+            let position = self.phase as f64 * filter.phases as f64 / self.phase_count as f64;
+            let index = position as usize;
+            let t = (position - index as f64) as f32;
+            let (row_a, row_b) = (filter.row(index), filter.row(index + 1));
+            for (channel, s) in self.frame.iter_mut().enumerate() {
+                let a = convolve(window, row_a, channel, self.channel_count);
+                let b = convolve(window, row_b, channel, self.channel_count);
+                *s = a + (b - a) * t;
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = f32> + Send> Iterator for Sinc<I> {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        if self.channel_offset == 0 {
+            if !self.primed {
+                if !self.prime() {
+                    return None;
+                }
+            } else {
+                self.phase += self.step;
+                while self.phase >= self.phase_count {
+                    self.phase -= self.phase_count;
+                    if !self.push_frame() {
+                        return None;
+                    }
+                }
+            }
+            self.compute_frame();
+        }
+        let sample = self.frame[self.channel_offset];
+        self.channel_offset += 1;
+        if self.channel_offset >= self.channel_count {
+            self.channel_offset = 0;
+        }
+        Some(sample)
+    }
+}
+
+impl<I: MusicSource> MusicSource for Sinc<I> {
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        self.samples.seek(seconds * self.seek_scale)?;
+        self.phase = 0;
+        self.channel_offset = 0;
+        self.silent_frames = None;
+        self.primed = false;
+        Ok(())
+    }
+}
+
 pub(super) fn from_music_stream<I>(
     stream: GenericMusicStream<I, f32>,
     target_sample_rate: u32,
+    quality: ResampleQuality,
+) -> MusicStream
+where
+    I: MusicSource + 'static
+{
+    from_music_stream_scaled(stream, target_sample_rate, 1.0, quality)
+}
+
+/// Like `from_music_stream`, but seeking to `t` seconds in the result seeks to `t * seek_scale`
+/// seconds in `stream`.
+pub(super) fn from_music_stream_scaled<I>(
+    stream: GenericMusicStream<I, f32>,
+    target_sample_rate: u32,
+    seek_scale: f64,
+    quality: ResampleQuality,
 ) -> MusicStream
 where
-    I: Iterator<Item = f32> + Send + 'static
+    I: MusicSource + 'static
 {
-    let le_samples = Resample {
-        samples: stream.samples.peekable(),
-        channel_count: stream.channel_count as usize,
-        from_sample_rate: stream.sample_rate,
-        to_sample_rate: target_sample_rate,
-        channel_offset: 0,
-        sampling_offset: 0,
-        previous_values: Vec::new(),
-        next_values: Vec::new(),
-    };
-
-    MusicStream {
-        samples: Box::new(le_samples),
+    match quality.zero_crossings() {
+        None => MusicStream::new(Linear {
+            samples: stream.samples,
+            peeked: None,
+            seek_scale,
+            channel_count: stream.channel_count as usize,
+            from_sample_rate: stream.sample_rate,
+            to_sample_rate: target_sample_rate,
+            channel_offset: 0,
+            sampling_offset: 0,
+            previous_values: Vec::new(),
+            next_values: Vec::new(),
+        }),
+        Some(zero_crossings) => MusicStream::new(Sinc::new(
+            stream.samples,
+            stream.channel_count as usize,
+            stream.sample_rate,
+            target_sample_rate,
+            seek_scale,
+            zero_crossings,
+        )),
     }
 }
@@ -0,0 +1,231 @@
+//! Change the speed of audio without changing its pitch
+
+use std::{collections::VecDeque, f32::consts::PI};
+
+use crate::audio::{MusicSource, SeekError};
+
+/// The length of each grain in frames. Around 40ms at 48kHz.
+const GRAIN_LENGTH: usize = 2048;
+
+/// How many frames a grain can be moved from where it should start to line up with the grain
+/// before it. Enough for waves down to about 90Hz at 48kHz.
+const SEEK_TOLERANCE: usize = 256;
+
+/// Only every this many frames are compared when lining grains up, to keep it cheap
+const CORRELATION_STEP: usize = 4;
+
+/// Time stretch an interleaved sample iterator using WSOLA (overlap-add with a Hann window, with
+/// each grain moved to where it lines up best with the one before).
+///
+/// Grains are read from the input about every `GRAIN_LENGTH / 2 * rate` frames and written to the
+/// output every `GRAIN_LENGTH / 2` frames, so the output is `1 / rate` times as long as the input
+/// but at the same pitch. Without lining them up, the grains' waves would cancel out where they
+/// overlap and move the pitch towards a multiple of how often grains are written.
+pub struct TimeStretch<I: Iterator<Item = f32> + Send> {
+    samples: I,
+    channel_count: usize,
+    rate: f64,
+
+    /// Interleaved input samples that haven't been fully used yet
+    input: VecDeque<f32>,
+
+    /// Where the next grain should start in `input`, in frames. The fractional part is kept so the
+    /// rate doesn't drift.
+    input_position: f64,
+
+    /// Where the input that would come right after the last grain's first half starts in `input`,
+    /// in frames. The next grain is lined up with it.
+    continuation: Option<usize>,
+
+    /// Whether `samples` has run out
+    input_ended: bool,
+
+    /// Overlap-add accumulator, `GRAIN_LENGTH` frames long. The first half is finished once the
+    /// next grain has been added.
+    output: Vec<f32>,
+    output_index: usize,
+
+    window: Vec<f32>,
+}
+
+impl<I: Iterator<Item = f32> + Send> TimeStretch<I> {
+    pub fn new(samples: I, channel_count: usize, rate: f64) -> Self {
+        let window = (0..GRAIN_LENGTH)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / GRAIN_LENGTH as f32).cos())
+            .collect();
+        TimeStretch {
+            samples,
+            channel_count,
+            rate,
+            input: VecDeque::with_capacity(GRAIN_LENGTH * channel_count * 2),
+            input_position: 0.0,
+            continuation: None,
+            input_ended: false,
+            output: vec![0.0; GRAIN_LENGTH * channel_count],
+            // Start "finished" so the first call to `next` adds a grain
+            output_index: GRAIN_LENGTH / 2 * channel_count,
+            window,
+        }
+    }
+
+    /// Add the next grain to the output buffer. Returns false if there's no input left.
+    fn add_grain(&mut self) -> bool {
+        let half = GRAIN_LENGTH / 2 * self.channel_count;
+
+        // Shift out the finished half of the accumulator
+        self.output.copy_within(half.., 0);
+        for s in &mut self.output[half..] {
+            *s = 0.0;
+        }
+        self.output_index = 0;
+
+        // Drop the input that no grain will use anymore, keeping the continuation of the last grain
+        let position = self.input_position as usize;
+        let skip = position.saturating_sub(SEEK_TOLERANCE);
+        let skip = self.continuation.map_or(skip, |continuation| skip.min(continuation));
+        let skip = skip.min(self.input.len() / self.channel_count);
+        self.input.drain(..skip * self.channel_count);
+        self.input_position -= skip as f64;
+        self.continuation = self.continuation.map(|continuation| continuation - skip);
+
+        let position = self.input_position as usize;
+        let needed = (position + SEEK_TOLERANCE + GRAIN_LENGTH) * self.channel_count;
+        while self.input.len() < needed && !self.input_ended {
+            match self.samples.next() {
+                Some(s) => self.input.push_back(s),
+                None => self.input_ended = true,
+            }
+        }
+        if self.input_ended && self.input.len() <= position * self.channel_count {
+            return false;
+        }
+
+        let grain = match self.continuation {
+            Some(continuation) => self.best_grain_start(position, continuation),
+            None => position,
+        };
+        let start = grain * self.channel_count;
+        for (i, s) in self.input.iter().skip(start).take(GRAIN_LENGTH * self.channel_count).enumerate() {
+            self.output[i] += s * self.window[i / self.channel_count];
+        }
+        self.continuation = Some(grain + GRAIN_LENGTH / 2);
+        self.input_position += (GRAIN_LENGTH / 2) as f64 * self.rate;
+        true
+    }
+
+    /// Where near `position` a grain starts that's most like the input at `continuation`
+    fn best_grain_start(&self, position: usize, continuation: usize) -> usize {
+        let overlap = GRAIN_LENGTH / 2;
+        let frame_count = self.input.len() / self.channel_count;
+        let first = position.saturating_sub(SEEK_TOLERANCE);
+        let last = (position + SEEK_TOLERANCE).min(frame_count.saturating_sub(1));
+        // Mix down the compared frames once instead of for every candidate
+        let mix = |start: usize, end: usize| -> Vec<f32> {
+            (start..end.min(frame_count)).step_by(CORRELATION_STEP).map(|i| {
+                let start = i * self.channel_count;
+                self.input.range(start..start + self.channel_count).sum()
+            }).collect()
+        };
+        let target = mix(continuation, continuation + overlap);
+        let correlation = |frames: &[f32]| -> f32 { frames.iter().zip(&target).map(|(a, b)| a * b).sum() };
+
+        // Look roughly at every `CORRELATION_STEP`th start first, then around the best of those
+        let frames = mix(first, last + overlap);
+        let mut best = (position, f32::NEG_INFINITY);
+        for (j, start) in (first..=last).step_by(CORRELATION_STEP).enumerate() {
+            let c = correlation(&frames[j..]);
+            if c > best.1 {
+                best = (start, c);
+            }
+        }
+        let around_first = best.0.saturating_sub(CORRELATION_STEP - 1).max(first);
+        let around_last = (best.0 + CORRELATION_STEP - 1).min(last);
+        for start in around_first..=around_last {
+            let c = correlation(&mix(start, start + overlap));
+            if c > best.1 {
+                best = (start, c);
+            }
+        }
+        best.0
+    }
+}
+
+impl<I: MusicSource> MusicSource for TimeStretch<I> {
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        // One second of output is `rate` seconds of input
+        self.samples.seek(seconds * self.rate)?;
+        self.input.clear();
+        self.input_position = 0.0;
+        self.continuation = None;
+        self.input_ended = false;
+        for s in &mut self.output {
+            *s = 0.0;
+        }
+        self.output_index = GRAIN_LENGTH / 2 * self.channel_count;
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = f32> + Send> Iterator for TimeStretch<I> {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        if self.output_index >= GRAIN_LENGTH / 2 * self.channel_count && !self.add_grain() {
+            return None;
+        }
+        let sample = self.output[self.output_index];
+        self.output_index += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48000.0;
+    const FREQUENCY: f64 = 440.0;
+
+    /// The loudest frequency in the first channel of `samples`, to the nearest Hz within a major
+    /// third of `FREQUENCY`
+    fn frequency(samples: &[f32], channel_count: usize) -> f64 {
+        let power = |f: f64| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, &s) in samples.iter().step_by(channel_count).enumerate() {
+                let phase = 2.0 * std::f64::consts::PI * f * i as f64 / SAMPLE_RATE;
+                re += s as f64 * phase.cos();
+                im += s as f64 * phase.sin();
+            }
+            re * re + im * im
+        };
+        let range = (FREQUENCY * 0.8) as usize..=(FREQUENCY * 1.25) as usize;
+        range.map(|f| f as f64).fold((0.0, 0.0), |best, f| {
+            let p = power(f);
+            if p > best.1 { (f, p) } else { best }
+        }).0
+    }
+
+    #[test]
+    fn test_length_and_pitch() {
+        let frame_count = SAMPLE_RATE as usize;
+        for &rate in &[0.75, 1.5] {
+            for &channel_count in &[1, 2] {
+                let input = (0..frame_count).flat_map(|i| {
+                    let s = (2.0 * std::f64::consts::PI * FREQUENCY * i as f64 / SAMPLE_RATE).sin() as f32;
+                    std::iter::repeat(s).take(channel_count)
+                });
+                let output: Vec<f32> = TimeStretch::new(input, channel_count, rate).collect();
+
+                let expected_frames = frame_count as f64 / rate;
+                let frames = (output.len() / channel_count) as f64;
+                assert!(
+                    (frames - expected_frames).abs() <= GRAIN_LENGTH as f64,
+                    "{} frames at rate {}, expected about {}", frames, rate, expected_frames,
+                );
+                // Away from the fades at the start and end
+                let middle = &output[GRAIN_LENGTH * channel_count..output.len() - GRAIN_LENGTH * channel_count];
+                let f = frequency(middle, channel_count);
+                assert!((f - FREQUENCY).abs() <= 1.0, "{} Hz at rate {}", f, rate);
+            }
+        }
+    }
+}
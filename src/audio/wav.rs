@@ -1,53 +1,50 @@
 //! Load WAV files
 
-use crate::audio::GenericMusicStream;
+use crate::audio::{GenericMusicStream, MusicSource, SeekError};
 
 use std::io;
 
 use cpal::Sample;
-use either::Either;
 
-pub(super) fn decode<R: io::Read + Send + 'static>(reader: R) -> Result<GenericMusicStream<impl Iterator<Item = f32>>, String> {
+/// Lazy iterator over audio samples from a WAV
+struct WavSamples<R: io::Read + io::Seek + Send> {
+    reader: hound::WavReader<io::BufReader<R>>,
+}
+
+impl<R: io::Read + io::Seek + Send> Iterator for WavSamples<R> {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        let sample = match self.reader.spec().sample_format {
+            hound::SampleFormat::Int => self.reader.samples::<i16>().next()?.map(|s| s.to_f32()),
+            hound::SampleFormat::Float => self.reader.samples::<f32>().next()?,
+        };
+        match sample {
+            Ok(s) => Some(s),
+            Err(e) => {
+                remani_warn!("wav read error: {}", e);
+                Some(0.0)
+            }
+        }
+    }
+}
+
+impl<R: io::Read + io::Seek + Send> MusicSource for WavSamples<R> {
+    fn seek(&mut self, seconds: f64) -> Result<(), SeekError> {
+        let frame = (seconds * self.reader.spec().sample_rate as f64) as u32;
+        self.reader.seek(frame.min(self.reader.duration()))
+            .map_err(|e| SeekError::Decode(e.to_string()))
+    }
+}
+
+pub(super) fn decode<R: io::Read + io::Seek + Send + 'static>(reader: R) -> Result<GenericMusicStream<impl MusicSource>, String> {
     let buf_reader = io::BufReader::new(reader);
     let wav_reader = hound::WavReader::new(buf_reader).map_err(|e| e.to_string())?;
     let format = wav_reader.spec();
     let channel_count = format.channels;
     let sample_rate = format.sample_rate;
-    let samples = match format.sample_format {
-        hound::SampleFormat::Int => {
-            Either::Left(
-                wav_reader
-                .into_samples::<i16>()
-                .map(|s| {
-                    match s {
-                        Ok(s) => s.to_f32(),
-                        Err(e) => {
-                            remani_warn!("wav read error: {}", e);
-                            0.0
-                        }
-                    }
-                })
-            )
-        }
-        hound::SampleFormat::Float => {
-            Either::Right(
-                wav_reader
-                .into_samples::<f32>()
-                .map(|s| {
-                    match s {
-                        Ok(s) => s,
-                        Err(e) => {
-                            remani_warn!("wav read error: {}", e);
-                            0.0
-                        },
-                    }
-                })
-            )
-        }
-    };
 
     Ok(GenericMusicStream {
-        samples,
+        samples: WavSamples { reader: wav_reader },
         channel_count: channel_count as u8,
         sample_rate,
     })
@@ -7,7 +7,7 @@ use std::{ffi::OsStr, env, error::Error, thread, time};
 use cpal::BufferSize;
 
 fn output_help(binary_name: &OsStr) {
-    println!("Usage:  {} path/to/music/file", binary_name.to_string_lossy());
+    println!("Usage:  {} path/to/music/file [start time in seconds]", binary_name.to_string_lossy());
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -22,7 +22,17 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    let music = audio::music_from_path(audio_filename, audio.format())?;
+    let start_time = match args.next().map(|s| s.to_string_lossy().parse::<f64>()) {
+        Some(Ok(t)) => t,
+        Some(Err(_)) => {
+            output_help(&binary);
+            return Ok(());
+        }
+        None => 0.0,
+    };
+
+    let mut music = audio::music_from_path(audio_filename, audio.format(), Default::default())?;
+    music.seek(start_time)?;
     if !audio.play_music(music) {
         Err("Error sending music to audio thread")?;
     }
@@ -0,0 +1,116 @@
+//! Renders a chart's music, autoplay sounds and keysounds into a WAV file, as if every note was
+//! hit perfectly
+
+use remani::{audio::{self, Output}, chart, config};
+
+use std::{cmp::Ordering, env, error::Error, ffi::OsStr};
+
+/// How far ahead of the mix sounds are scheduled, in seconds
+const SCHEDULE_LOOKAHEAD: f64 = 1.0;
+
+fn output_help(binary_name: &OsStr) {
+    println!("Usage:  {} path/to/chart.osu output.wav [sample rate]", binary_name.to_string_lossy());
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args_os();
+    let binary = args.next().unwrap_or(format!("./{}", file!().rsplitn(2, ".rs").nth(1).unwrap()).into());
+    let (chart_path, output_path) = match (args.next(), args.next()) {
+        (Some(c), Some(o)) => (c, o),
+        _ => {
+            output_help(&binary);
+            return Ok(());
+        }
+    };
+    let sample_rate = match args.next().map(|s| s.to_string_lossy().parse::<u32>()) {
+        Some(Ok(r)) => r,
+        Some(Err(_)) => {
+            output_help(&binary);
+            return Ok(());
+        }
+        None => 44100,
+    };
+
+    let config = config::get_config(config::config_path());
+    let format = cpal::Format {
+        channels: 2,
+        sample_rate: cpal::SampleRate(sample_rate),
+        data_type: cpal::SampleFormat::F32,
+    };
+
+    let mut chart = chart::modifier::apply(Box::new(chart::osu::from_path(chart_path)?), &config);
+    chart.load_sounds(&format, &config);
+
+    let (audio, mixer) = audio::mixer(&format);
+    let volume = config.general.volume;
+    audio.set_volume(audio::Bus::Master, volume.master);
+    audio.set_volume(audio::Bus::Music, volume.music);
+    audio.set_volume(audio::Bus::Keysound, volume.keysound);
+    audio.set_volume(audio::Bus::Autoplay, volume.autoplay);
+    audio.set_polyphony(config.general.polyphony.max_voices, config.general.polyphony.stealing);
+
+    // Decoded up front, so the render knows how long the music is
+    let mut length = 0.0;
+    match chart.music(&format, &config) {
+        Ok(music) => {
+            let music = audio::EffectStream::from(music);
+            length = music.duration(&format);
+            audio.play_music(music.into());
+        }
+        Err(e) => eprintln!("WARNING: Couldn't load the music, rendering without it: {}", e),
+    }
+
+    // Same as the game, so O2Jam-style keysounds cut themselves off
+    let choke_group = |sound_index| if config.general.polyphony.choke_keysounds {
+        Some(sound_index)
+    } else {
+        None
+    };
+
+    let mut effects = Vec::new();
+    for sound in chart.autoplay_sounds() {
+        if let Some(effect) = chart.get_sound(sound.sound_index) {
+            let effect = effect
+                .with_volume(sound.volume)
+                .with_pan(sound.pan)
+                .with_choke_group(choke_group(sound.sound_index));
+            effects.push((sound.time, audio::Bus::Autoplay, effect));
+        }
+    }
+    for note in chart.notes() {
+        if let Some((i, effect)) = note.sound_index.and_then(|i| chart.get_sound(i).map(|e| (i, e))) {
+            let effect = effect
+                .with_volume(note.volume)
+                .with_pan(note.pan)
+                .with_choke_group(choke_group(i));
+            effects.push((note.time, audio::Bus::Keysound, effect));
+        }
+    }
+    // Stable, so sounds at the same time keep their order
+    effects.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    for (time, _, effect) in &effects {
+        length = f64::max(length, time + effect.duration(&format));
+    }
+
+    // Like the game, sounds are scheduled a bit ahead of the mix instead of all at once, which
+    // would fill up the audio thread's queue
+    let mut effects = effects.into_iter().peekable();
+    audio::HeadlessOutput::new(format.channels, sample_rate)
+        .realtime(false)
+        .length(length)
+        .write_wav(&output_path)?
+        .on_buffer(move |time| {
+            while let Some((effect_time, bus, effect)) = effects.peek() {
+                if *effect_time >= time + SCHEDULE_LOOKAHEAD
+                    || !audio.schedule_effect(effect.clone(), *bus, *effect_time)
+                {
+                    break;
+                }
+                effects.next();
+            }
+        })
+        .run(mixer);
+
+    println!("Wrote {:.1} seconds of audio to {}", length, output_path.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! Compares the speed and accuracy of each resampling quality
+
+use remani::audio::{self, ResampleQuality};
+
+use std::{f64::consts::PI, sync::Arc, time};
+
+/// How many seconds of audio to resample for each test
+const LENGTH: f64 = 10.0;
+
+const QUALITIES: [ResampleQuality; 4] = [
+    ResampleQuality::Linear,
+    ResampleQuality::Low,
+    ResampleQuality::Medium,
+    ResampleQuality::High,
+];
+
+/// How many input frames late each quality's output is meant to be. The sinc filters are centered
+/// on the first input frame, and linear interpolation repeats it once.
+fn group_delay(quality: ResampleQuality) -> f64 {
+    match quality {
+        ResampleQuality::Linear => 1.0,
+        _ => 0.0,
+    }
+}
+
+/// A stereo sine wave with the same frequency in both channels, starting `delay` seconds late
+fn sine(frequency: f64, sample_rate: u32, frames: usize, delay: f64) -> impl Iterator<Item = f32> {
+    (0..frames).flat_map(move |n| {
+        let s = (2.0 * PI * frequency * (n as f64 / sample_rate as f64 - delay)).sin() as f32;
+        vec![s, s]
+    })
+}
+
+/// How many seconds late the sine wave in the left channel of `samples` is, from its phase, if the
+/// first frame of `samples` is frame `start`. Only right for delays shorter than half a period.
+fn delay(samples: &[f32], start: usize, frequency: f64, sample_rate: u32) -> f64 {
+    let (sin, cos) = samples.iter().step_by(2).enumerate().fold((0.0, 0.0), |(sin, cos), (n, &s)| {
+        let phase = 2.0 * PI * frequency * (start + n) as f64 / sample_rate as f64;
+        (sin + s as f64 * phase.sin(), cos + s as f64 * phase.cos())
+    });
+    -cos.atan2(sin) / (2.0 * PI * frequency)
+}
+
+/// Resample a sine wave and return how long it took, the RMS error compared to a perfect sine wave
+/// at the new sample rate delayed by the quality's group delay in dB, and how many input frames late
+/// the output actually is.
+fn run(quality: ResampleQuality, frequency: f64, from: u32, to: u32) -> (time::Duration, f64, f64) {
+    let input: Arc<Vec<f32>> = Arc::new(sine(frequency, from, (LENGTH * from as f64) as usize, 0.0).collect());
+    let start = time::Instant::now();
+    let output: Vec<f32> = audio::resample(input, 2, from, to, quality).collect();
+    let elapsed = start.elapsed();
+
+    // Ignore the ends, where the filter runs into silence
+    let skip = to as usize / 10;
+    let end = output.len().saturating_sub(skip * 2).max(skip * 2);
+    let output = &output[skip * 2..end];
+    let expected = sine(frequency, to, output.len() / 2 + skip, group_delay(quality) / from as f64).skip(skip * 2);
+    let (sum, count) = output
+        .iter()
+        .zip(expected)
+        .fold((0.0, 0), |(sum, count), (&a, b)| (sum + ((a - b) as f64).powi(2), count + 1));
+    let error = (sum / count.max(1) as f64).sqrt();
+    let latency = delay(output, skip, frequency, to);
+    (elapsed, 20.0 * error.log10(), latency * from as f64)
+}
+
+fn main() {
+    for &(from, to) in &[(44100, 48000), (48000, 44100), (24000, 48000), (96000, 48000)] {
+        println!("{} Hz -> {} Hz ({} seconds of stereo audio)", from, to, LENGTH);
+        for &quality in &QUALITIES {
+            let (elapsed, error_1k, latency) = run(quality, 1000.0, from, to);
+            let (_, error_high, _) = run(quality, 0.4 * from.min(to) as f64, from, to);
+            println!(
+                "    {:<8} {:>8.2} ms ({:>6.0}x realtime), latency: {:>+6.3} frames, \
+                 error at 1 kHz: {:>7.1} dB, at {} Hz: {:>7.1} dB",
+                format!("{:?}", quality),
+                elapsed.as_secs_f64() * 1000.0,
+                LENGTH / elapsed.as_secs_f64(),
+                latency,
+                error_1k,
+                (0.4 * from.min(to) as f64) as u32,
+                error_high,
+            );
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Loads skins without a window and reports which images each one uses, and which lines of its
+//! config files were ignored
+
+use graphics::{draw_state::DrawState, Graphics};
+use remani::{
+    config,
+    gameskin::{self, SkinReport, TextureSource},
+};
+use texture::{CreateTexture, Format, ImageSize, TextureOp, TextureSettings};
+
+use std::{env, ffi::OsStr, path, process};
+
+fn output_help(binary_name: &OsStr) {
+    println!("Usage:  {} [skin name or path/to/skin]...", binary_name.to_string_lossy());
+    println!();
+    println!("Checks every skin in the config if no skins are given. Skin names are looked up in the config,");
+    println!("anything else is a skin directory, with its type guessed from the files in it.");
+}
+
+/// Stands in for a texture on the GPU, only remembering its size
+struct MockTexture([u32; 2]);
+
+impl ImageSize for MockTexture {
+    fn get_size(&self) -> (u32, u32) {
+        (self.0[0], self.0[1])
+    }
+}
+
+impl TextureOp<()> for MockTexture {
+    type Error = String;
+}
+
+impl CreateTexture<()> for MockTexture {
+    fn create<S: Into<[u32; 2]>>(
+        _factory: &mut (),
+        _format: Format,
+        _memory: &[u8],
+        size: S,
+        _settings: &TextureSettings,
+    ) -> Result<Self, Self::Error> {
+        Ok(MockTexture(size.into()))
+    }
+}
+
+/// Skins are only loaded, never drawn, so this doesn't draw anything
+struct MockGraphics;
+
+impl Graphics for MockGraphics {
+    type Texture = MockTexture;
+
+    fn clear_color(&mut self, _color: [f32; 4]) {}
+    fn clear_stencil(&mut self, _value: u8) {}
+    fn tri_list<F>(&mut self, _draw_state: &DrawState, _color: &[f32; 4], _f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]])),
+    {
+    }
+    fn tri_list_c<F>(&mut self, _draw_state: &DrawState, _f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]])),
+    {
+    }
+    fn tri_list_uv<F>(&mut self, _draw_state: &DrawState, _color: &[f32; 4], _texture: &MockTexture, _f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])),
+    {
+    }
+    fn tri_list_uv_c<F>(&mut self, _draw_state: &DrawState, _texture: &MockTexture, _f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])),
+    {
+    }
+}
+
+/// A skin from the config if there's one called `arg`, otherwise the skin in the directory `arg`
+fn skin_from_arg(config: &config::Config, arg: &OsStr) -> (String, config::SkinEntry) {
+    if let Some((name, entry)) = config.game.skins.iter().find(|(name, _)| OsStr::new(name) == arg) {
+        return (name.clone(), entry.clone());
+    }
+    let dir = path::PathBuf::from(arg);
+    let name = dir.display().to_string();
+    let entry = if dir.join("skin.toml").exists() {
+        config::SkinEntry::Native(dir)
+    } else if dir.join("Playing1.opi").exists() {
+        config::SkinEntry::O2Jam(dir)
+    } else {
+        config::SkinEntry::Osu(dir)
+    };
+    (name, entry)
+}
+
+fn print_report(report: &SkinReport) {
+    println!("Textures:");
+    for (element, source) in &report.textures {
+        match source {
+            TextureSource::Skin(p) => println!("    {:<24} skin     {}", element, p.display()),
+            TextureSource::Default(p) => println!("    {:<24} default  {}", element, p.display()),
+            TextureSource::Missing => println!("    {:<24} missing", element),
+        }
+    }
+    if !report.unrecognised_keys.is_empty() {
+        println!("Unrecognised keys:");
+        for (file, line, key) in &report.unrecognised_keys {
+            println!("    {}:{}: {}", file.display(), line, key);
+        }
+    }
+    if !report.malformed_values.is_empty() {
+        println!("Malformed values:");
+        for (file, line, reason) in &report.malformed_values {
+            println!("    {}:{}: {}", file.display(), line, reason);
+        }
+    }
+}
+
+fn main() {
+    let mut args = env::args_os();
+    let binary = args.next().unwrap_or(format!("./{}", file!().rsplitn(2, ".rs").nth(1).unwrap()).into());
+    let args: Vec<_> = args.collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        output_help(&binary);
+        return;
+    }
+
+    let config = config::get_config(config::config_path());
+    let skins: Vec<_> = if args.is_empty() {
+        config.game.skins.clone()
+    } else {
+        args.iter().map(|arg| skin_from_arg(&config, arg)).collect()
+    };
+
+    let mut failed = 0;
+    for (name, entry) in &skins {
+        println!("== {} ({}) ==", name, entry.path().display());
+        let mut report = SkinReport::default();
+        let result = gameskin::from_path_with_report::<MockGraphics, _>(&mut (), entry, &config, &mut report);
+        print_report(&report);
+        match result {
+            Ok(_) => println!("Loaded successfully"),
+            Err(e) => {
+                println!("Failed to load: {}", e);
+                failed += 1;
+            }
+        }
+        println!();
+    }
+
+    println!("{} of {} skins loaded", skins.len() - failed, skins.len());
+    if failed > 0 {
+        process::exit(1);
+    }
+}
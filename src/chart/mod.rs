@@ -4,8 +4,12 @@ use crate::{audio, config::Config};
 
 use std::{error, fmt, io, path};
 
+pub mod modifier;
 pub mod osu;
 pub mod ojn;
+mod scroll_map;
+
+pub use self::scroll_map::ScrollMap;
 
 // TODO temporary for testing
 pub use self::ojn::dump_data as ojn_dump;
@@ -13,7 +17,7 @@ pub use self::ojn::ojm_dump;
 
 /// Either a long note or a regular note. The existence of end_time signifies whether this is a long
 /// note or not.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Note {
     /// Where the note begins, in seconds.
     pub time: f64,
@@ -26,7 +30,13 @@ pub struct Note {
 
     /// The index of the sound to play when the note is hit. You can get the actual sound via
     /// Chart::get_sound
-    pub sound_index: Option<usize>
+    pub sound_index: Option<usize>,
+
+    /// What the volume of the note's sound is multiplied by
+    pub volume: f32,
+
+    /// Where the note's sound sits between the speakers, from -1.0 (left) to 1.0 (right)
+    pub pan: f32,
 }
 
 #[derive(Debug)]
@@ -36,6 +46,8 @@ pub struct AutoplaySound {
     /// The index of the sound. You can get the actual sound via Chart::get_sound
     pub sound_index: usize,
     pub volume: f32,
+    /// From -1.0 (left) to 1.0 (right)
+    pub pan: f32,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -78,6 +90,123 @@ impl TimingPoint {
     }
 }
 
+/// Find when a measure starts, assuming 4 beats per measure. Measures are counted from 1, and every
+/// BPM change starts a new measure like it does in osu. Returns `None` if there are no BPM changes
+/// or `measure` is 0.
+pub fn measure_time(timing_points: &[TimingPoint], measure: u32) -> Option<f64> {
+    let mut bpm_points = timing_points.iter()
+        .filter(|tp| tp.is_bpm() && tp.value.inner() > 0.0)
+        .peekable();
+
+    // How many more measures to go
+    let mut remaining = measure.checked_sub(1)? as f64;
+    while let Some(tp) = bpm_points.next() {
+        let measure_length = 4.0 * 60.0 / tp.value.inner();
+        match bpm_points.peek() {
+            Some(next) => {
+                let measure_count = ((next.offset - tp.offset) / measure_length).ceil().max(0.0);
+                if remaining < measure_count {
+                    return Some(tp.offset + remaining * measure_length);
+                }
+                remaining -= measure_count;
+            }
+            None => return Some(tp.offset + remaining * measure_length),
+        }
+    }
+    None
+}
+
+/// The beat divisions notes can be coloured by, as note values in 4/4 time. Notes on the beat are
+/// 4ths, notes halfway between beats are 8ths, and so on.
+pub const SNAP_DIVISIONS: [u32; 8] = [4, 8, 12, 16, 24, 32, 48, 64];
+
+/// How far off a division a note can be and still be on it, in seconds. Osu charts round times to
+/// the millisecond.
+const SNAP_TOLERANCE: f64 = 0.002;
+
+/// Which beat division a note falls on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Snap {
+    /// One of `SNAP_DIVISIONS`
+    Division(u32),
+    /// Not on any of `SNAP_DIVISIONS`
+    Unsnapped,
+}
+
+impl Snap {
+    /// Where the division is in `SNAP_DIVISIONS`, or `SNAP_DIVISIONS.len()` for unsnapped notes.
+    /// Meant for looking up colours in a palette.
+    pub fn index(self) -> usize {
+        match self {
+            Snap::Division(d) => SNAP_DIVISIONS.iter().position(|&s| s == d).unwrap_or(SNAP_DIVISIONS.len()),
+            Snap::Unsnapped => SNAP_DIVISIONS.len(),
+        }
+    }
+}
+
+/// Find the coarsest beat division `time` falls on, counting beats from the last BPM change
+/// before it. Times before the first BPM change count back from it.
+pub fn note_snap(timing_points: &[TimingPoint], time: f64) -> Snap {
+    let mut bpm_points = timing_points.iter().filter(|tp| tp.is_bpm() && tp.value.inner() > 0.0);
+    let first = match bpm_points.next() {
+        Some(tp) => tp,
+        None => return Snap::Unsnapped,
+    };
+    let tp = bpm_points
+        .take_while(|tp| tp.offset <= time + SNAP_TOLERANCE)
+        .last()
+        .unwrap_or(first);
+
+    let beat_length = 60.0 / tp.value.inner();
+    let beats = (time - tp.offset) / beat_length;
+    for &division in SNAP_DIVISIONS.iter() {
+        let per_beat = f64::from(division) / 4.0;
+        let distance = ((beats * per_beat).round() - beats * per_beat).abs() * beat_length / per_beat;
+        if distance <= SNAP_TOLERANCE {
+            return Snap::Division(division);
+        }
+    }
+    Snap::Unsnapped
+}
+
+/// Charts with more beats than this between two BPM changes get no bar lines there. Some osu
+/// charts use huge BPMs for visual effects.
+const MAX_BEATS_PER_SECTION: f64 = 100_000.0;
+
+/// A line across the lanes on a beat
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BarLine {
+    /// In seconds, like note times
+    pub time: f64,
+    /// Whether the beat starts a measure
+    pub measure: bool,
+}
+
+/// Put a line on every beat until `end_time`, with every 4th one starting a measure. Every BPM
+/// change starts a new measure, like in `measure_time`.
+pub fn bar_lines(timing_points: &[TimingPoint], end_time: f64) -> Vec<BarLine> {
+    let mut bpm_points = timing_points.iter()
+        .filter(|tp| tp.is_bpm() && tp.value.inner() > 0.0)
+        .peekable();
+
+    let mut lines = Vec::new();
+    while let Some(tp) = bpm_points.next() {
+        let section_end = bpm_points.peek().map(|next| next.offset).unwrap_or(end_time);
+        let beat_length = 60.0 / tp.value.inner();
+        let beat_count = ((section_end - tp.offset) / beat_length).ceil();
+        if beat_count > MAX_BEATS_PER_SECTION {
+            continue;
+        }
+        for beat in 0..beat_count.max(0.0) as u32 {
+            lines.push(BarLine {
+                time: tp.offset + f64::from(beat) * beat_length,
+                measure: beat % 4 == 0,
+            });
+        }
+    }
+    lines
+}
+
 /// The error type from parsing
 #[derive(Debug)]
 pub enum ParseError {
@@ -130,8 +259,8 @@ pub trait Chart {
     /// The bpm for most of the song
     fn primary_bpm(&self) -> f64;
 
-    /// Loads and returns the music
-    fn music(&mut self, format: &cpal::Format) -> Result<audio::MusicStream, audio::AudioLoadError>;
+    /// Loads and returns the music, resampled with the config's quality if needed
+    fn music(&mut self, format: &cpal::Format, config: &Config) -> Result<audio::MusicStream, audio::AudioLoadError>;
 
     /// Returns the autoplay sounds sorted by time
     fn autoplay_sounds(&self) -> &[AutoplaySound];
@@ -142,6 +271,16 @@ pub trait Chart {
     /// Should always returns None until `load_sounds` has been called, in which case it might return
     /// `None` or an empty `EffectStream`.
     fn get_sound(&self, i: usize) -> Option<audio::EffectStream>;
+
+    /// Measure and beat lines, sorted by time. Worked out from the BPM changes by default, charts
+    /// that store their own measures (like OJN and BMS) should use those instead.
+    fn bar_lines(&self) -> Vec<BarLine> {
+        let end_time = self.notes()
+            .iter()
+            .map(|n| n.end_time.unwrap_or(n.time))
+            .fold(0.0, f64::max);
+        bar_lines(self.timing_points(), end_time)
+    }
 }
 
 /// Chart metadata used by the song select scene
@@ -170,3 +309,47 @@ pub struct Difficulty {
     pub name: String,
     pub path: path::PathBuf,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bpm(offset: f64, bpm: f64) -> TimingPoint {
+        TimingPoint { offset, value: TimingPointValue::BPM(bpm) }
+    }
+
+    #[test]
+    fn test_note_snap() {
+        // 120 bpm, half a second per beat
+        let timing_points = [bpm(1.0, 120.0), TimingPoint { offset: 2.0, value: TimingPointValue::SV(2.0) }];
+        assert_eq!(Snap::Division(4), note_snap(&timing_points, 3.0));
+        assert_eq!(Snap::Division(8), note_snap(&timing_points, 3.25));
+        assert_eq!(Snap::Division(12), note_snap(&timing_points, 1.0 + 0.5 / 3.0));
+        assert_eq!(Snap::Division(16), note_snap(&timing_points, 1.125));
+        // rounded to the millisecond like in osu charts
+        assert_eq!(Snap::Division(16), note_snap(&timing_points, 1.375 + 0.0004));
+        assert_eq!(Snap::Unsnapped, note_snap(&timing_points, 1.01));
+        // before the first bpm change
+        assert_eq!(Snap::Division(8), note_snap(&timing_points, 0.75));
+    }
+
+    #[test]
+    fn test_note_snap_uses_last_bpm_change() {
+        let timing_points = [bpm(0.0, 120.0), bpm(1.1, 60.0)];
+        assert_eq!(Snap::Division(4), note_snap(&timing_points, 2.1));
+        assert_eq!(Snap::Division(8), note_snap(&timing_points, 1.6));
+        assert_eq!(Snap::Unsnapped, note_snap(&[], 1.0));
+        assert_eq!(SNAP_DIVISIONS.len(), Snap::Unsnapped.index());
+        assert_eq!(2, Snap::Division(12).index());
+    }
+
+    #[test]
+    fn test_bar_lines() {
+        let timing_points = [bpm(1.0, 120.0), TimingPoint { offset: 2.0, value: TimingPointValue::SV(0.5) }, bpm(3.25, 60.0)];
+        let lines = bar_lines(&timing_points, 5.0);
+        let times: Vec<_> = lines.iter().map(|l| l.time).collect();
+        assert_eq!(vec![1.0, 1.5, 2.0, 2.5, 3.0, 3.25, 4.25], times);
+        let measures: Vec<_> = lines.iter().map(|l| l.measure).collect();
+        assert_eq!(vec![true, false, false, false, true, true, false], measures);
+    }
+}
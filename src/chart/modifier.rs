@@ -0,0 +1,357 @@
+//! Modifiers that wrap a `Chart` and change its notes or timing.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{
+    audio,
+    chart::{AutoplaySound, BarLine, Chart, Note, TimingPoint, TimingPointValue},
+    config::{Config, NoteConversion},
+};
+
+/// How long before the next note in the same column a generated long note ends, in seconds.
+const LONG_NOTE_GAP: f64 = 0.05;
+
+/// Long notes shorter than this are turned into regular notes instead, in seconds.
+const MIN_LONG_NOTE_LENGTH: f64 = 0.05;
+
+/// A chart with its note types rewritten. Everything other than the notes is passed through to
+/// the inner chart, and every note keeps its `sound_index` so keysounds still line up.
+pub struct ConvertedChart {
+    inner: Box<dyn Chart>,
+    notes: Vec<Note>,
+}
+
+impl ConvertedChart {
+    pub fn new(inner: Box<dyn Chart>, conversion: NoteConversion) -> Self {
+        let notes = convert_notes(inner.notes(), conversion);
+        ConvertedChart { inner, notes }
+    }
+}
+
+impl Chart for ConvertedChart {
+    fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+    fn timing_points(&self) -> &[TimingPoint] {
+        self.inner.timing_points()
+    }
+    fn primary_bpm(&self) -> f64 {
+        self.inner.primary_bpm()
+    }
+    fn music(&mut self, format: &cpal::Format, config: &Config) -> Result<audio::MusicStream, audio::AudioLoadError> {
+        self.inner.music(format, config)
+    }
+    fn autoplay_sounds(&self) -> &[AutoplaySound] {
+        self.inner.autoplay_sounds()
+    }
+    fn load_sounds(&mut self, format: &cpal::Format, config: &Config) {
+        self.inner.load_sounds(format, config)
+    }
+    fn get_sound(&self, i: usize) -> Option<audio::EffectStream> {
+        self.inner.get_sound(i)
+    }
+    fn bar_lines(&self) -> Vec<BarLine> {
+        self.inner.bar_lines()
+    }
+}
+
+/// The slowest and fastest rates supported by `RateChart`.
+pub const RATE_RANGE: (f64, f64) = (0.5, 2.0);
+
+/// A chart played back faster or slower. Note times, timing points and autoplay sound times are
+/// scaled, and the music and sounds are resampled (or time stretched) to match.
+pub struct RateChart {
+    inner: Box<dyn Chart>,
+    rate: f64,
+    preserve_pitch: bool,
+    notes: Vec<Note>,
+    timing_points: Vec<TimingPoint>,
+    autoplay_sounds: Vec<AutoplaySound>,
+    /// Sounds from the inner chart with the rate applied, filled in by `load_sounds`
+    sounds: HashMap<usize, audio::EffectStream>,
+}
+
+impl RateChart {
+    pub fn new(inner: Box<dyn Chart>, rate: f64, preserve_pitch: bool) -> Self {
+        let notes = inner.notes()
+            .iter()
+            .map(|n| Note {
+                time: n.time / rate,
+                end_time: n.end_time.map(|t| t / rate),
+                ..n.clone()
+            })
+            .collect();
+        let timing_points = inner.timing_points()
+            .iter()
+            .map(|tp| TimingPoint {
+                offset: tp.offset / rate,
+                value: match tp.value {
+                    TimingPointValue::BPM(bpm) => TimingPointValue::BPM(bpm * rate),
+                    v @ TimingPointValue::SV(_) => v,
+                },
+            })
+            .collect();
+        let autoplay_sounds = inner.autoplay_sounds()
+            .iter()
+            .map(|s| AutoplaySound {
+                time: s.time / rate,
+                sound_index: s.sound_index,
+                volume: s.volume,
+                pan: s.pan,
+            })
+            .collect();
+        RateChart {
+            inner,
+            rate,
+            preserve_pitch,
+            notes,
+            timing_points,
+            autoplay_sounds,
+            sounds: HashMap::new(),
+        }
+    }
+}
+
+impl Chart for RateChart {
+    fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+    fn timing_points(&self) -> &[TimingPoint] {
+        &self.timing_points
+    }
+    fn primary_bpm(&self) -> f64 {
+        self.inner.primary_bpm() * self.rate
+    }
+    fn music(&mut self, format: &cpal::Format, config: &Config) -> Result<audio::MusicStream, audio::AudioLoadError> {
+        let (rate, preserve_pitch) = (self.rate, self.preserve_pitch);
+        let quality = config.general.resample_quality;
+        self.inner.music(format, config).map(|m| m.with_rate(rate, format, preserve_pitch, quality))
+    }
+    fn autoplay_sounds(&self) -> &[AutoplaySound] {
+        &self.autoplay_sounds
+    }
+    fn load_sounds(&mut self, format: &cpal::Format, config: &Config) {
+        self.inner.load_sounds(format, config);
+
+        // Convert every sound up front so it doesn't have to happen during gameplay
+        let indices = self.notes
+            .iter()
+            .filter_map(|n| n.sound_index)
+            .chain(self.autoplay_sounds.iter().map(|s| s.sound_index));
+        for i in indices {
+            if self.sounds.contains_key(&i) {
+                continue;
+            }
+            if let Some(sound) = self.inner.get_sound(i) {
+                self.sounds.insert(i, sound.with_rate(self.rate, format, self.preserve_pitch, config.general.resample_quality));
+            }
+        }
+    }
+    fn get_sound(&self, i: usize) -> Option<audio::EffectStream> {
+        self.sounds.get(&i).cloned()
+    }
+    fn bar_lines(&self) -> Vec<BarLine> {
+        self.inner
+            .bar_lines()
+            .into_iter()
+            .map(|l| BarLine { time: l.time / self.rate, ..l })
+            .collect()
+    }
+}
+
+/// Wrap the chart with whatever modifiers are enabled in the config.
+pub fn apply(chart: Box<dyn Chart>, config: &Config) -> Box<dyn Chart> {
+    let modifiers = &config.game.modifiers;
+    let chart: Box<dyn Chart> = match modifiers.note_conversion {
+        NoteConversion::None => chart,
+        c => Box::new(ConvertedChart::new(chart, c)),
+    };
+    let rate = modifiers.rate.max(RATE_RANGE.0).min(RATE_RANGE.1);
+    if rate != modifiers.rate {
+        remani_warn!("Rate {} is out of range, using {}", modifiers.rate, rate);
+    }
+    if rate == 1.0 {
+        chart
+    } else {
+        Box::new(RateChart::new(chart, rate, modifiers.preserve_pitch))
+    }
+}
+
+/// Returns the converted notes, sorted by time.
+fn convert_notes(notes: &[Note], conversion: NoteConversion) -> Vec<Note> {
+    let mut new_notes: Vec<Note> = match conversion {
+        NoteConversion::None => notes.to_vec(),
+        NoteConversion::NoLongNotes => notes
+            .iter()
+            .map(|n| Note { end_time: None, ..n.clone() })
+            .collect(),
+        NoteConversion::FullLongNotes => {
+            let mut new_notes = Vec::with_capacity(notes.len());
+            for (i, note) in notes.iter().enumerate() {
+                let end_time = match next_in_column(notes, i) {
+                    Some(next) => long_note_end(note.time, next.time).or(note.end_time),
+                    None => note.end_time,
+                };
+                new_notes.push(Note { end_time, ..note.clone() });
+            }
+            new_notes
+        }
+        NoteConversion::Inverse => {
+            let mut new_notes = Vec::with_capacity(notes.len());
+            for (i, note) in notes.iter().enumerate() {
+                // The new long note starts where the old one ended and fills the gap up to the
+                // next note
+                let start_time = note.end_time.unwrap_or(note.time);
+                let end_time = next_in_column(notes, i)
+                    .and_then(|next| long_note_end(start_time, next.time));
+                new_notes.push(Note {
+                    time: start_time,
+                    end_time,
+                    ..note.clone()
+                });
+            }
+            new_notes
+        }
+    };
+    new_notes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+    new_notes
+}
+
+/// Find the note after `notes[index]` that's in the same column.
+fn next_in_column(notes: &[Note], index: usize) -> Option<&Note> {
+    let column = notes[index].column;
+    notes[index + 1..].iter().find(|n| n.column == column)
+}
+
+/// Calculate the end time of a long note that starts at `start` and ends just before `next`.
+/// Returns `None` if the long note would be too short.
+fn long_note_end(start: f64, next: f64) -> Option<f64> {
+    let end = next - LONG_NOTE_GAP;
+    if end - start >= MIN_LONG_NOTE_LENGTH {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(time: f64, column: usize, end_time: Option<f64>) -> Note {
+        Note { time, column, end_time, sound_index: Some(column), volume: 0.5, pan: -0.25 }
+    }
+
+    /// A chart without any sounds
+    struct TestChart {
+        notes: Vec<Note>,
+        timing_points: Vec<TimingPoint>,
+        autoplay_sounds: Vec<AutoplaySound>,
+    }
+
+    impl Chart for TestChart {
+        fn notes(&self) -> &[Note] {
+            &self.notes
+        }
+        fn timing_points(&self) -> &[TimingPoint] {
+            &self.timing_points
+        }
+        fn primary_bpm(&self) -> f64 {
+            120.0
+        }
+        fn music(&mut self, _: &cpal::Format, _: &Config) -> Result<audio::MusicStream, audio::AudioLoadError> {
+            Err(audio::AudioLoadError::UnsupportedFormat("no music".into()))
+        }
+        fn autoplay_sounds(&self) -> &[AutoplaySound] {
+            &self.autoplay_sounds
+        }
+        fn load_sounds(&mut self, _: &cpal::Format, _: &Config) {}
+        fn get_sound(&self, _: usize) -> Option<audio::EffectStream> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_rate_scales_times() {
+        let chart = TestChart {
+            notes: vec![note(1.0, 0, Some(2.0)), note(3.0, 1, None)],
+            timing_points: vec![
+                TimingPoint { offset: 0.5, value: TimingPointValue::BPM(120.0) },
+                TimingPoint { offset: 2.5, value: TimingPointValue::SV(2.0) },
+            ],
+            autoplay_sounds: vec![AutoplaySound { time: 1.5, sound_index: 0, volume: 1.0, pan: 0.0 }],
+        };
+        let inner_bar_lines = chart.bar_lines();
+        let rate = 1.5;
+        let chart = RateChart::new(Box::new(chart), rate, false);
+
+        assert_eq!(1.0 / rate, chart.notes()[0].time);
+        assert_eq!(Some(2.0 / rate), chart.notes()[0].end_time);
+        assert_eq!(3.0 / rate, chart.notes()[1].time);
+        assert_eq!(None, chart.notes()[1].end_time);
+
+        assert_eq!(0.5 / rate, chart.timing_points()[0].offset);
+        assert!(chart.timing_points()[0].is_bpm());
+        assert_eq!(120.0 * rate, chart.timing_points()[0].value.inner());
+        assert_eq!(2.5 / rate, chart.timing_points()[1].offset);
+        // SV is relative to the BPM, which already went up
+        assert!(!chart.timing_points()[1].is_bpm());
+        assert_eq!(2.0, chart.timing_points()[1].value.inner());
+        assert_eq!(120.0 * rate, chart.primary_bpm());
+
+        assert_eq!(1.5 / rate, chart.autoplay_sounds()[0].time);
+
+        let bar_lines = chart.bar_lines();
+        assert!(!bar_lines.is_empty());
+        assert_eq!(inner_bar_lines.len(), bar_lines.len());
+        for (line, inner) in bar_lines.iter().zip(&inner_bar_lines) {
+            assert_eq!(inner.time / rate, line.time);
+            assert_eq!(inner.measure, line.measure);
+        }
+    }
+
+    #[test]
+    fn test_conversions_keep_volume_and_pan() {
+        let notes = [note(1.0, 0, Some(2.0)), note(1.5, 1, None), note(3.0, 0, None)];
+        for &conversion in &[
+            NoteConversion::NoLongNotes,
+            NoteConversion::FullLongNotes,
+            NoteConversion::Inverse,
+        ] {
+            for n in convert_notes(&notes, conversion) {
+                assert_eq!((0.5, -0.25), (n.volume, n.pan));
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_long_notes() {
+        let notes = [note(1.0, 0, Some(2.0)), note(1.5, 1, None)];
+        let converted = convert_notes(&notes, NoteConversion::NoLongNotes);
+        assert!(converted.iter().all(|n| n.end_time.is_none()));
+        assert_eq!(Some(0), converted[0].sound_index);
+    }
+
+    #[test]
+    fn test_full_long_notes() {
+        let notes = [note(1.0, 0, None), note(1.5, 1, None), note(2.0, 0, None), note(2.02, 0, None)];
+        let converted = convert_notes(&notes, NoteConversion::FullLongNotes);
+        assert_eq!(Some(2.0 - LONG_NOTE_GAP), converted[0].end_time);
+        assert_eq!(None, converted[1].end_time);
+        // too close to the next note
+        assert_eq!(None, converted[2].end_time);
+        assert_eq!(None, converted[3].end_time);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let notes = [note(1.0, 0, Some(1.5)), note(1.2, 1, None), note(3.0, 0, None)];
+        let converted = convert_notes(&notes, NoteConversion::Inverse);
+        assert_eq!(1.2, converted[0].time);
+        assert_eq!(1.5, converted[1].time);
+        assert_eq!(Some(3.0 - LONG_NOTE_GAP), converted[1].end_time);
+        assert_eq!(Some(0), converted[1].sound_index);
+        assert_eq!(3.0, converted[2].time);
+        assert_eq!(None, converted[2].end_time);
+    }
+}
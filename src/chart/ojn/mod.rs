@@ -2,7 +2,10 @@
 
 use nom::*;
 
-use crate::chart::{Note, TimingPoint};
+use crate::{
+    audio,
+    chart::{Note, TimingPoint},
+};
 
 fn string_from_slice(s: &[u8]) -> String {
     String::from_utf8_lossy(s).into_owned()
@@ -159,6 +162,35 @@ struct NoteEvent {
     note_type: u8,
 }
 
+impl NoteEvent {
+    /// The volume as a multiplier for `EffectStream::with_volume`
+    fn volume(&self) -> f32 {
+        f32::from(16 - self.volume.min(15)) / 16.0
+    }
+
+    /// The pan from -1.0 (left) to 1.0 (right) for `EffectStream::with_pan`
+    fn pan(&self) -> f32 {
+        match self.pan {
+            p @ 1..=15 => (f32::from(p) - 8.0) / 7.0,
+            _ => 0.0,
+        }
+    }
+
+    /// The sound this event plays from the chart's OJM file, at the event's volume and pan. `None`
+    /// if the event is ignored or its sample doesn't exist.
+    fn keysound(&self, sounds: &ojm::Sounds) -> Option<audio::EffectStream> {
+        if self.value <= 0 {
+            return None;
+        }
+        let sounds = match self.note_type {
+            4 => &sounds.bg_sounds,
+            _ => &sounds.key_sounds,
+        };
+        sounds.get(self.value as usize - 1)
+            .map(|s| s.clone().with_volume(self.volume()).with_pan(self.pan()))
+    }
+}
+
 #[derive(Debug)]
 enum Events {
     MeasureFraction(Vec<f32>),
@@ -299,3 +331,38 @@ pub fn dump_data<P: AsRef<Path>>(path: P) {
     println!("Level: {}", hdr.level[2]);
     print_packages(&hard_packages);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(value: i16, volume: u8, pan: u8, note_type: u8) -> NoteEvent {
+        NoteEvent { value, volume, pan, note_type }
+    }
+
+    #[test]
+    fn test_note_event_pan() {
+        assert_eq!(event(1, 0, 0, 0).pan(), 0.0);
+        assert_eq!(event(1, 0, 1, 0).pan(), -1.0);
+        assert_eq!(event(1, 0, 8, 0).pan(), 0.0);
+        assert_eq!(event(1, 0, 15, 0).pan(), 1.0);
+    }
+
+    #[test]
+    fn test_note_event_volume() {
+        assert_eq!(event(1, 0, 0, 0).volume(), 1.0);
+        assert_eq!(event(1, 15, 0, 0).volume(), 1.0 / 16.0);
+    }
+
+    #[test]
+    fn test_note_event_keysound() {
+        let sounds = ojm::Sounds {
+            key_sounds: vec![audio::EffectStream::empty()],
+            bg_sounds: vec![],
+        };
+        assert!(event(1, 0, 0, 0).keysound(&sounds).is_some());
+        assert!(event(0, 0, 0, 0).keysound(&sounds).is_none());
+        assert!(event(2, 0, 0, 0).keysound(&sounds).is_none());
+        assert!(event(1, 0, 0, 4).keysound(&sounds).is_none());
+    }
+}
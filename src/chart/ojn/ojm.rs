@@ -313,9 +313,11 @@ named!(omc_ogg_sounds(&[u8]) -> Vec<OmcOggSound>,
     many0!(complete!(omc_ogg_sound))
 );
 
-struct Sounds {
-    key_sounds: Vec<audio::EffectStream>,
-    bg_sounds: Vec<audio::EffectStream>,
+pub(super) struct Sounds {
+    /// The WAV sounds, or M30 sounds with a `note_ref` of 0
+    pub(super) key_sounds: Vec<audio::EffectStream>,
+    /// The OGG sounds, or the rest of the M30 sounds
+    pub(super) bg_sounds: Vec<audio::EffectStream>,
 }
 
 use std::{
@@ -411,6 +411,9 @@ impl HitObject {
             column: self.column,
             end_time: self.end_time,
             sound_index: Some(*sound_cache.entry(self.sounds).or_insert(len)),
+            // The hit sounds' volumes are already mixed into the sound
+            volume: 1.0,
+            pan: 0.0,
         }
     }
 }
@@ -443,11 +446,11 @@ impl HitSound {
                     return Ok(effect_stream.clone());
                 }
                 path.set_extension("wav");
-                let effect_stream: audio::EffectStream = match audio::music_from_path(&path, format) {
+                let effect_stream: audio::EffectStream = match audio::music_from_path(&path, format, config.general.resample_quality) {
                     Ok(s) => s.into(),
                     Err(_) => {
                         path.set_extension("ogg");
-                        match audio::music_from_path(&path, format) {
+                        match audio::music_from_path(&path, format, config.general.resample_quality) {
                             Ok(s) => s.into(),
                             Err(e) => return Err((path, e)),
                         }
@@ -468,7 +471,7 @@ impl HitSound {
                     }
                 }
                 if let Some(path) = the_path {
-                    let effect_stream: audio::EffectStream = match audio::music_from_path(&path, format) {
+                    let effect_stream: audio::EffectStream = match audio::music_from_path(&path, format, config.general.resample_quality) {
                         Ok(s) => s.into(),
                         Err(e) => return Err((path, e)),
                     };
@@ -581,7 +584,7 @@ impl SampleHitSound {
         let path1 = chart.chart_path.join(&filename_with_index_wav);
         let path2 = match &config.game.current_skin().1 {
             config::SkinEntry::Osu(path) => Some(path.join(&filename_without_index_wav)),
-            config::SkinEntry::O2Jam(_path) => None,
+            config::SkinEntry::O2Jam(_path) | config::SkinEntry::Native(_path) => None,
         };
         let path3 = config.game.default_osu_skin_path.join(&filename_without_index_wav);
 
@@ -658,6 +661,7 @@ impl OsuAutoplaySound {
         AutoplaySound {
             time: self.time,
             volume: self.volume,
+            pan: 0.0,
             sound_index: *cache.entry(self.sound_file).or_insert(start_index + cache_len),
         }
     }
@@ -748,8 +752,8 @@ impl Chart for OsuChart {
     fn autoplay_sounds(&self) -> &[AutoplaySound] {
         &self.autoplay_sounds
     }
-    fn music(&mut self, format: &cpal::Format) -> Result<audio::MusicStream, audio::AudioLoadError> {
-        audio::music_from_path(&self.chart_path.join(&self.music_path), format)
+    fn music(&mut self, format: &cpal::Format, config: &Config) -> Result<audio::MusicStream, audio::AudioLoadError> {
+        audio::music_from_path(&self.chart_path.join(&self.music_path), format, config.general.resample_quality)
     }
     fn load_sounds(&mut self, format: &cpal::Format, config: &Config) {
         let mut cache = HashMap::new();
@@ -782,14 +786,14 @@ impl Chart for OsuChart {
                     let sound = if let Some(s) = cache.get(&path) {
                         s.clone()
                     } else {
-                        let s = audio::music_from_path(&path, format)
+                        let s = audio::music_from_path(&path, format, config.general.resample_quality)
                             .or_else(|_| {
                                 path.set_extension("ogg");
-                                audio::music_from_path(&path, format)
+                                audio::music_from_path(&path, format, config.general.resample_quality)
                             })
                             .or_else(|_| {
                                 path.set_extension("mp3");
-                                audio::music_from_path(&path, format)
+                                audio::music_from_path(&path, format, config.general.resample_quality)
                             })
                             .map(Into::into)
                             .unwrap_or_else(|e| {
@@ -0,0 +1,171 @@
+//! Works out where things are on the scrolling playfield from a chart's BPM and SV changes
+
+use super::{TimingPoint, TimingPointValue};
+
+/// Part of the chart where the playfield scrolls at the same speed
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Segment {
+    /// When the segment starts, in seconds
+    time: f64,
+    /// The position at `time`
+    position: f64,
+    /// Position per second, negative if the playfield scrolls backwards
+    velocity: f64,
+}
+
+/// How far the playfield has scrolled at any time in a chart, built once from its timing points.
+///
+/// Positions are in seconds of scrolling at the chart's primary BPM with no SV, so something
+/// `scroll_speed` lanes per second away from the judgement line is `(position(t) -
+/// position(now)) * scroll_speed` lanes above it. The speed is the BPM over the primary BPM, times
+/// the SV. Every BPM change resets the SV like in osu, a BPM or SV of 0 stops the playfield and a
+/// negative one scrolls it backwards.
+#[derive(Clone, Debug)]
+pub struct ScrollMap {
+    /// Sorted by time, never empty
+    segments: Vec<Segment>,
+    /// How fast the playfield scrolls before the first segment
+    initial_velocity: f64,
+}
+
+impl ScrollMap {
+    /// Timing points that aren't finite are skipped.
+    pub fn new(timing_points: &[TimingPoint], primary_bpm: f64) -> Self {
+        let bpm_velocity = |bpm: f64| if primary_bpm != 0.0 { bpm / primary_bpm } else { 1.0 };
+        let timing_points = timing_points.iter()
+            .filter(|tp| tp.offset.is_finite() && tp.value.inner().is_finite());
+
+        // The first BPM change also counts for everything before it
+        let mut bpm_speed = match timing_points.clone().next() {
+            Some(TimingPoint { value: TimingPointValue::BPM(bpm), .. }) => bpm_velocity(*bpm),
+            _ => 1.0,
+        };
+        let initial_velocity = bpm_speed;
+        let mut segments = Vec::new();
+
+        for tp in timing_points {
+            let velocity = match tp.value {
+                // with the SV back at 1x
+                TimingPointValue::BPM(bpm) => {
+                    bpm_speed = bpm_velocity(bpm);
+                    bpm_speed
+                }
+                TimingPointValue::SV(sv) => bpm_speed * sv,
+            };
+            match segments.last_mut() {
+                // Timing points at the same time replace each other
+                Some(Segment { time, velocity: v, .. }) if *time == tp.offset => *v = velocity,
+                Some(&mut last) => segments.push(Segment {
+                    time: tp.offset,
+                    position: last.position + (tp.offset - last.time) * last.velocity,
+                    velocity,
+                }),
+                None => segments.push(Segment { time: tp.offset, position: 0.0, velocity }),
+            }
+        }
+        if segments.is_empty() {
+            segments.push(Segment { time: 0.0, position: 0.0, velocity: initial_velocity });
+        }
+        ScrollMap { segments, initial_velocity }
+    }
+
+    /// How far the playfield has scrolled at `time`
+    pub fn position(&self, time: f64) -> f64 {
+        let index = self.segments.partition_point(|s| s.time <= time);
+        match index.checked_sub(1) {
+            Some(i) => {
+                let s = &self.segments[i];
+                s.position + (time - s.time) * s.velocity
+            }
+            None => {
+                let first = &self.segments[0];
+                first.position + (time - first.time) * self.initial_velocity
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bpm(offset: f64, bpm: f64) -> TimingPoint {
+        TimingPoint { offset, value: TimingPointValue::BPM(bpm) }
+    }
+
+    fn sv(offset: f64, sv: f64) -> TimingPoint {
+        TimingPoint { offset, value: TimingPointValue::SV(sv) }
+    }
+
+    fn assert_positions(map: &ScrollMap, expected: &[(f64, f64)]) {
+        for &(time, position) in expected {
+            let actual = map.position(time);
+            assert!((actual - position).abs() < 1e-9, "position at {} is {}, expected {}", time, actual, position);
+        }
+    }
+
+    #[test]
+    fn test_constant_bpm() {
+        let map = ScrollMap::new(&[bpm(1.0, 120.0)], 120.0);
+        assert_positions(&map, &[(0.0, -1.0), (1.0, 0.0), (3.5, 2.5)]);
+    }
+
+    #[test]
+    fn test_no_timing_points() {
+        let map = ScrollMap::new(&[], 120.0);
+        assert_positions(&map, &[(-1.0, -1.0), (2.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_bpm_and_sv_changes() {
+        let timing_points = [
+            bpm(0.0, 120.0),
+            sv(1.0, 2.0),
+            // resets the sv
+            bpm(2.0, 240.0),
+            sv(3.0, 0.5),
+        ];
+        let map = ScrollMap::new(&timing_points, 120.0);
+        assert_positions(&map, &[
+            (1.0, 1.0),
+            (1.5, 2.0),
+            (2.0, 3.0),
+            (2.5, 4.0),
+            (3.0, 5.0),
+            (4.0, 6.0),
+        ]);
+    }
+
+    #[test]
+    fn test_timing_points_at_the_same_time() {
+        // like an osu uninherited timing point with an inherited one on top of it
+        let map = ScrollMap::new(&[bpm(0.0, 120.0), bpm(1.0, 60.0), sv(1.0, 4.0)], 120.0);
+        assert_positions(&map, &[(1.0, 1.0), (2.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_sv_before_first_bpm() {
+        let map = ScrollMap::new(&[sv(0.0, 2.0), bpm(1.0, 60.0)], 120.0);
+        assert_positions(&map, &[(-1.0, -1.0), (0.5, 1.0), (1.0, 2.0), (3.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_stop() {
+        let map = ScrollMap::new(&[bpm(0.0, 120.0), sv(1.0, 0.0), sv(2.0, 1.0), bpm(3.0, 0.0)], 120.0);
+        assert_positions(&map, &[(1.0, 1.0), (1.5, 1.0), (2.0, 1.0), (3.0, 2.0), (10.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_negative_sv() {
+        let map = ScrollMap::new(&[bpm(0.0, 120.0), sv(1.0, -1.0), sv(2.0, 1.0)], 120.0);
+        assert_positions(&map, &[(1.0, 1.0), (1.5, 0.5), (2.0, 0.0), (3.0, 1.0)]);
+        // halfway back up, the playfield is still behind where it was when it turned around
+        assert!(map.position(2.5) < map.position(1.0));
+    }
+
+    #[test]
+    fn test_non_finite_timing_points_are_skipped() {
+        let map = ScrollMap::new(&[bpm(0.0, 120.0), bpm(1.0, f64::INFINITY), sv(2.0, f64::NAN)], 120.0);
+        assert_positions(&map, &[(3.0, 3.0)]);
+    }
+}
@@ -2,9 +2,11 @@
 
 use piston::input;
 use serde_derive::{Deserialize, Serialize};
-use std::{collections::BTreeMap, env, fmt, fs, io, path};
+use crate::audio;
+use std::{collections::BTreeMap, env, fmt, fs, io, path, str};
 
 mod serde_buffer_size;
+mod serde_button;
 mod serde_key_bindings;
 
 /// Holds all the configuration values relevant to the gameplay itself, such as like skin
@@ -21,11 +23,48 @@ struct UnverifiedGameConfig {
     current_judge: String,
     osu_hitsound_enable: bool,
 
+    #[serde(default)]
+    osu_judgement_images: OsuJudgementImages,
+
+    #[serde(default)]
+    snap_colors: bool,
+
+    #[serde(default)]
+    watch_skin: bool,
+
     skins: BTreeMap<String, SkinEntry>,
     judges: BTreeMap<String, Judge>,
 
     #[serde(with = "serde_key_bindings")]
     key_bindings: [input::Button; 7],
+
+    #[serde(with = "serde_button", default = "default_lane_cover_up_key")]
+    lane_cover_up_key: input::Button,
+    #[serde(with = "serde_button", default = "default_lane_cover_down_key")]
+    lane_cover_down_key: input::Button,
+    #[serde(with = "serde_button", default = "default_reload_skin_key")]
+    reload_skin_key: input::Button,
+
+    #[serde(default)]
+    modifiers: Modifiers,
+
+    #[serde(default)]
+    practice: Practice,
+
+    #[serde(default)]
+    hit_error: HitError,
+}
+
+fn default_lane_cover_up_key() -> input::Button {
+    input::Button::Keyboard(input::keyboard::Key::Up)
+}
+
+fn default_lane_cover_down_key() -> input::Button {
+    input::Button::Keyboard(input::keyboard::Key::Down)
+}
+
+fn default_reload_skin_key() -> input::Button {
+    input::Button::Keyboard(input::keyboard::Key::F5)
 }
 
 #[derive(Clone, Debug)]
@@ -49,10 +88,33 @@ pub struct GameConfig {
     /// be, so this setting is here.
     pub osu_hitsound_enable: bool,
 
+    /// Which of an osu skin's hit images is shown for each judgement
+    pub osu_judgement_images: OsuJudgementImages,
+
+    /// Colour notes by the beat division they're on, using the skin's palette
+    pub snap_colors: bool,
+
+    /// Load the skin again whenever its files change, instead of only when `reload_skin_key` is
+    /// pressed
+    pub watch_skin: bool,
+
     pub skins: Vec<(String, SkinEntry)>,
     pub judges: Vec<(String, Judge)>,
 
     pub key_bindings: [input::Button; 7],
+
+    /// Moves the lane cover up during play
+    pub lane_cover_up_key: input::Button,
+    /// Moves the lane cover down during play
+    pub lane_cover_down_key: input::Button,
+    /// Loads the skin again during play, for seeing changes to it without restarting the chart
+    pub reload_skin_key: input::Button,
+
+    pub modifiers: Modifiers,
+
+    pub practice: Practice,
+
+    pub hit_error: HitError,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -86,11 +148,20 @@ impl UnverifiedGameConfig {
                 .map_err(|_| GameConfigVerifyError::BadCurrentJudge)?,
 
             osu_hitsound_enable: self.osu_hitsound_enable,
+            osu_judgement_images: self.osu_judgement_images,
+            snap_colors: self.snap_colors,
+            watch_skin: self.watch_skin,
 
             skins,
             judges,
 
             key_bindings: self.key_bindings,
+            lane_cover_up_key: self.lane_cover_up_key,
+            lane_cover_down_key: self.lane_cover_down_key,
+            reload_skin_key: self.reload_skin_key,
+            modifiers: self.modifiers,
+            practice: self.practice,
+            hit_error: self.hit_error,
         })
     }
 }
@@ -104,9 +175,18 @@ impl From<GameConfig> for UnverifiedGameConfig {
             scroll_speed: game_config.scroll_speed,
             default_osu_skin_path: game_config.default_osu_skin_path,
             osu_hitsound_enable: game_config.osu_hitsound_enable,
+            osu_judgement_images: game_config.osu_judgement_images,
+            snap_colors: game_config.snap_colors,
+            watch_skin: game_config.watch_skin,
             skins: game_config.skins.into_iter().collect(),
             judges: game_config.judges.into_iter().collect(),
             key_bindings: game_config.key_bindings,
+            lane_cover_up_key: game_config.lane_cover_up_key,
+            lane_cover_down_key: game_config.lane_cover_down_key,
+            reload_skin_key: game_config.reload_skin_key,
+            modifiers: game_config.modifiers,
+            practice: game_config.practice,
+            hit_error: game_config.hit_error,
         }
     }
 }
@@ -116,6 +196,15 @@ impl GameConfig {
     pub fn current_skin(&self) -> &(String, SkinEntry) {
         &self.skins[self.current_skin_index]
     }
+    /// An index into the `skins` field
+    pub fn current_skin_index(&self) -> usize {
+        self.current_skin_index
+    }
+    /// Panics if `index` isn't an index into the `skins` field
+    pub fn set_current_skin_index(&mut self, index: usize) {
+        assert!(index < self.skins.len(), "Skin index {} out of bounds", index);
+        self.current_skin_index = index;
+    }
     /// The string is the name of the judge
     pub fn current_judge(&self) -> &(String, Judge) {
         &self.judges[self.current_judge_index]
@@ -128,11 +217,230 @@ pub struct Judge {
     pub windows: Vec<[f64; 2]>,
 }
 
+/// Gameplay modifiers that change the chart or how it's played.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Modifiers {
+    pub note_conversion: NoteConversion,
+
+    /// How fast the chart is played, from 0.5 to 2.0.
+    pub rate: f64,
+
+    /// Time stretch the audio when `rate` isn't 1.0 instead of resampling it, so the pitch
+    /// doesn't change.
+    pub preserve_pitch: bool,
+
+    pub visibility: Visibility,
+
+    /// Which end of the lanes the lane cover is on
+    pub lane_cover: LaneCover,
+
+    /// How much of the lanes the lane cover hides, from 0.0 to 1.0. Can be changed during play.
+    pub lane_cover_size: f64,
+
+    pub gauge: GaugeType,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Modifiers {
+            note_conversion: NoteConversion::None,
+            rate: 1.0,
+            preserve_pitch: false,
+            visibility: Visibility::Normal,
+            lane_cover: LaneCover::None,
+            lane_cover_size: 0.25,
+            gauge: GaugeType::Normal,
+        }
+    }
+}
+
+/// Rewrites the note types of a chart. See `chart::modifier`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteConversion {
+    /// Leave the notes alone
+    None,
+    /// Every long note becomes a regular note
+    NoLongNotes,
+    /// Every note is held until just before the next note in its column
+    FullLongNotes,
+    /// The gaps between notes become long notes, and the long notes become gaps
+    Inverse,
+}
+
+impl Default for NoteConversion {
+    fn default() -> Self {
+        NoteConversion::None
+    }
+}
+
+/// Changes which part of the lanes notes are visible in.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Normal,
+    /// Notes fade out before they reach the judgement line
+    Hidden,
+    /// Notes only appear once they're partway down the lanes
+    Sudden,
+    /// Like `Sudden`, but the notes fade in instead of popping in
+    FadeIn,
+}
+
+/// Decides how the health gauge behaves
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GaugeType {
+    /// Has to be above a threshold at the end of the chart
+    Normal,
+    /// Fails as soon as it's empty
+    Hard,
+    /// Can't fail
+    NoFail,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LaneCover {
+    None,
+    Top,
+    Bottom,
+}
+
+/// Settings for playing and looping only part of a chart.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Practice {
+    pub enabled: bool,
+    pub start: PracticePosition,
+    /// `None` means the end of the chart
+    pub end: Option<PracticePosition>,
+    /// How many seconds of music to play before the section starts
+    pub lead_in: f64,
+    /// Go back to the start of the section once it ends
+    pub looping: bool,
+}
+
+impl Default for Practice {
+    fn default() -> Self {
+        Practice {
+            enabled: false,
+            start: PracticePosition::Time(0.0),
+            end: None,
+            lead_in: 2.0,
+            looping: true,
+        }
+    }
+}
+
+/// A position in a chart, either in seconds or as a measure number starting from 1.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PracticePosition {
+    Time(f64),
+    Measure(u32),
+}
+
+/// Parses either a number of seconds like `"61.5"`, or a measure like `"m32"`.
+impl str::FromStr for PracticePosition {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let s = s.trim();
+        if s.starts_with('m') {
+            s[1..].parse().map(PracticePosition::Measure).map_err(|_| ())
+        } else {
+            match s.parse::<f64>() {
+                Ok(t) if t.is_finite() => Ok(PracticePosition::Time(t)),
+                _ => Err(()),
+            }
+        }
+    }
+}
+
+impl fmt::Display for PracticePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PracticePosition::Time(t) => write!(f, "{}", t),
+            PracticePosition::Measure(m) => write!(f, "m{}", m),
+        }
+    }
+}
+
+/// Settings for showing how early or late each hit was, for tuning the offset.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct HitError {
+    /// Show a bar under the lanes with a tick for every hit, early hits on the left
+    pub bar: bool,
+    /// How long each tick stays on the bar, in seconds
+    pub fade_time: f64,
+    /// How many of the latest hits the bar's average marker is taken over
+    pub average_count: usize,
+    /// Show FAST or SLOW when a hit is outside the best judgement window
+    pub fast_slow: bool,
+}
+
+impl Default for HitError {
+    fn default() -> Self {
+        HitError {
+            bar: false,
+            fade_time: 3.0,
+            average_count: 20,
+            fast_slow: false,
+        }
+    }
+}
+
+/// One of the judgement images in an osu skin, named after the `mania-hit*` files
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OsuHitImage {
+    Hit0,
+    Hit50,
+    Hit100,
+    Hit200,
+    Hit300,
+    Hit300g,
+}
+
+/// Which osu hit image is used for each of remani's judgements
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct OsuJudgementImages {
+    pub perfect: OsuHitImage,
+    pub good: OsuHitImage,
+    pub bad: OsuHitImage,
+    pub miss: OsuHitImage,
+}
+
+impl Default for OsuJudgementImages {
+    fn default() -> Self {
+        OsuJudgementImages {
+            perfect: OsuHitImage::Hit300g,
+            good: OsuHitImage::Hit200,
+            bad: OsuHitImage::Hit50,
+            miss: OsuHitImage::Hit0,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "path", rename_all = "lowercase")]
 pub enum SkinEntry {
     Osu(path::PathBuf),
     O2Jam(path::PathBuf),
+    /// A directory with a skin.toml
+    Native(path::PathBuf),
+}
+
+impl SkinEntry {
+    /// The skin's directory
+    pub fn path(&self) -> &path::Path {
+        match self {
+            SkinEntry::Osu(p) | SkinEntry::O2Jam(p) | SkinEntry::Native(p) => p,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -142,9 +450,60 @@ pub struct GeneralConfig {
     #[serde(with = "serde_buffer_size")]
     pub audio_buffer_size: cpal::BufferSize,
 
+    #[serde(default)]
+    pub volume: Volume,
+
+    #[serde(default)]
+    pub polyphony: Polyphony,
+
+    /// Used for audio that doesn't match the output device's sample rate
+    #[serde(default)]
+    pub resample_quality: audio::ResampleQuality,
+
     pub chart_path: Vec<ChartPath>,
 }
 
+/// Volume levels for each of the audio thread's buses, where 0.0 is silent and 1.0 is unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Volume {
+    pub master: f32,
+    pub music: f32,
+    pub keysound: f32,
+    pub autoplay: f32,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume {
+            master: 0.7,
+            music: 1.0,
+            keysound: 1.0,
+            autoplay: 1.0,
+        }
+    }
+}
+
+/// Limits on how many sound effects can play at the same time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Polyphony {
+    pub max_voices: usize,
+    pub stealing: audio::VoiceStealing,
+    /// Whether playing a keysound cuts off the previous instance of the same sound, like O2Jam
+    pub choke_keysounds: bool,
+}
+
+impl Default for Polyphony {
+    fn default() -> Self {
+        Polyphony {
+            max_voices: 64,
+            stealing: audio::VoiceStealing::Oldest,
+            choke_keysounds: true,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "path", rename_all = "lowercase")]
 pub enum ChartPath {
@@ -282,7 +641,7 @@ pub fn write_config_to_path<P: AsRef<path::Path>>(config: Config, path: P) -> Re
 }
 
 /// Create the default configuration
-fn default_config() -> Config {
+pub(crate) fn default_config() -> Config {
     use piston::input::{keyboard::Key, Button::Keyboard};
 
     let mut skin_map = BTreeMap::new();
@@ -308,6 +667,9 @@ fn default_config() -> Config {
         general: GeneralConfig {
             resolution: [800, 600],
             audio_buffer_size: cpal::BufferSize::Fixed(1024),
+            volume: Volume::default(),
+            polyphony: Polyphony::default(),
+            resample_quality: audio::ResampleQuality::default(),
             chart_path: vec![], // TODO use directories crate
         },
         game: UnverifiedGameConfig {
@@ -326,10 +688,19 @@ fn default_config() -> Config {
             current_skin: "test".into(),
             current_judge: "easy".into(),
             osu_hitsound_enable: false,
+            osu_judgement_images: OsuJudgementImages::default(),
+            snap_colors: false,
+            watch_skin: false,
             skins: skin_map,
             judges: judge_map,
             scroll_speed: 1.7,
             offset: -0.1,
+            lane_cover_up_key: default_lane_cover_up_key(),
+            lane_cover_down_key: default_lane_cover_down_key(),
+            reload_skin_key: default_reload_skin_key(),
+            modifiers: Modifiers::default(),
+            practice: Practice::default(),
+            hit_error: HitError::default(),
         }.verify().unwrap(),
     }
 }
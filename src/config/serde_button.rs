@@ -0,0 +1,18 @@
+use piston::input;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::serde_key_bindings::InputButton;
+
+pub fn serialize<S>(button: &input::Button, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    InputButton::from(*button).serialize(s)
+}
+
+pub fn deserialize<'de, D>(d: D) -> Result<input::Button, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    InputButton::deserialize(d).map(Into::into)
+}
@@ -8,7 +8,7 @@ use std::fmt;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "lowercase")]
-enum InputButton {
+pub(super) enum InputButton {
     Keyboard {
         value: u32,
     },
@@ -41,6 +41,17 @@ impl From<InputButton> for input::Button {
     }
 }
 
+impl From<input::Button> for InputButton {
+    fn from(button: input::Button) -> Self {
+        match button {
+            input::Button::Keyboard(key) => InputButton::Keyboard { value: key.into() },
+            input::Button::Mouse(button) => InputButton::Mouse { value: button.into() },
+            input::Button::Controller(c) => InputButton::Controller { id: c.id, button: c.button },
+            input::Button::Hat(hat) => InputButton::Hat { id: hat.id, state: hat.state, which: hat.which },
+        }
+    }
+}
+
 pub fn serialize<S>(buttons: &[input::Button; 7], s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
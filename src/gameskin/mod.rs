@@ -1,13 +1,22 @@
 //! A module for reading skins.
 
-use graphics::{math, Graphics};
+use graphics::{math, types, Graphics, ImageSize};
 use texture::{CreateTexture, TextureOp};
 
-use std::{error, fmt, io, path::PathBuf};
+use std::{error, fmt, io, path::PathBuf, rc::Rc, time};
 
-use crate::{config, judgement::Judgement};
+use crate::{
+    chart::{self, Snap},
+    config,
+    judgement::Judgement,
+};
 
+mod native;
+mod o2jam_skin;
 mod osu_skin;
+mod watcher;
+
+pub use self::watcher::SkinWatcher;
 
 /// The error type from parsing
 #[derive(Debug)]
@@ -61,14 +70,52 @@ impl error::Error for ParseError {
     }
 }
 
-/// Parse from a directory specified by the path.
-///
-/// For now, the osu parser is assumed (TODO).
+/// Where a skin element's image was found
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureSource {
+    /// In the skin's own files
+    Skin(PathBuf),
+    /// In the default osu skin, since the skin doesn't have it
+    Default(PathBuf),
+    /// Nowhere. Skins can do without some elements, like long note tails.
+    Missing,
+}
+
+/// What a skin parser looked at while loading a skin, for checking skins without playing them
+#[derive(Debug, Default)]
+pub struct SkinReport {
+    /// Every image the skin looked for, as (element name, where it was found). Animations have
+    /// an entry for every frame.
+    pub textures: Vec<(String, TextureSource)>,
+    /// Config lines that were ignored since the key isn't known, as (file, line number, key)
+    pub unrecognised_keys: Vec<(PathBuf, usize, String)>,
+    /// Config lines that were ignored since the value couldn't be parsed, as (file, line number,
+    /// reason)
+    pub malformed_values: Vec<(PathBuf, usize, String)>,
+}
+
+/// Parse from a directory specified by the path, using the parser for the skin's type.
 pub fn from_path<G, F>(
     factory: &mut F,
     skin_entry: &config::SkinEntry,
     config: &config::Config,
 ) -> Result<Box<dyn GameSkin<G>>, ParseError>
+where
+    G: Graphics + 'static,
+    G::Texture: CreateTexture<F>,
+    <G::Texture as TextureOp<F>>::Error: ToString,
+{
+    from_path_with_report(factory, skin_entry, config, &mut SkinReport::default())
+}
+
+/// Same as `from_path`, but also writes down what was loaded and what was ignored into `report`.
+/// The report is filled in as far as loading got, even if it fails.
+pub fn from_path_with_report<G, F>(
+    factory: &mut F,
+    skin_entry: &config::SkinEntry,
+    config: &config::Config,
+    report: &mut SkinReport,
+) -> Result<Box<dyn GameSkin<G>>, ParseError>
 where
     G: Graphics + 'static,
     G::Texture: CreateTexture<F>,
@@ -76,11 +123,39 @@ where
 {
     match skin_entry {
         config::SkinEntry::Osu(p) =>
-            osu_skin::from_path(factory, p, &config.game.default_osu_skin_path),
-        config::SkinEntry::O2Jam(_p) => unimplemented!(),
+            osu_skin::from_path(
+                factory,
+                p,
+                &config.game.default_osu_skin_path,
+                config.game.osu_judgement_images,
+                report,
+            ),
+        config::SkinEntry::O2Jam(p) => o2jam_skin::from_path(factory, p, report),
+        config::SkinEntry::Native(p) => native::from_path(factory, p, config, report),
     }
 }
 
+/// Every directory that loading `skin_entry` reads from: the skin's own directory, then the
+/// directories of the skins it falls back to
+pub fn skin_dirs(skin_entry: &config::SkinEntry, config: &config::Config) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let mut entry = Some(skin_entry.clone());
+    // Stops at skins that fall back to each other, which fail to load anyway
+    while let Some(e) = entry.take().filter(|e| !dirs.iter().any(|d| d == e.path())) {
+        dirs.push(e.path().to_owned());
+        entry = match e {
+            config::SkinEntry::Osu(_) => Some(config::SkinEntry::Osu(config.game.default_osu_skin_path.clone())),
+            config::SkinEntry::O2Jam(_) => None,
+            config::SkinEntry::Native(p) => native::fallback(&p, config),
+        };
+    }
+    dirs
+}
+
+/// A note to draw: (column index, start pos, end pos, snap (None if notes shouldn't be coloured by
+/// snap), alpha (below 1.0 while a visibility modifier is fading the note))
+pub type NotePosition = (usize, f64, Option<f64>, Option<Snap>, f32);
+
 /// A skin. Should be returned by skin parsers.
 pub trait GameSkin<G: Graphics> {
     fn draw_play_scene(
@@ -89,10 +164,61 @@ pub trait GameSkin<G: Graphics> {
         graphics: &mut G,
         stage_height: f64,
         keys_down: &[bool; 7],
-        // column index, start pos, end pos
-        notes: &[(usize, f64, Option<f64>)],
+        notes: &[NotePosition],
+        // pos, whether the line starts a measure
+        bar_lines: &[(f64, bool)],
     );
+    /// The area the notes scroll through as `[x, y, w, h]`. A note at position 0.0 is at the
+    /// bottom of this area, and a note at position 1.0 is at the top. Used to draw things like
+    /// lane covers on top of the skin.
+    fn lane_area(&self, stage_height: f64) -> [f64; 4];
     fn draw_judgement(&mut self, column: usize, judgement: Judgement);
+    /// Draw the judgement from the last `draw_judgement` call while it's still showing. Skins call
+    /// this from `draw_play_scene`, and skins falling back to this one for judgements call it
+    /// separately. Nothing is drawn by default.
+    fn draw_last_judgement(&mut self, _transform: math::Matrix2d, _g: &mut G, _stage_height: f64) {}
+
+    /// Draw measure and beat lines across the lanes, at positions like the notes'. Skins call this
+    /// from `draw_play_scene`, so the lines go under the notes.
+    ///
+    /// By default, this draws plain lines across `lane_area`, fainter for beats than for measures.
+    fn draw_bar_lines(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64, bar_lines: &[(f64, bool)]) {
+        let lane_area = self.lane_area(stage_height);
+        for &(pos, measure) in bar_lines {
+            draw_default_bar_line(lane_area, transform, g, stage_height, pos, measure);
+        }
+    }
+
+    /// Draw the health gauge. `value` goes from 0.0 (empty) to 1.0 (full).
+    ///
+    /// By default, this draws a plain bar to the right of the lanes.
+    fn draw_gauge(
+        &mut self,
+        transform: math::Matrix2d,
+        g: &mut G,
+        stage_height: f64,
+        value: f64,
+    ) {
+        draw_default_gauge(self.lane_area(stage_height), transform, g, stage_height, value);
+    }
+
+    /// Draw how many notes in a row have been hit. Nothing is drawn by default.
+    fn draw_combo(&mut self, _transform: math::Matrix2d, _g: &mut G, _stage_height: f64, _combo: u32) {}
+
+    /// Draw the score, which goes from 0 to 1,000,000. `window_size` is `[width, height]`, the
+    /// stage is as tall as the window. Nothing is drawn by default.
+    fn draw_score(&mut self, _transform: math::Matrix2d, _g: &mut G, _window_size: [f64; 2], _score: u32) {}
+
+    /// Draw the accuracy, from 0.0 to 1.0. See `draw_score`. Nothing is drawn by default.
+    fn draw_accuracy(
+        &mut self,
+        _transform: math::Matrix2d,
+        _g: &mut G,
+        _window_size: [f64; 2],
+        _accuracy: f64,
+    ) {
+    }
+
     fn key_down(&mut self, column: usize);
     fn key_up(&mut self, column: usize);
     fn single_note_hit_anim(&mut self, _column: usize) {}
@@ -101,3 +227,117 @@ pub trait GameSkin<G: Graphics> {
     }
     fn long_note_hit_anim_stop(&mut self, _column: usize) {}
 }
+
+/// Images that are shown one after another
+struct Animation<T> {
+    frames: Rc<[Rc<T>]>,
+    fps: f64,
+}
+
+impl<T: ImageSize> Animation<T> {
+    fn new(frames: Rc<[Rc<T>]>, fps: f64) -> Self {
+        Animation { frames, fps }
+    }
+
+    fn first(&self) -> &T {
+        &self.frames[0]
+    }
+
+    fn frame_index(&self, elapsed: time::Duration) -> usize {
+        (elapsed.as_secs_f64() * self.fps) as usize
+    }
+
+    /// The frame to draw `elapsed` after the animation started, looping forever
+    fn looped(&self, elapsed: time::Duration) -> &T {
+        &self.frames[self.frame_index(elapsed) % self.frames.len()]
+    }
+
+    /// The frame to draw `elapsed` after the animation started, staying on the last frame
+    fn once(&self, elapsed: time::Duration) -> &T {
+        &self.frames[self.frame_index(elapsed).min(self.frames.len() - 1)]
+    }
+
+    /// Whether every frame has been shown `elapsed` after the animation started
+    fn finished(&self, elapsed: time::Duration) -> bool {
+        self.frame_index(elapsed) >= self.frames.len()
+    }
+
+    fn height(&self) -> f64 {
+        self.first().get_height() as f64
+    }
+}
+
+/// Note colours for each beat division, in the same order as `chart::SNAP_DIVISIONS`, and then one
+/// for notes that aren't on any of them. Note images are multiplied by these, so they look best on
+/// white notes.
+pub type SnapPalette = [types::Color; chart::SNAP_DIVISIONS.len() + 1];
+
+/// Red 4ths, blue 8ths, purple 12ths, yellow 16ths, pink 24ths, orange 32nds, cyan 48ths, green
+/// 64ths and grey for the rest
+pub const DEFAULT_SNAP_PALETTE: SnapPalette = [
+    [1.0, 0.25, 0.25, 1.0],
+    [0.3, 0.45, 1.0, 1.0],
+    [0.7, 0.3, 1.0, 1.0],
+    [1.0, 0.9, 0.25, 1.0],
+    [1.0, 0.45, 0.8, 1.0],
+    [1.0, 0.6, 0.2, 1.0],
+    [0.3, 0.9, 1.0, 1.0],
+    [0.35, 1.0, 0.35, 1.0],
+    [0.6, 0.6, 0.6, 1.0],
+];
+
+/// The colour to tint a note with, or `None` to draw it as it is
+fn note_color(palette: &SnapPalette, snap: Option<Snap>, alpha: f32) -> Option<types::Color> {
+    match snap {
+        Some(snap) => {
+            let [r, g, b, a] = palette[snap.index()];
+            Some([r, g, b, a * alpha])
+        }
+        None => fade_color(alpha),
+    }
+}
+
+/// The colour to tint the untinted parts of a note with, like long note bodies, so they fade with
+/// the rest of it
+fn fade_color(alpha: f32) -> Option<types::Color> {
+    if alpha < 1.0 {
+        Some([1.0, 1.0, 1.0, alpha])
+    } else {
+        None
+    }
+}
+
+/// Draw a plain line across `lane_area` at `pos`, for skins without their own bar line images
+fn draw_default_bar_line<G: Graphics>(
+    lane_area: [f64; 4],
+    transform: math::Matrix2d,
+    g: &mut G,
+    stage_height: f64,
+    pos: f64,
+    measure: bool,
+) {
+    let [x, y, w, h] = lane_area;
+    let line_h = stage_height / 480.0;
+    let alpha = if measure { 0.6 } else { 0.2 };
+    graphics::rectangle([1.0, 1.0, 1.0, alpha], [x, y + h * (1.0 - pos) - line_h / 2.0, w, line_h], transform, g);
+}
+
+/// Draw a plain health bar to the right of `lane_area`, for skins without their own
+fn draw_default_gauge<G: Graphics>(
+    lane_area: [f64; 4],
+    transform: math::Matrix2d,
+    g: &mut G,
+    stage_height: f64,
+    value: f64,
+) {
+    let [x, y, w, h] = lane_area;
+    let bar_w = stage_height / 48.0;
+    let bar_x = x + w + bar_w;
+    graphics::rectangle([0.2, 0.2, 0.2, 1.0], [bar_x, y, bar_w, h], transform, g);
+    graphics::rectangle(
+        [1.0 - value as f32, value as f32, 0.2, 1.0],
+        [bar_x, y + h * (1.0 - value), bar_w, h * value],
+        transform,
+        g,
+    );
+}
@@ -0,0 +1,885 @@
+//! Remani's own skin format: a `skin.toml` manifest next to the images it uses
+//!
+//! Positions and sizes in the manifest are in pixels on a screen `screen_height` pixels tall
+//! (480 by default), and are relative to the top left corner of the stage. The stage is the
+//! columns side by side, from the top of the screen down to `hit_position`. Images are drawn at
+//! their own size in those pixels, except for the ones stretched across a column or the stage.
+//!
+//! Anything that takes an image can also take an animation:
+//!
+//! ```toml
+//! note = { frames = ["note-0.png", "note-1.png"], fps = 15 }
+//! ```
+//!
+//! A skin needs a layout for every key mode it supports, e.g.
+//!
+//! ```toml
+//! fallback = "o2jamu" # optional, a skin from the config
+//!
+//! [keymodes.7k]
+//! x = 136
+//! hit_position = 402
+//! background = "stage.png" # optional, stretched over the stage
+//! judge_line = "line.png" # optional, stretched across the stage and centered on hit_position
+//! measure_line = "measure.png" # optional, like judge_line but for measure lines, plain by default
+//! beat_line = "beat.png" # optional, the same for beat lines
+//!
+//! [[keymodes.7k.columns]] # one for each key
+//! width = 30
+//! note = "note1.png"
+//! long_note_body = "note1-body.png"
+//! # optional: long_note_head and long_note_tail (which default to note), key and key_down
+//! # (drawn under hit_position) and flash (drawn over the column while its key is held)
+//!
+//! [keymodes.7k.judgement] # optional
+//! position = [105, 250] # where the middle of the image goes
+//! perfect = "perfect.png"
+//! good = "good.png"
+//! bad = "bad.png"
+//! miss = "miss.png"
+//!
+//! [keymodes.7k.combo] # optional
+//! position = [105, 150]
+//! digits = ["0.png", "1.png", "2.png", "3.png", "4.png", "5.png", "6.png", "7.png", "8.png", "9.png"]
+//!
+//! [keymodes.7k.snap_colors] # optional, for tinting notes by beat division when that's turned on
+//! 4 = [1.0, 0.25, 0.25, 1.0] # 4, 8, 12, 16, 24, 32, 48, 64 or other
+//! ```
+//!
+//! Whatever the skin leaves out is drawn by the fallback skin: the whole play scene when there's
+//! no layout for the key mode, the combo when there's no `combo` section, and always the score,
+//! accuracy and health gauge.
+
+use graphics::{draw_state::DrawState, image::Image, math, types, Graphics, Transformed};
+use serde_derive::Deserialize;
+use texture::{CreateTexture, ImageSize, TextureOp, TextureSettings};
+
+use std::{collections::BTreeMap, collections::HashMap, fs, path, rc::Rc, time};
+
+use crate::{
+    chart::SNAP_DIVISIONS,
+    config,
+    judgement::Judgement,
+};
+use super::{
+    osu_skin::texture_from_path, fade_color, note_color, Animation, GameSkin, NotePosition, ParseError, SkinReport,
+    SnapPalette, TextureSource, DEFAULT_SNAP_PALETTE,
+};
+
+/// The manifest's file name
+const MANIFEST_NAME: &str = "skin.toml";
+
+/// Remani is always played with 7 keys for now
+const KEYMODE: &str = "7k";
+
+fn default_screen_height() -> f64 {
+    480.0
+}
+
+fn default_fps() -> f64 {
+    30.0
+}
+
+fn default_judgement_duration() -> f64 {
+    0.2
+}
+
+/// False for NaN too
+fn is_positive(n: f64) -> bool {
+    n > 0.0
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum AnimationManifest {
+    Image(String),
+    Frames {
+        frames: Vec<String>,
+        #[serde(default = "default_fps")]
+        fps: f64,
+    },
+}
+
+impl AnimationManifest {
+    fn validate(&self, name: &str) -> Result<(), String> {
+        match self {
+            AnimationManifest::Image(_) => Ok(()),
+            AnimationManifest::Frames { frames, .. } if frames.is_empty() => Err(format!("{} has no frames", name)),
+            AnimationManifest::Frames { fps, .. } if !is_positive(*fps) => Err(format!("{} has an fps of {}", name, fps)),
+            AnimationManifest::Frames { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default = "default_screen_height")]
+    screen_height: f64,
+
+    /// The name of a skin in the config that draws whatever this one leaves out
+    fallback: Option<String>,
+
+    #[serde(default)]
+    keymodes: BTreeMap<String, LayoutManifest>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct LayoutManifest {
+    /// The left edge of the stage
+    x: f64,
+    hit_position: f64,
+    #[serde(default)]
+    column_spacing: f64,
+    columns: Vec<ColumnManifest>,
+    background: Option<AnimationManifest>,
+    judge_line: Option<AnimationManifest>,
+    measure_line: Option<AnimationManifest>,
+    beat_line: Option<AnimationManifest>,
+    judgement: Option<JudgementManifest>,
+    combo: Option<ComboManifest>,
+    /// Keyed by beat division, or "other"
+    #[serde(default)]
+    snap_colors: BTreeMap<String, types::Color>,
+}
+
+/// Where a `snap_colors` key goes in a `SnapPalette`
+fn snap_palette_index(key: &str) -> Option<usize> {
+    if key == "other" {
+        Some(SNAP_DIVISIONS.len())
+    } else {
+        key.parse().ok().and_then(|d| SNAP_DIVISIONS.iter().position(|&s| s == d))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ColumnManifest {
+    width: f64,
+    note: AnimationManifest,
+    long_note_head: Option<AnimationManifest>,
+    long_note_body: AnimationManifest,
+    long_note_tail: Option<AnimationManifest>,
+    key: Option<AnimationManifest>,
+    key_down: Option<AnimationManifest>,
+    flash: Option<AnimationManifest>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JudgementManifest {
+    position: [f64; 2],
+    /// Seconds
+    #[serde(default = "default_judgement_duration")]
+    duration: f64,
+    perfect: AnimationManifest,
+    good: AnimationManifest,
+    bad: AnimationManifest,
+    miss: AnimationManifest,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ComboManifest {
+    position: [f64; 2],
+    digits: Vec<String>,
+    /// Space between digits, negative to make them overlap
+    #[serde(default)]
+    spacing: f64,
+}
+
+impl Manifest {
+    /// Check everything that can be checked without loading the images
+    fn validate(&self) -> Result<(), String> {
+        if !is_positive(self.screen_height) {
+            return Err(format!("screen_height is {}", self.screen_height));
+        }
+        if !self.keymodes.contains_key(KEYMODE) && self.fallback.is_none() {
+            return Err(format!("There's no layout for {} and no fallback skin", KEYMODE));
+        }
+
+        for (keymode, layout) in &self.keymodes {
+            let key_count = keymode
+                .strip_suffix('k')
+                .and_then(|n| n.parse::<usize>().ok())
+                .ok_or_else(|| format!("keymodes.{} isn't a key mode like 7k", keymode))?;
+            if layout.columns.len() != key_count {
+                return Err(format!("keymodes.{} has {} columns", keymode, layout.columns.len()));
+            }
+
+            let section = |name: &str| format!("keymodes.{}.{}", keymode, name);
+            for (n, column) in layout.columns.iter().enumerate() {
+                if !is_positive(column.width) {
+                    return Err(format!("{} has a width of {}", section(&format!("columns[{}]", n)), column.width));
+                }
+                let animations = [
+                    ("note", Some(&column.note)),
+                    ("long_note_head", column.long_note_head.as_ref()),
+                    ("long_note_body", Some(&column.long_note_body)),
+                    ("long_note_tail", column.long_note_tail.as_ref()),
+                    ("key", column.key.as_ref()),
+                    ("key_down", column.key_down.as_ref()),
+                    ("flash", column.flash.as_ref()),
+                ];
+                for &(name, animation) in &animations {
+                    if let Some(animation) = animation {
+                        animation.validate(&section(&format!("columns[{}].{}", n, name)))?;
+                    }
+                }
+            }
+            let animations = [
+                ("background", &layout.background),
+                ("judge_line", &layout.judge_line),
+                ("measure_line", &layout.measure_line),
+                ("beat_line", &layout.beat_line),
+            ];
+            for (name, animation) in &animations {
+                if let Some(animation) = animation {
+                    animation.validate(&section(name))?;
+                }
+            }
+            if let Some(judgement) = &layout.judgement {
+                judgement.perfect.validate(&section("judgement.perfect"))?;
+                judgement.good.validate(&section("judgement.good"))?;
+                judgement.bad.validate(&section("judgement.bad"))?;
+                judgement.miss.validate(&section("judgement.miss"))?;
+            }
+            if let Some(combo) = &layout.combo {
+                if combo.digits.len() != 10 {
+                    return Err(format!("{} has {} images instead of 10", section("combo.digits"), combo.digits.len()));
+                }
+            }
+            if let Some(key) = layout.snap_colors.keys().find(|k| snap_palette_index(k).is_none()) {
+                return Err(format!("{} isn't a beat division", section(&format!("snap_colors.{}", key))));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Column<T> {
+    /// The left edge, relative to the stage
+    x: f64,
+    width: f64,
+    note: Animation<T>,
+    long_note_head: Animation<T>,
+    long_note_body: Animation<T>,
+    long_note_tail: Animation<T>,
+    key: Option<Animation<T>>,
+    key_down: Option<Animation<T>>,
+    flash: Option<Animation<T>>,
+}
+
+struct JudgementLayout<T> {
+    position: [f64; 2],
+    duration: time::Duration,
+    perfect: Animation<T>,
+    good: Animation<T>,
+    bad: Animation<T>,
+    miss: Animation<T>,
+}
+
+struct ComboLayout<T> {
+    position: [f64; 2],
+    digits: Vec<Rc<T>>,
+    spacing: f64,
+}
+
+struct Layout<T> {
+    x: f64,
+    /// The width of every column and the spacing between them
+    width: f64,
+    hit_position: f64,
+    columns: Vec<Column<T>>,
+    background: Option<Animation<T>>,
+    judge_line: Option<Animation<T>>,
+    measure_line: Option<Animation<T>>,
+    beat_line: Option<Animation<T>>,
+    judgement: Option<JudgementLayout<T>>,
+    combo: Option<ComboLayout<T>>,
+    snap_palette: SnapPalette,
+}
+
+struct NativeSkin<G: Graphics> {
+    screen_height: f64,
+
+    /// `None` if the skin doesn't have a layout for the key mode, in which case the fallback
+    /// draws the play scene
+    layout: Option<Layout<G::Texture>>,
+    fallback: Option<Box<dyn GameSkin<G>>>,
+
+    /// judgement, time of first frame
+    judgement: Option<(Judgement, time::Instant)>,
+
+    /// When each key was pressed, for animating the key images and flashes
+    keys_down_time: [time::Instant; 7],
+
+    /// When the skin was loaded, used for animations that loop forever
+    created: time::Instant,
+}
+
+/// Draw `texture` stretched over `rect`, which is in skin pixels
+fn draw_image<G: Graphics>(
+    texture: &G::Texture,
+    rect: [f64; 4],
+    scale: f64,
+    transform: math::Matrix2d,
+    g: &mut G,
+) {
+    draw_tinted_image(texture, rect, None, scale, transform, g);
+}
+
+/// Same as `draw_image`, but multiplied by `color`
+fn draw_tinted_image<G: Graphics>(
+    texture: &G::Texture,
+    rect: [f64; 4],
+    color: Option<types::Color>,
+    scale: f64,
+    transform: math::Matrix2d,
+    g: &mut G,
+) {
+    let [x, y, w, h] = rect;
+    Image::new()
+        .maybe_color(color)
+        .rect([x * scale, y * scale, w * scale, h * scale])
+        .draw(texture, &DrawState::default(), transform, g);
+}
+
+/// Draw `texture` at its own size with its middle at `center`
+fn draw_centered<G: Graphics>(
+    texture: &G::Texture,
+    center: [f64; 2],
+    scale: f64,
+    transform: math::Matrix2d,
+    g: &mut G,
+) {
+    let (w, h) = (texture.get_width() as f64, texture.get_height() as f64);
+    draw_image(texture, [center[0] - w / 2.0, center[1] - h / 2.0, w, h], scale, transform, g);
+}
+
+impl<G: Graphics> NativeSkin<G> {
+    /// `transform` is at the top left corner of the stage
+    fn draw_layout_bar_lines(
+        &self,
+        layout: &Layout<G::Texture>,
+        transform: math::Matrix2d,
+        g: &mut G,
+        scale: f64,
+        bar_lines: &[(f64, bool)],
+    ) {
+        let now = self.created.elapsed();
+        let hit_p = layout.hit_position;
+        for &(pos, measure) in bar_lines {
+            let line = if measure { &layout.measure_line } else { &layout.beat_line };
+            match line {
+                Some(line) => {
+                    let h = line.height();
+                    let rect = [0.0, hit_p * (1.0 - pos) - h / 2.0, layout.width, h];
+                    draw_image(line.looped(now), rect, scale, transform, g);
+                }
+                None => {
+                    let lane_area = [0.0, 0.0, layout.width * scale, hit_p * scale];
+                    super::draw_default_bar_line(lane_area, transform, g, self.screen_height * scale, pos, measure);
+                }
+            }
+        }
+    }
+
+    /// Only draws anything if there's a layout
+    fn draw_layout(
+        &self,
+        transform: math::Matrix2d,
+        g: &mut G,
+        stage_height: f64,
+        keys_down: &[bool; 7],
+        notes: &[NotePosition],
+        bar_lines: &[(f64, bool)],
+    ) {
+        let layout = match &self.layout {
+            Some(layout) => layout,
+            None => return,
+        };
+        let scale = stage_height / self.screen_height;
+        let transform = transform.trans(layout.x * scale, 0.0);
+        let now = self.created.elapsed();
+        let hit_p = layout.hit_position;
+        // The bottom edge of a note at `pos`
+        let note_y = |pos: f64| hit_p * (1.0 - pos);
+
+        if let Some(background) = &layout.background {
+            draw_image(background.looped(now), [0.0, 0.0, layout.width, hit_p], scale, transform, g);
+        }
+        self.draw_layout_bar_lines(layout, transform, g, scale, bar_lines);
+
+        for (n, (column, &down)) in layout.columns.iter().zip(keys_down).enumerate() {
+            let key = if down { column.key_down.as_ref().or(column.key.as_ref()) } else { column.key.as_ref() };
+            if let Some(key) = key {
+                let key = key.once(self.keys_down_time[n].elapsed());
+                let rect = [column.x, hit_p, column.width, key.get_height() as f64];
+                draw_image(key, rect, scale, transform, g);
+            }
+        }
+        for (n, column) in layout.columns.iter().enumerate().filter(|&(n, _)| keys_down[n]) {
+            if let Some(flash) = &column.flash {
+                let flash = flash.once(self.keys_down_time[n].elapsed());
+                let h = flash.get_height() as f64;
+                draw_image(flash, [column.x, hit_p - h, column.width, h], scale, transform, g);
+            }
+        }
+
+        if let Some(judge_line) = &layout.judge_line {
+            let h = judge_line.height();
+            draw_image(judge_line.looped(now), [0.0, hit_p - h / 2.0, layout.width, h], scale, transform, g);
+        }
+
+        for &(n, pos, end_pos, snap, alpha) in notes {
+            let column = &layout.columns[n];
+            let color = note_color(&layout.snap_palette, snap, alpha);
+            let draw_note = |animation: &Animation<G::Texture>, pos: f64, g: &mut G| {
+                let h = animation.height();
+                let rect = [column.x, note_y(pos) - h, column.width, h];
+                draw_tinted_image(animation.looped(now), rect, color, scale, transform, g);
+            };
+            if let Some(end_pos) = end_pos {
+                // Held long notes stop at the judge line
+                let pos = pos.max(0.0);
+                let (bottom, top) = (note_y(pos), note_y(end_pos));
+                let body = column.long_note_body.looped(now);
+                let rect = [column.x, top, column.width, bottom - top];
+                draw_tinted_image(body, rect, fade_color(alpha), scale, transform, g);
+                draw_note(&column.long_note_head, pos, g);
+                draw_note(&column.long_note_tail, end_pos, g);
+            } else {
+                draw_note(&column.note, pos, g);
+            }
+        }
+    }
+}
+
+impl<G: Graphics> GameSkin<G> for NativeSkin<G> {
+    fn draw_play_scene(
+        &mut self,
+        transform: math::Matrix2d,
+        g: &mut G,
+        stage_height: f64,
+        keys_down: &[bool; 7],
+        notes: &[NotePosition],
+        bar_lines: &[(f64, bool)],
+    ) {
+        if self.layout.is_some() {
+            self.draw_layout(transform, g, stage_height, keys_down, notes, bar_lines);
+            self.draw_last_judgement(transform, g, stage_height);
+        } else if let Some(fallback) = &mut self.fallback {
+            fallback.draw_play_scene(transform, g, stage_height, keys_down, notes, bar_lines);
+        }
+    }
+
+    fn lane_area(&self, stage_height: f64) -> [f64; 4] {
+        match (&self.layout, &self.fallback) {
+            (Some(layout), _) => {
+                let scale = stage_height / self.screen_height;
+                [layout.x * scale, 0.0, layout.width * scale, layout.hit_position * scale]
+            }
+            (None, Some(fallback)) => fallback.lane_area(stage_height),
+            (None, None) => unreachable!("skins without a layout always have a fallback"),
+        }
+    }
+
+    fn draw_judgement(&mut self, column: usize, judgement: Judgement) {
+        self.judgement = Some((judgement, time::Instant::now()));
+        if self.layout.as_ref().and_then(|l| l.judgement.as_ref()).is_none() {
+            if let Some(fallback) = &mut self.fallback {
+                fallback.draw_judgement(column, judgement);
+            }
+        }
+    }
+
+    fn draw_last_judgement(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64) {
+        let judgement_layout = self.layout.as_ref().and_then(|l| l.judgement.as_ref().map(|j| (l.x, j)));
+        if let Some((stage_x, layout)) = judgement_layout {
+            let (judgement, time) = match self.judgement {
+                Some(judgement) => judgement,
+                None => return,
+            };
+            let elapsed = time.elapsed();
+            if elapsed <= layout.duration {
+                let animation = match judgement {
+                    Judgement::Perfect => &layout.perfect,
+                    Judgement::Good => &layout.good,
+                    Judgement::Bad => &layout.bad,
+                    Judgement::Miss => &layout.miss,
+                };
+                let scale = stage_height / self.screen_height;
+                let transform = transform.trans(stage_x * scale, 0.0);
+                draw_centered(animation.once(elapsed), layout.position, scale, transform, g);
+            }
+        } else if let Some(fallback) = &mut self.fallback {
+            fallback.draw_last_judgement(transform, g, stage_height);
+        }
+    }
+
+    fn draw_bar_lines(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64, bar_lines: &[(f64, bool)]) {
+        match (&self.layout, &mut self.fallback) {
+            (Some(layout), _) => {
+                let scale = stage_height / self.screen_height;
+                self.draw_layout_bar_lines(layout, transform.trans(layout.x * scale, 0.0), g, scale, bar_lines);
+            }
+            (None, Some(fallback)) => fallback.draw_bar_lines(transform, g, stage_height, bar_lines),
+            (None, None) => unreachable!("skins without a layout always have a fallback"),
+        }
+    }
+
+    fn draw_gauge(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64, value: f64) {
+        match &mut self.fallback {
+            Some(fallback) => fallback.draw_gauge(transform, g, stage_height, value),
+            None => super::draw_default_gauge(self.lane_area(stage_height), transform, g, stage_height, value),
+        }
+    }
+
+    fn draw_combo(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64, combo: u32) {
+        let combo_layout = self.layout.as_ref().and_then(|l| l.combo.as_ref().map(|c| (l.x, c)));
+        if let Some((stage_x, layout)) = combo_layout {
+            if combo == 0 {
+                return;
+            }
+            let scale = stage_height / self.screen_height;
+            let digits: Vec<&G::Texture> = combo
+                .to_string()
+                .bytes()
+                .map(|d| &*layout.digits[(d - b'0') as usize])
+                .collect();
+            let width = digits.iter().map(|d| d.get_width() as f64 + layout.spacing).sum::<f64>() - layout.spacing;
+            let mut x = stage_x + layout.position[0] - width / 2.0;
+            for digit in digits {
+                let (w, h) = (digit.get_width() as f64, digit.get_height() as f64);
+                draw_image(digit, [x, layout.position[1] - h / 2.0, w, h], scale, transform, g);
+                x += w + layout.spacing;
+            }
+        } else if let Some(fallback) = &mut self.fallback {
+            fallback.draw_combo(transform, g, stage_height, combo);
+        }
+    }
+
+    fn draw_score(&mut self, transform: math::Matrix2d, g: &mut G, window_size: [f64; 2], score: u32) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.draw_score(transform, g, window_size, score);
+        }
+    }
+
+    fn draw_accuracy(&mut self, transform: math::Matrix2d, g: &mut G, window_size: [f64; 2], accuracy: f64) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.draw_accuracy(transform, g, window_size, accuracy);
+        }
+    }
+
+    fn key_down(&mut self, column: usize) {
+        self.keys_down_time[column] = time::Instant::now();
+        if let Some(fallback) = &mut self.fallback {
+            fallback.key_down(column);
+        }
+    }
+
+    fn key_up(&mut self, column: usize) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.key_up(column);
+        }
+    }
+
+    fn single_note_hit_anim(&mut self, column: usize) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.single_note_hit_anim(column);
+        }
+    }
+
+    fn long_note_hit_anim_start(&mut self, column: usize) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.long_note_hit_anim_start(column);
+        }
+    }
+
+    fn long_note_hit_anim_stop(&mut self, column: usize) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.long_note_hit_anim_stop(column);
+        }
+    }
+}
+
+/// Loads the images a manifest refers to, loading each file only once
+struct Loader<'a, F, T> {
+    factory: &'a mut F,
+    report: &'a mut SkinReport,
+    dir: &'a path::Path,
+    texture_settings: TextureSettings,
+    cache: HashMap<String, Rc<T>>,
+}
+
+impl<'a, F, T> Loader<'a, F, T>
+where
+    T: CreateTexture<F>,
+    T::Error: ToString,
+{
+    fn image(&mut self, name: &str) -> Result<Rc<T>, ParseError> {
+        if let Some(texture) = self.cache.get(name) {
+            return Ok(Rc::clone(texture));
+        }
+        let path = self.dir.join(name);
+        self.report.textures.push((name.to_owned(), TextureSource::Skin(path.clone())));
+        let texture = Rc::new(texture_from_path(self.factory, path, &self.texture_settings)?);
+        self.cache.insert(name.to_owned(), Rc::clone(&texture));
+        Ok(texture)
+    }
+
+    fn animation(&mut self, manifest: &AnimationManifest) -> Result<Animation<T>, ParseError> {
+        match manifest {
+            AnimationManifest::Image(name) => Ok(Animation::new(Rc::from(vec![self.image(name)?]), default_fps())),
+            AnimationManifest::Frames { frames, fps } => Ok(Animation::new(
+                frames.iter().map(|name| self.image(name)).collect::<Result<Vec<_>, _>>()?.into(),
+                *fps,
+            )),
+        }
+    }
+
+    fn optional_animation(&mut self, manifest: &Option<AnimationManifest>) -> Result<Option<Animation<T>>, ParseError> {
+        manifest.as_ref().map(|m| self.animation(m)).transpose()
+    }
+
+    fn layout(&mut self, manifest: &LayoutManifest) -> Result<Layout<T>, ParseError> {
+        let mut columns = Vec::with_capacity(manifest.columns.len());
+        let mut x = 0.0;
+        for column in &manifest.columns {
+            let head = column.long_note_head.as_ref().unwrap_or(&column.note);
+            let tail = column.long_note_tail.as_ref().unwrap_or(head);
+            columns.push(Column {
+                x,
+                width: column.width,
+                note: self.animation(&column.note)?,
+                long_note_head: self.animation(head)?,
+                long_note_body: self.animation(&column.long_note_body)?,
+                long_note_tail: self.animation(tail)?,
+                key: self.optional_animation(&column.key)?,
+                key_down: self.optional_animation(&column.key_down)?,
+                flash: self.optional_animation(&column.flash)?,
+            });
+            x += column.width + manifest.column_spacing;
+        }
+
+        let judgement = match &manifest.judgement {
+            Some(judgement) => Some(JudgementLayout {
+                position: judgement.position,
+                duration: time::Duration::from_secs_f64(judgement.duration.max(0.0)),
+                perfect: self.animation(&judgement.perfect)?,
+                good: self.animation(&judgement.good)?,
+                bad: self.animation(&judgement.bad)?,
+                miss: self.animation(&judgement.miss)?,
+            }),
+            None => None,
+        };
+        let mut snap_palette = DEFAULT_SNAP_PALETTE;
+        for (key, &color) in &manifest.snap_colors {
+            if let Some(i) = snap_palette_index(key) {
+                snap_palette[i] = color;
+            }
+        }
+
+        let combo = match &manifest.combo {
+            Some(combo) => Some(ComboLayout {
+                position: combo.position,
+                digits: combo.digits.iter().map(|name| self.image(name)).collect::<Result<_, _>>()?,
+                spacing: combo.spacing,
+            }),
+            None => None,
+        };
+
+        Ok(Layout {
+            x: manifest.x,
+            width: x - manifest.column_spacing,
+            hit_position: manifest.hit_position,
+            columns,
+            background: self.optional_animation(&manifest.background)?,
+            judge_line: self.optional_animation(&manifest.judge_line)?,
+            measure_line: self.optional_animation(&manifest.measure_line)?,
+            beat_line: self.optional_animation(&manifest.beat_line)?,
+            judgement,
+            combo,
+            snap_palette,
+        })
+    }
+}
+
+fn read_manifest(path: &path::Path) -> Result<Manifest, ParseError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| ParseError::Io(format!("Error reading {}", path.display()), e))?;
+    let manifest: Manifest = toml::from_str(&text)
+        .map_err(|e| ParseError::Parse(format!("Error parsing {}", path.display()), Some(Box::new(e))))?;
+    manifest
+        .validate()
+        .map_err(|e| ParseError::Parse(format!("Invalid skin {}: {}", path.display(), e), None))?;
+    Ok(manifest)
+}
+
+pub fn from_path<F, G>(
+    factory: &mut F,
+    dir: &path::Path,
+    config: &config::Config,
+    report: &mut SkinReport,
+) -> Result<Box<dyn GameSkin<G>>, ParseError>
+where
+    G: Graphics + 'static,
+    G::Texture: CreateTexture<F>,
+    <G::Texture as TextureOp<F>>::Error: ToString,
+{
+    load(factory, dir, config, report, &mut Vec::new())
+}
+
+/// The config's entry for the skin `manifest` falls back to, if it has one
+fn fallback_entry<'a>(
+    manifest: &Manifest,
+    config: &'a config::Config,
+) -> Result<Option<&'a config::SkinEntry>, ParseError> {
+    match &manifest.fallback {
+        Some(name) => config
+            .game
+            .skins
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, entry)| Some(entry))
+            .ok_or_else(|| ParseError::Parse(format!("The fallback skin {} isn't in the config", name), None)),
+        None => Ok(None),
+    }
+}
+
+/// The entry for the skin that the skin in `dir` falls back to, or `None` if it doesn't have one
+/// or its manifest can't be read
+pub(super) fn fallback(dir: &path::Path, config: &config::Config) -> Option<config::SkinEntry> {
+    let manifest = read_manifest(&dir.join(MANIFEST_NAME)).ok()?;
+    fallback_entry(&manifest, config).ok().flatten().cloned()
+}
+
+/// `chain` is the directories of the native skins that are falling back to this one, so skins
+/// that fall back to each other don't load forever
+fn load<F, G>(
+    factory: &mut F,
+    dir: &path::Path,
+    config: &config::Config,
+    report: &mut SkinReport,
+    chain: &mut Vec<path::PathBuf>,
+) -> Result<Box<dyn GameSkin<G>>, ParseError>
+where
+    G: Graphics + 'static,
+    G::Texture: CreateTexture<F>,
+    <G::Texture as TextureOp<F>>::Error: ToString,
+{
+    let manifest = read_manifest(&dir.join(MANIFEST_NAME))?;
+    chain.push(dir.to_owned());
+
+    let fallback = match (&manifest.fallback, fallback_entry(&manifest, config)?) {
+        (Some(name), Some(entry)) => {
+            Some(match entry {
+                config::SkinEntry::Native(p) if chain.contains(p) => {
+                    return Err(ParseError::Parse(format!("The fallback skin {} falls back to {}", name, dir.display()), None));
+                }
+                config::SkinEntry::Native(p) => load(factory, p, config, report, chain)?,
+                entry => super::from_path_with_report(factory, entry, config, report)?,
+            })
+        }
+        _ => None,
+    };
+
+    let layout = match manifest.keymodes.get(KEYMODE) {
+        Some(layout) => {
+            let mut loader = Loader {
+                factory,
+                report,
+                dir,
+                texture_settings: TextureSettings::new(),
+                cache: HashMap::new(),
+            };
+            Some(loader.layout(layout)?)
+        }
+        None => None,
+    };
+
+    let now = time::Instant::now();
+    Ok(Box::new(NativeSkin {
+        screen_height: manifest.screen_height,
+        layout,
+        fallback,
+        judgement: None,
+        keys_down_time: [now; 7],
+        created: now,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(width: u32) -> String {
+        format!("[[keymodes.7k.columns]]\nwidth = {}\nnote = \"note.png\"\nlong_note_body = \"body.png\"\n", width)
+    }
+
+    fn parse(text: &str) -> Result<Manifest, String> {
+        let manifest: Manifest = toml::from_str(text).map_err(|e| e.to_string())?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    #[test]
+    fn parses_layout() {
+        let mut text = String::from("[keymodes.7k]\nx = 100\nhit_position = 400\n");
+        for _ in 0..6 {
+            text += &column(30);
+        }
+        text += "[[keymodes.7k.columns]]\nwidth = 40\nnote = { frames = [\"a.png\", \"b.png\"], fps = 10 }\n";
+        text += "long_note_body = \"body.png\"\n";
+        let manifest = parse(&text).unwrap();
+
+        assert_eq!(manifest.screen_height, 480.0);
+        let layout = &manifest.keymodes[KEYMODE];
+        assert_eq!(layout.columns.len(), 7);
+        match &layout.columns[6].note {
+            AnimationManifest::Frames { frames, fps } => {
+                assert_eq!(frames, &["a.png", "b.png"]);
+                assert_eq!(*fps, 10.0);
+            }
+            AnimationManifest::Image(_) => panic!("expected frames"),
+        }
+    }
+
+    #[test]
+    fn needs_layout_or_fallback() {
+        assert!(parse("").is_err());
+        assert!(parse("fallback = \"o2jamu\"").is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_column_count() {
+        let text = String::from("[keymodes.7k]\nx = 100\nhit_position = 400\n") + &column(30);
+        assert!(parse(&text).unwrap_err().contains("1 columns"));
+    }
+
+    #[test]
+    fn rejects_empty_animation() {
+        let mut text = String::from("[keymodes.7k]\nx = 100\nhit_position = 400\n");
+        for _ in 0..7 {
+            text += &column(30);
+        }
+        text += "[keymodes.7k.judgement]\nposition = [0, 0]\nperfect = { frames = [] }\n";
+        text += "good = \"good.png\"\nbad = \"bad.png\"\nmiss = \"miss.png\"\n";
+        assert!(parse(&text).unwrap_err().contains("judgement.perfect has no frames"));
+    }
+
+    #[test]
+    fn checks_snap_colors() {
+        let mut text = String::from("[keymodes.7k]\nx = 100\nhit_position = 400\n");
+        for _ in 0..7 {
+            text += &column(30);
+        }
+        text += "[keymodes.7k.snap_colors]\n4 = [1.0, 0.0, 0.0, 1.0]\nother = [0.5, 0.5, 0.5, 1.0]\n";
+        assert_eq!(2, parse(&text).unwrap().keymodes[KEYMODE].snap_colors.len());
+
+        text += "5 = [1.0, 1.0, 1.0, 1.0]\n";
+        assert!(parse(&text).unwrap_err().contains("snap_colors.5 isn't a beat division"));
+    }
+}
@@ -0,0 +1,684 @@
+//! O2Jam skin parser module
+//!
+//! O2Jam keeps its images in OPI archives, and every image in an archive is an OJI sprite: one or
+//! more frames of 16-bit pixels, each with the position it's drawn at on O2Jam's 800x600 screen.
+//! There's no separate layout file, the play scene is laid out using those positions.
+//!
+//! The default skin isn't in the repo (see sample_config.toml). The tests read
+//! test/o2jam_skin/Playing1.opi, a small archive with every sprite this module uses.
+
+use graphics::{draw_state::DrawState, image::Image, math, types, Graphics, ImageSize, Transformed};
+use nom::*;
+use texture::{CreateTexture, Format, TextureOp, TextureSettings};
+
+use std::{fs, path, rc::Rc, time};
+
+use crate::judgement::Judgement;
+use super::{fade_color, Animation, GameSkin, NotePosition, ParseError, SkinReport, TextureSource};
+
+/// The archive with everything drawn during play
+const PLAYING_ARCHIVE: &str = "Playing1.opi";
+
+/// Sprite positions are on a screen this tall
+const SCREEN_HEIGHT: f64 = 600.0;
+
+/// How fast sprites with more than one frame animate
+const FRAMES_PER_SECOND: f64 = 30.0;
+
+/// How long a judgement sprite stays on screen
+const JUDGEMENT_DURATION: time::Duration = time::Duration::from_millis(300);
+
+/// How big each entry in an OPI archive's file table is
+const ARCHIVE_ENTRY_SIZE: usize = 152;
+
+/// Lanes 1, 3, 5 and 7 are white, 2 and 6 are blue and 4 is yellow. Notes, long notes and key
+/// flashes come in one sprite for each color.
+const LANE_COLORS: [usize; 7] = [0, 1, 0, 2, 0, 1, 0];
+const COLOR_NAMES: [&str; 3] = ["White", "Blue", "Yellow"];
+
+fn string_from_slice(s: &[u8]) -> String {
+    String::from_utf8_lossy(s).into_owned()
+}
+
+macro_rules! string_block {
+    ($i:expr, $n:expr) => (flat_map!($i, take!($n), map!(take_until!("\0"), string_from_slice)));
+}
+
+/// A file in an OPI archive
+#[derive(Debug)]
+struct ArchiveEntry {
+    /// 1 for files, anything else is skipped
+    kind: i32,
+    name: String,
+    offset: u32,
+    /// Files whose compressed size is different from their size are zlib streams
+    compressed_size: u32,
+    size: u32,
+}
+
+named!(archive_file_count(&[u8]) -> u32,
+    do_parse!(
+        le_u32 >>
+        file_count: le_u32 >>
+        (file_count)
+    )
+);
+
+named!(archive_entry(&[u8]) -> ArchiveEntry,
+    do_parse!(
+        kind: le_i32 >>
+        name: string_block!(128) >>
+        offset: le_u32 >>
+        compressed_size: le_u32 >>
+        le_u32 >>
+        size: le_u32 >>
+        le_u32 >>
+        (ArchiveEntry { kind, name, offset, compressed_size, size })
+    )
+);
+
+/// An OPI archive. The file table is at the end, after the files.
+struct Archive {
+    data: Vec<u8>,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl Archive {
+    fn new(data: Vec<u8>) -> Result<Self, String> {
+        let file_count = archive_file_count(&data)
+            .map_err(|_| String::from("Archive is too short"))?
+            .1 as usize;
+        let table_start = file_count
+            .checked_mul(ARCHIVE_ENTRY_SIZE)
+            .and_then(|size| data.len().checked_sub(size))
+            .filter(|&start| start >= 8)
+            .ok_or_else(|| format!("Archive is too short for {} files", file_count))?;
+        let entries = count!(&data[table_start..], archive_entry, file_count)
+            .map_err(|_| String::from("Invalid file table"))?
+            .1;
+        Ok(Archive { data, entries })
+    }
+
+    /// File names aren't case sensitive, like on Windows
+    fn entry(&self, name: &str) -> Option<&ArchiveEntry> {
+        self.entries.iter().find(|e| e.kind == 1 && e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The contents of a file, decompressed
+    fn file(&self, name: &str) -> Result<Vec<u8>, String> {
+        let entry = self.entry(name).ok_or_else(|| format!("{} is missing", name))?;
+        let start = entry.offset as usize;
+        let data = self
+            .data
+            .get(start..start + entry.compressed_size as usize)
+            .ok_or_else(|| format!("{} goes past the end of the archive", name))?;
+        if entry.compressed_size == entry.size {
+            Ok(data.to_vec())
+        } else {
+            miniz_oxide::inflate::decompress_to_vec_zlib(data)
+                .map_err(|e| format!("Error decompressing {}: {:?}", name, e))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SpriteHeader {
+    /// 0 for RGB565 with a transparent color, 1 for ARGB1555
+    color_format: u16,
+    frame_count: u16,
+    /// The RGB565 color that's drawn as transparent
+    transparent_color: u16,
+    data_size: u32,
+}
+
+named!(sprite_header(&[u8]) -> SpriteHeader,
+    do_parse!(
+        color_format: le_u16 >>
+        frame_count: le_u16 >>
+        transparent_color: le_u16 >>
+        le_u16 >>
+        data_size: le_u32 >>
+        (SpriteHeader { color_format, frame_count, transparent_color, data_size })
+    )
+);
+
+#[derive(Debug)]
+struct FrameHeader {
+    /// Where the top left corner goes on the screen
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    /// Where the frame's pixels are, from the start of the pixel data
+    offset: u32,
+    size: u32,
+}
+
+named!(frame_header(&[u8]) -> FrameHeader,
+    do_parse!(
+        x: le_i16 >>
+        y: le_i16 >>
+        width: le_u16 >>
+        height: le_u16 >>
+        offset: le_u32 >>
+        size: le_u32 >>
+        le_u32 >>
+        (FrameHeader { x, y, width, height, offset, size })
+    )
+);
+
+/// Expand a 5 or 6 bit color channel to 8 bits
+fn expand(value: u16, bits: u32) -> u8 {
+    let value = value << (8 - bits);
+    (value | value >> bits) as u8
+}
+
+/// Convert a pixel to RGBA
+fn rgba(pixel: u16, header: &SpriteHeader) -> [u8; 4] {
+    if header.color_format == 1 {
+        let alpha = if pixel & 0x8000 != 0 { 255 } else { 0 };
+        [expand(pixel >> 10 & 0x1f, 5), expand(pixel >> 5 & 0x1f, 5), expand(pixel & 0x1f, 5), alpha]
+    } else {
+        let alpha = if pixel == header.transparent_color { 0 } else { 255 };
+        [expand(pixel >> 11, 5), expand(pixel >> 5 & 0x3f, 6), expand(pixel & 0x1f, 5), alpha]
+    }
+}
+
+/// Decode an OJI sprite into its frames' headers and RGBA pixels
+fn decode_sprite(data: &[u8]) -> Result<Vec<(FrameHeader, Vec<u8>)>, String> {
+    let (rest, header) = sprite_header(data).map_err(|_| String::from("Invalid sprite header"))?;
+    if header.color_format > 1 {
+        return Err(format!("Unknown color format {}", header.color_format));
+    }
+    let (pixel_data, frames) = count!(rest, frame_header, header.frame_count as usize)
+        .map_err(|_| String::from("Invalid frame headers"))?;
+    let pixel_data = pixel_data
+        .get(..header.data_size as usize)
+        .ok_or_else(|| String::from("Pixel data is too short"))?;
+
+    frames
+        .into_iter()
+        .enumerate()
+        .map(|(n, frame)| {
+            let pixel_count = frame.width as usize * frame.height as usize;
+            let start = frame.offset as usize;
+            let pixels = pixel_data
+                .get(start..start + frame.size as usize)
+                .filter(|p| p.len() >= pixel_count * 2)
+                .ok_or_else(|| format!("Frame {} goes past the end of the pixel data", n))?;
+            let rgba = pixels
+                .chunks(2)
+                .take(pixel_count)
+                .flat_map(|p| rgba(u16::from(p[0]) | u16::from(p[1]) << 8, &header).to_vec())
+                .collect();
+            Ok((frame, rgba))
+        })
+        .collect()
+}
+
+/// One frame of a sprite, in screen pixels
+struct Frame<T> {
+    texture: T,
+    position: [f64; 2],
+    size: [f64; 2],
+}
+
+impl<T> ImageSize for Frame<T> {
+    fn get_size(&self) -> (u32, u32) {
+        (self.size[0] as u32, self.size[1] as u32)
+    }
+}
+
+/// A sprite's frames. Sprites always have at least one frame.
+type Sprite<T> = Animation<Frame<T>>;
+
+fn load_sprite<F, T>(
+    factory: &mut F,
+    archive: &Archive,
+    archive_path: &path::Path,
+    name: &str,
+    texture_settings: &TextureSettings,
+) -> Result<Sprite<T>, ParseError>
+where
+    T: CreateTexture<F>,
+    T::Error: ToString,
+{
+    let parse_error = |e: String| ParseError::Parse(format!("Error reading {} from {}: {}", name, archive_path.display(), e), None);
+    let frames = decode_sprite(&archive.file(name).map_err(parse_error)?).map_err(parse_error)?;
+    if frames.is_empty() {
+        return Err(parse_error(String::from("Sprite has no frames")));
+    }
+
+    let frames = frames
+        .into_iter()
+        .map(|(header, rgba)| {
+            let texture = CreateTexture::create(
+                factory,
+                Format::Rgba8,
+                &rgba,
+                [u32::from(header.width), u32::from(header.height)],
+                texture_settings,
+            ).map_err(|e: T::Error| ParseError::TextureError {
+                path: archive_path.join(name),
+                error: e.to_string(),
+            })?;
+            Ok(Rc::new(Frame {
+                texture,
+                position: [f64::from(header.x), f64::from(header.y)],
+                size: [f64::from(header.width), f64::from(header.height)],
+            }))
+        })
+        .collect::<Result<Vec<_>, ParseError>>()?;
+    Ok(Animation::new(Rc::from(frames), FRAMES_PER_SECOND))
+}
+
+struct O2JamSkinTextures<T> {
+    /// The lanes' background, which also sets where the lanes start
+    stage: Sprite<T>,
+    /// Sets where notes get hit
+    judge_line: Sprite<T>,
+
+    /// One of each for every lane color
+    notes: [Sprite<T>; 3],
+    long_note_bodies: [Sprite<T>; 3],
+    /// Lights up the lane while its key is held
+    key_flashes: [Sprite<T>; 3],
+
+    cool: Sprite<T>,
+    good: Sprite<T>,
+    bad: Sprite<T>,
+    miss: Sprite<T>,
+}
+
+struct O2JamSkin<G: Graphics> {
+    textures: O2JamSkinTextures<G::Texture>,
+
+    /// The left edge and width of each lane, in screen pixels. The lanes are as wide as their
+    /// notes and sit next to each other, starting at the left edge of the stage.
+    lanes: [(f64, f64); 7],
+
+    /// The top of the lanes and the y position notes get hit at, in screen pixels
+    lane_top: f64,
+    hit_position: f64,
+
+    /// judgement, time of first frame
+    judgement: Option<(Judgement, time::Instant)>,
+
+    /// When each key was pressed, for animating the key flashes
+    keys_down_time: [time::Instant; 7],
+
+    /// When the skin was loaded, used for animations that loop forever
+    created: time::Instant,
+}
+
+/// Draw a frame stretched over `rect`, tinted with `color` if there is one. `rect` and `transform`
+/// are in screen pixels.
+fn draw_frame<G: Graphics>(
+    frame: &Frame<G::Texture>,
+    rect: [f64; 4],
+    color: Option<types::Color>,
+    draw_state: &DrawState,
+    transform: math::Matrix2d,
+    g: &mut G,
+) {
+    Image::new()
+        .maybe_color(color)
+        .rect(rect)
+        .draw(&frame.texture, draw_state, transform, g);
+}
+
+/// Draw a frame where the sprite says it goes
+fn draw_frame_in_place<G: Graphics>(
+    frame: &Frame<G::Texture>,
+    draw_state: &DrawState,
+    transform: math::Matrix2d,
+    g: &mut G,
+) {
+    let rect = [frame.position[0], frame.position[1], frame.size[0], frame.size[1]];
+    draw_frame(frame, rect, None, draw_state, transform, g);
+}
+
+impl<G: Graphics> O2JamSkin<G> {
+    /// The y position of a note's bottom edge
+    fn note_y(&self, pos: f64) -> f64 {
+        self.hit_position - (self.hit_position - self.lane_top) * pos
+    }
+
+    fn draw_note(
+        &self,
+        color: Option<types::Color>,
+        transform: math::Matrix2d,
+        g: &mut G,
+        column: usize,
+        pos: f64,
+    ) {
+        let draw_state = &DrawState::default();
+        let (x, w) = self.lanes[column];
+        let frame = self.textures.notes[LANE_COLORS[column]].looped(self.created.elapsed());
+        let h = frame.size[1];
+        draw_frame(frame, [x, self.note_y(pos) - h, w, h], color, draw_state, transform, g);
+    }
+
+    fn draw_long_note(
+        &self,
+        color: Option<types::Color>,
+        transform: math::Matrix2d,
+        g: &mut G,
+        column: usize,
+        pos: f64,
+        end_pos: f64,
+    ) {
+        // Held long notes stop at the judge line
+        let pos = pos.max(0.0);
+        let draw_state = &DrawState::default();
+        let (x, w) = self.lanes[column];
+        let bottom = self.note_y(pos);
+        let top = self.note_y(end_pos);
+
+        let body = self.textures.long_note_bodies[LANE_COLORS[column]].looped(self.created.elapsed());
+        draw_frame(body, [x, top, w, bottom - top], color, draw_state, transform, g);
+
+        // Both ends of an O2Jam long note look like a normal note
+        self.draw_note(color, transform, g, column, pos);
+        self.draw_note(color, transform, g, column, end_pos);
+    }
+
+    fn judgement_sprite(&self, judgement: Judgement) -> &Sprite<G::Texture> {
+        match judgement {
+            Judgement::Perfect => &self.textures.cool,
+            Judgement::Good => &self.textures.good,
+            Judgement::Bad => &self.textures.bad,
+            Judgement::Miss => &self.textures.miss,
+        }
+    }
+}
+
+impl<G: Graphics> GameSkin<G> for O2JamSkin<G> {
+    fn draw_play_scene(
+        &mut self,
+        transform: math::Matrix2d,
+        g: &mut G,
+        stage_height: f64,
+        keys_down: &[bool; 7],
+        notes: &[NotePosition],
+        bar_lines: &[(f64, bool)],
+    ) {
+        let draw_state = &DrawState::default();
+        let scale = stage_height / SCREEN_HEIGHT;
+        let screen_transform = transform.scale(scale, scale);
+
+        draw_frame_in_place(self.textures.stage.first(), draw_state, screen_transform, g);
+        self.draw_bar_lines(transform, g, stage_height, bar_lines);
+
+        for (column, _) in keys_down.iter().enumerate().filter(|&(_, &down)| down) {
+            let (x, w) = self.lanes[column];
+            let flash = self.textures.key_flashes[LANE_COLORS[column]].once(self.keys_down_time[column].elapsed());
+            let h = flash.size[1];
+            draw_frame(flash, [x, self.hit_position - h, w, h], None, draw_state, screen_transform, g);
+        }
+
+        draw_frame_in_place(self.textures.judge_line.first(), draw_state, screen_transform, g);
+
+        // O2Jam skins don't have a snap palette, notes keep their lane colours
+        for &(column, pos, end_pos, _, alpha) in notes {
+            let color = fade_color(alpha);
+            if let Some(end_pos) = end_pos {
+                self.draw_long_note(color, screen_transform, g, column, pos, end_pos);
+            } else {
+                self.draw_note(color, screen_transform, g, column, pos);
+            }
+        }
+
+        self.draw_last_judgement(transform, g, stage_height);
+    }
+
+    fn draw_last_judgement(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64) {
+        if let Some((judgement, time)) = self.judgement {
+            let elapsed = time.elapsed();
+            if elapsed <= JUDGEMENT_DURATION {
+                let frame = self.judgement_sprite(judgement).once(elapsed);
+                let scale = stage_height / SCREEN_HEIGHT;
+                draw_frame_in_place(frame, &DrawState::default(), transform.scale(scale, scale), g);
+            } else {
+                self.judgement = None;
+            }
+        }
+    }
+
+    fn lane_area(&self, stage_height: f64) -> [f64; 4] {
+        let scale = stage_height / SCREEN_HEIGHT;
+        let (x, _) = self.lanes[0];
+        let (last_x, last_w) = self.lanes[6];
+        [
+            x * scale,
+            self.lane_top * scale,
+            (last_x + last_w - x) * scale,
+            (self.hit_position - self.lane_top) * scale,
+        ]
+    }
+
+    fn draw_judgement(&mut self, _column: usize, judgement: Judgement) {
+        self.judgement = Some((judgement, time::Instant::now()));
+    }
+
+    fn key_down(&mut self, column: usize) {
+        self.keys_down_time[column] = time::Instant::now();
+    }
+
+    fn key_up(&mut self, _column: usize) {}
+}
+
+/// The left edge and width of each lane. Lanes start at `stage_x` and are as wide as their lane
+/// colour's note, given by `note_widths`.
+fn lanes(stage_x: f64, note_widths: [f64; 3]) -> [(f64, f64); 7] {
+    let mut lanes = [(0.0, 0.0); 7];
+    let mut x = stage_x;
+    for (lane, &color) in lanes.iter_mut().zip(&LANE_COLORS) {
+        *lane = (x, note_widths[color]);
+        x += note_widths[color];
+    }
+    lanes
+}
+
+pub fn from_path<F, G>(
+    factory: &mut F,
+    dir: &path::Path,
+    report: &mut SkinReport,
+) -> Result<Box<dyn GameSkin<G>>, ParseError>
+where
+    G: Graphics + 'static,
+    G::Texture: CreateTexture<F>,
+    <G::Texture as TextureOp<F>>::Error: ToString,
+{
+    let archive_path = dir.join(PLAYING_ARCHIVE);
+    let data = fs::read(&archive_path)
+        .map_err(|e| ParseError::Io(format!("Error reading {}", archive_path.display()), e))?;
+    let archive = Archive::new(data)
+        .map_err(|e| ParseError::Parse(format!("Error reading {}: {}", archive_path.display(), e), None))?;
+
+    let texture_settings = TextureSettings::new();
+    let mut load = |name: &str| {
+        let source = if archive.entry(name).is_some() {
+            TextureSource::Skin(archive_path.clone())
+        } else {
+            TextureSource::Missing
+        };
+        report.textures.push((name.to_owned(), source));
+        load_sprite(factory, &archive, &archive_path, name, &texture_settings)
+    };
+
+    let stage = load("Stage.oji")?;
+    let judge_line = load("JudgeLine.oji")?;
+    let notes = [
+        load(&format!("Note{}.oji", COLOR_NAMES[0]))?,
+        load(&format!("Note{}.oji", COLOR_NAMES[1]))?,
+        load(&format!("Note{}.oji", COLOR_NAMES[2]))?,
+    ];
+    let long_note_bodies = [
+        load(&format!("LongNote{}.oji", COLOR_NAMES[0]))?,
+        load(&format!("LongNote{}.oji", COLOR_NAMES[1]))?,
+        load(&format!("LongNote{}.oji", COLOR_NAMES[2]))?,
+    ];
+    let key_flashes = [
+        load(&format!("KeyFlash{}.oji", COLOR_NAMES[0]))?,
+        load(&format!("KeyFlash{}.oji", COLOR_NAMES[1]))?,
+        load(&format!("KeyFlash{}.oji", COLOR_NAMES[2]))?,
+    ];
+    let cool = load("JudgeCool.oji")?;
+    let good = load("JudgeGood.oji")?;
+    let bad = load("JudgeBad.oji")?;
+    let miss = load("JudgeMiss.oji")?;
+
+    let note_widths = [notes[0].first().size[0], notes[1].first().size[0], notes[2].first().size[0]];
+    let lanes = lanes(stage.first().position[0], note_widths);
+    let lane_top = stage.first().position[1];
+    let hit_position = judge_line.first().position[1];
+
+    let now = time::Instant::now();
+    Ok(Box::new(O2JamSkin {
+        textures: O2JamSkinTextures {
+            stage,
+            judge_line,
+            notes,
+            long_note_bodies,
+            key_flashes,
+            cool,
+            good,
+            bad,
+            miss,
+        },
+        lanes,
+        lane_top,
+        hit_position,
+        judgement: None,
+        keys_down_time: [now; 7],
+        created: now,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An OJI sprite with one frame of RGB565 pixels
+    fn sprite(x: i16, y: i16, width: u16, pixels: &[u16], transparent_color: u16) -> Vec<u8> {
+        let height = pixels.len() as u16 / width;
+        let data_size = pixels.len() as u32 * 2;
+        let mut data = Vec::new();
+        for &n in &[0, 1, transparent_color, 0] {
+            data.extend_from_slice(&u16::to_le_bytes(n));
+        }
+        data.extend_from_slice(&data_size.to_le_bytes());
+        for &n in &[x as u16, y as u16, width, height] {
+            data.extend_from_slice(&n.to_le_bytes());
+        }
+        for &n in &[0, data_size, 0] {
+            data.extend_from_slice(&u32::to_le_bytes(n));
+        }
+        for &p in pixels {
+            data.extend_from_slice(&p.to_le_bytes());
+        }
+        data
+    }
+
+    /// An OPI archive with `files`, compressing the ones marked `true`
+    fn archive(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        let mut table = Vec::new();
+        for &(name, contents, compress) in files {
+            let stored = if compress {
+                miniz_oxide::deflate::compress_to_vec_zlib(contents, 6)
+            } else {
+                contents.to_vec()
+            };
+            let mut name_block = [0; 128];
+            name_block[..name.len()].copy_from_slice(name.as_bytes());
+            table.extend_from_slice(&1u32.to_le_bytes());
+            table.extend_from_slice(&name_block);
+            for &n in &[data.len() as u32, stored.len() as u32, 0, contents.len() as u32, 0] {
+                table.extend_from_slice(&n.to_le_bytes());
+            }
+            data.extend_from_slice(&stored);
+        }
+        data.extend_from_slice(&table);
+        data
+    }
+
+    #[test]
+    fn reads_archive_files() {
+        let compressible = vec![7; 1000];
+        let archive = Archive::new(archive(&[
+            ("Stage.oji", b"stage", false),
+            ("JudgeLine.oji", &compressible, true),
+        ])).unwrap();
+        assert_eq!(archive.file("stage.OJI").unwrap(), b"stage");
+        assert_eq!(archive.file("JudgeLine.oji").unwrap(), compressible);
+        assert!(archive.file("NoteWhite.oji").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_archive() {
+        let mut data = archive(&[("Stage.oji", b"stage", false)]);
+        data.truncate(100);
+        assert!(Archive::new(data).is_err());
+    }
+
+    #[test]
+    fn decodes_rgb565_frames() {
+        let frames = decode_sprite(&sprite(12, -3, 2, &[0xf800, 0x07e0, 0x001f, 0xffff], 0x001f)).unwrap();
+        assert_eq!(frames.len(), 1);
+        let (header, pixels) = &frames[0];
+        assert_eq!((header.x, header.y, header.width, header.height), (12, -3, 2, 2));
+        assert_eq!(
+            pixels,
+            &[255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 0, 255, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn decodes_argb1555_pixels() {
+        let header = SpriteHeader { color_format: 1, frame_count: 1, transparent_color: 0, data_size: 0 };
+        assert_eq!(rgba(0xfc00, &header), [255, 0, 0, 255]);
+        assert_eq!(rgba(0x7fff, &header), [255, 255, 255, 0]);
+    }
+
+    #[test]
+    fn rejects_frame_past_pixel_data() {
+        let mut data = sprite(0, 0, 2, &[0; 4], 0);
+        data.truncate(data.len() - 2);
+        assert!(decode_sprite(&data).is_err());
+    }
+
+    #[test]
+    fn reads_fixture_skin() {
+        let archive_path = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test/o2jam_skin").join(PLAYING_ARCHIVE);
+        let archive = Archive::new(fs::read(&archive_path).unwrap()).unwrap();
+        let sprite = |name: &str| decode_sprite(&archive.file(name).unwrap()).unwrap();
+
+        let mut names = vec![String::from("Stage.oji"), String::from("JudgeLine.oji")];
+        for kind in &["Note", "LongNote", "KeyFlash"] {
+            names.extend(COLOR_NAMES.iter().map(|color| format!("{}{}.oji", kind, color)));
+        }
+        for judgement in &["Cool", "Good", "Bad", "Miss"] {
+            names.push(format!("Judge{}.oji", judgement));
+        }
+        for name in &names {
+            assert!(!sprite(name).is_empty(), "{} has no frames", name);
+        }
+        assert_eq!(sprite("JudgeCool.oji").len(), 2);
+        // ARGB1555
+        assert_eq!(&sprite("KeyFlashYellow.oji")[0].1[..4], &[255, 255, 0, 255]);
+
+        let stage = &sprite("Stage.oji")[0].0;
+        assert_eq!((stage.x, stage.y), (10, 40));
+        assert_eq!(sprite("JudgeLine.oji")[0].0.y, 480);
+        let width = |name: &str| f64::from(sprite(name)[0].0.width);
+        let lanes = lanes(f64::from(stage.x), [width("NoteWhite.oji"), width("NoteBlue.oji"), width("NoteYellow.oji")]);
+        assert_eq!(lanes[0], (10.0, 28.0));
+        assert_eq!(lanes[1], (38.0, 24.0));
+        assert_eq!(lanes[3], (90.0, 32.0));
+        assert_eq!(lanes[6], (174.0, 28.0));
+    }
+}
@@ -6,7 +6,7 @@
 use graphics::{
     draw_state::{self, DrawState},
     image::Image,
-    math, Graphics,
+    math, types, Graphics, Transformed,
 };
 use texture::{CreateTexture, Format, ImageSize, TextureSettings, TextureOp};
 
@@ -14,8 +14,12 @@ use std::{
     collections::HashMap, error, fmt, fs::File, io::BufRead, io::BufReader, path, rc::Rc, str, time,
 };
 
-use crate::judgement::Judgement;
-use super::{ParseError, GameSkin};
+use crate::{
+    chart::SNAP_DIVISIONS,
+    config::{OsuHitImage, OsuJudgementImages},
+    judgement::Judgement,
+};
+use super::{fade_color, note_color, Animation, ParseError, GameSkin, NotePosition, SnapPalette, SkinReport, TextureSource, DEFAULT_SNAP_PALETTE};
 
 #[derive(Copy, Clone, Debug)]
 enum NoteBodyStyle {
@@ -55,20 +59,60 @@ enum HitAnimState {
     None,
 }
 
+/// The images used to draw numbers, set by the `[Fonts]` section of skin.ini
+struct OsuFont<T> {
+    digits: Vec<Rc<T>>,
+    dot: Rc<T>,
+    percent: Rc<T>,
+    /// How many pixels each character overlaps the previous one by
+    overlap: i16,
+}
+
+impl<T: ImageSize> OsuFont<T> {
+    /// The image for a character, if the font has one
+    fn char_texture(&self, c: char) -> Option<&T> {
+        match c {
+            '0'..='9' => self.digits.get(c as usize - '0' as usize).map(|t| &**t),
+            '.' => Some(&*self.dot),
+            '%' => Some(&*self.percent),
+            _ => None,
+        }
+    }
+
+    /// How wide `text` is when each pixel of the images is `scale` wide
+    fn width(&self, text: &str, scale: f64) -> f64 {
+        let (width, count) = text
+            .chars()
+            .filter_map(|c| self.char_texture(c))
+            .fold((0.0, 0), |(w, n), t| (w + t.get_width() as f64 * scale, n + 1));
+        width - self.overlap as f64 * scale * (count as f64 - 1.0).max(0.0)
+    }
+
+    fn height(&self, scale: f64) -> f64 {
+        self.digits[0].get_height() as f64 * scale
+    }
+}
+
+/// How fast the judgement images and the health bar animate
+const JUDGEMENT_FPS: f64 = 30.0;
+const SCOREBAR_FPS: f64 = 30.0;
+/// How fast lightingN and lightingL animate
+const LIGHTING_FPS: f64 = 60.0;
+
 /// Holds skin data, such as note images and what not.
 struct OsuSkinTextures<T> {
-    miss: Rc<[Rc<T>]>,
-    hit50: Rc<[Rc<T>]>,
-    hit100: Rc<[Rc<T>]>,
-    hit200: Rc<[Rc<T>]>,
-    hit300: Rc<[Rc<T>]>,
-    hit300g: Rc<[Rc<T>]>,
+    miss: Animation<T>,
+    hit50: Animation<T>,
+    hit100: Animation<T>,
+    hit200: Animation<T>,
+    hit300: Animation<T>,
+    hit300g: Animation<T>,
 
     /// The animation played when a single note is pressed
-    lighting_n: Rc<[Rc<T>]>,
+    lighting_n: Animation<T>,
 
     /// The animation played when a long note is pressed
-    lighting_l: Rc<[Rc<T>]>,
+    lighting_l: Animation<T>,
 
     /// The images virtual keys under the judgement line.
     keys: [Rc<T>; 7],
@@ -97,6 +141,26 @@ struct OsuSkinTextures<T> {
     stage_left: Rc<T>,
     stage_right: Rc<T>,
     stage_bottom: Option<Rc<[Rc<T>]>>,
+
+    /// The health bar background and fill
+    scorebar_bg: Rc<T>,
+    scorebar_colour: Animation<T>,
+
+    score_font: OsuFont<T>,
+    combo_font: OsuFont<T>,
+}
+
+impl<T> OsuSkinTextures<T> {
+    fn hit_image(&self, image: OsuHitImage) -> &Animation<T> {
+        match image {
+            OsuHitImage::Hit0 => &self.miss,
+            OsuHitImage::Hit50 => &self.hit50,
+            OsuHitImage::Hit100 => &self.hit100,
+            OsuHitImage::Hit200 => &self.hit200,
+            OsuHitImage::Hit300 => &self.hit300,
+            OsuHitImage::Hit300g => &self.hit300g,
+        }
+    }
 }
 
 /// Various information related to how to draw components. All the numbers are
@@ -110,15 +174,21 @@ struct OsuSkinConfig {
     colour_column_line: [u8; 4],
     hit_position: u16,
     score_position: u16,
+    combo_position: u16,
     light_position: u16,
     width_for_note_height_scale: f64,
     note_body_style: [NoteBodyStyle; 7],
 
     colour_light: [[u8; 3]; 7],
+    barline_height: f64,
+    colour_barline: [u8; 4],
+
+    /// From the ColourSnap4 to ColourSnap64 and ColourSnapOther keys, which osu doesn't have.
+    /// Already converted to floats.
+    snap_palette: SnapPalette,
     // TODO
     // lighting_n_width: [u16; 7],
     // lighting_l_width: [u16; 7],
-    // combo_position: u16,
     // judgement_line: bool,
 
     // low priority
@@ -136,8 +206,14 @@ struct OsuSkin<G: Graphics> {
     config: OsuSkinConfig,
     anim_states: OsuAnimStates,
 
+    /// Which hit image to draw for each judgement
+    judgement_images: OsuJudgementImages,
+
     /// judgement, time of first frame
     judgement: Option<(Judgement, time::Instant)>,
+
+    /// When the skin was loaded, used for animations that loop forever
+    created: time::Instant,
 }
 
 impl<G: Graphics> GameSkin<G> for OsuSkin<G> {
@@ -147,18 +223,20 @@ impl<G: Graphics> GameSkin<G> for OsuSkin<G> {
         g: &mut G,
         stage_height: f64,
         keys_down: &[bool; 7],
-        // column index, start pos, end pos
-        notes: &[(usize, f64, Option<f64>)],
+        notes: &[NotePosition],
+        bar_lines: &[(f64, bool)],
     ) {
         let draw_state = &DrawState::default();
 
         self.draw_track(draw_state, transform, g, stage_height);
+        self.draw_bar_lines(transform, g, stage_height, bar_lines);
         self.draw_keys(draw_state, transform, g, stage_height, keys_down);
-        for &(column, pos, end_pos) in notes {
+        for &(column, pos, end_pos, snap, alpha) in notes {
+            let color = note_color(&self.config.snap_palette, snap, alpha);
             if let Some(end_p) = end_pos {
-                self.draw_long_note(draw_state, transform, g, stage_height, pos, end_p, column);
+                self.draw_long_note(color, fade_color(alpha), transform, g, stage_height, pos, end_p, column);
             } else {
-                self.draw_note(draw_state, transform, g, stage_height, pos, column);
+                self.draw_note(color, transform, g, stage_height, pos, column);
             }
         }
 
@@ -169,35 +247,114 @@ impl<G: Graphics> GameSkin<G> for OsuSkin<G> {
             stage_height,
         );
 
-        // Draw judgement
+        self.draw_last_judgement(transform, g, stage_height);
+    }
+
+    fn lane_area(&self, stage_height: f64) -> [f64; 4] {
+        let scale = stage_height / 480.0;
+        let column_width_sum = (self.config.column_width.iter().sum::<u16>() as f64
+            + self.config.column_spacing.iter().sum::<u16>() as f64)
+            * scale;
+        [
+            self.config.column_start as f64 * scale,
+            0.0,
+            column_width_sum,
+            self.config.hit_position as f64 * scale,
+        ]
+    }
+
+    fn draw_judgement(&mut self, _column: usize, judgement: Judgement) {
+        self.judgement = Some((judgement, time::Instant::now()));
+    }
+
+    fn draw_last_judgement(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64) {
         if let Some((judgement, time)) = self.judgement {
             let elapsed = time.elapsed();
 
             if elapsed <= time::Duration::from_millis(200) {
-                // the "burst" animation
-                let scale = if elapsed <= time::Duration::from_millis(50) {
-                    1.5 - elapsed.subsec_nanos() as f64 / 50_000_000.0 / 2.0
-                } else if elapsed <= time::Duration::from_millis(160) {
-                    1.0
-                } else {
-                    1.0 - (elapsed.subsec_nanos() - 160_000_000) as f64 / 150_000_000.0
-                };
-                match judgement {
-                    Judgement::Miss => self.draw_miss(draw_state, transform, g, stage_height),
-                    Judgement::Bad => remani_warn!("draw Bad judgement is not implemented for osu skin"), // TODO
-                    Judgement::Good => remani_warn!("draw Bad judgement is not implemented for osu skin"),
-                    Judgement::Perfect => {
-                        self.draw_perfect(draw_state, transform, scale, g, stage_height, elapsed)
-                    }
-                };
+                self.draw_judgement_image(&DrawState::default(), transform, g, stage_height, judgement, elapsed);
             } else {
                 self.judgement = None;
             }
         }
     }
 
-    fn draw_judgement(&mut self, _column: usize, judgement: Judgement) {
-        self.judgement = Some((judgement, time::Instant::now()));
+    fn draw_bar_lines(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64, bar_lines: &[(f64, bool)]) {
+        let scale = stage_height / 480.0;
+        let [x, _, w, hit_p] = self.lane_area(stage_height);
+        let h = self.config.barline_height * scale;
+        let c = self.config.colour_barline;
+        let colour = [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0, c[3] as f32 / 255.0];
+        // osu!mania only has measure lines, beats get fainter ones
+        let beat_colour = [colour[0], colour[1], colour[2], colour[3] / 3.0];
+        for &(pos, measure) in bar_lines {
+            let y = hit_p * (1.0 - pos) - h / 2.0;
+            let colour = if measure { colour } else { beat_colour };
+            graphics::rectangle(colour, [x, y, w, h], transform, g);
+        }
+    }
+
+    fn draw_combo(&mut self, transform: math::Matrix2d, g: &mut G, stage_height: f64, combo: u32) {
+        if combo == 0 {
+            return;
+        }
+        let scale = stage_height / 480.0;
+        let scale2 = stage_height / 768.0;
+        let [x, _, w, _] = self.lane_area(stage_height);
+        let font = &self.textures.combo_font;
+        let y = self.config.combo_position as f64 * scale - font.height(scale2) / 2.0;
+        draw_text(font, &combo.to_string(), [x + w / 2.0, y], scale2, 0.5, transform, g);
+    }
+
+    fn draw_score(&mut self, transform: math::Matrix2d, g: &mut G, window_size: [f64; 2], score: u32) {
+        // osu!mania puts the score in the top right corner of the screen
+        let scale2 = window_size[1] / 768.0;
+        let text = format!("{:08}", score);
+        draw_text(&self.textures.score_font, &text, [window_size[0], 0.0], scale2, 1.0, transform, g);
+    }
+
+    fn draw_accuracy(&mut self, transform: math::Matrix2d, g: &mut G, window_size: [f64; 2], accuracy: f64) {
+        // Smaller, right under the score
+        let scale2 = window_size[1] / 768.0;
+        let font = &self.textures.score_font;
+        let text = format!("{:.2}%", accuracy * 100.0);
+        let y = font.height(scale2);
+        draw_text(font, &text, [window_size[0], y], scale2 * 0.6, 1.0, transform, g);
+    }
+
+    fn draw_gauge(
+        &mut self,
+        transform: math::Matrix2d,
+        g: &mut G,
+        stage_height: f64,
+        value: f64,
+    ) {
+        let draw_state = &DrawState::default();
+        let scale = stage_height / 480.0;
+        let scale2 = stage_height / 768.0;
+
+        let column_width_sum = (self.config.column_width.iter().sum::<u16>() as f64
+            + self.config.column_spacing.iter().sum::<u16>() as f64)
+            * scale;
+        let stage_r_width = self.textures.stage_right.get_width() as f64 * scale2;
+        let bar_x = self.config.column_start as f64 * scale + column_width_sum + stage_r_width;
+
+        // The scorebar images are horizontal, osu!mania turns them sideways so the bar fills
+        // upwards from the bottom of the screen
+        let bg = &*self.textures.scorebar_bg;
+        let bar_scale = stage_height / bg.get_width() as f64;
+        let transform = transform.trans(bar_x, stage_height).rot_deg(-90.0);
+
+        Image::new()
+            .rect([0.0, 0.0, stage_height, bg.get_height() as f64 * bar_scale])
+            .draw(bg, draw_state, transform, g);
+
+        let colour = self.textures.scorebar_colour.looped(self.created.elapsed());
+        let colour_w = colour.get_width() as f64;
+        Image::new()
+            .src_rect([0.0, 0.0, colour_w * value, colour.get_height() as f64])
+            .rect([0.0, 0.0, colour_w * value * bar_scale, colour.get_height() as f64 * bar_scale])
+            .draw(colour, draw_state, transform, g);
     }
 
     fn key_down(&mut self, column: usize) {
@@ -225,9 +382,10 @@ impl<G: Graphics> GameSkin<G> for OsuSkin<G> {
 }
 
 impl<G: Graphics> OsuSkin<G> {
+    /// `color` tints the note
     fn draw_note(
         &self,
-        draw_state: &DrawState,
+        color: Option<types::Color>,
         transform: math::Matrix2d,
         g: &mut G,
         stage_h: f64,
@@ -255,12 +413,14 @@ impl<G: Graphics> OsuSkin<G> {
         let note_y = hit_p * (1.0 - pos) - note_h;
 
         let note = &*self.textures.notes[column_index][0];
-        let note_img = Image::new().rect([note_x, note_y, note_w, note_h]);
-        note_img.draw(note, draw_state, transform, g);
+        let note_img = Image::new().maybe_color(color).rect([note_x, note_y, note_w, note_h]);
+        note_img.draw(note, &DrawState::default(), transform, g);
     }
+    /// `color` tints the head and tail, and `body_color` tints the body
     fn draw_long_note(
         &self,
-        draw_state: &DrawState,
+        color: Option<types::Color>,
+        body_color: Option<types::Color>,
         transform: math::Matrix2d,
         g: &mut G,
         stage_h: f64,
@@ -270,6 +430,7 @@ impl<G: Graphics> OsuSkin<G> {
     ) {
         // TODO mania-note#L is animatable
 
+        let draw_state = &DrawState::default();
         let scale = stage_h / 480.0;
         let scale2 = stage_h / 768.0; // long note body height when cascading is scaled with this
         let hit_p = self.config.hit_position as f64 * scale;
@@ -301,13 +462,14 @@ impl<G: Graphics> OsuSkin<G> {
         let note_head_y = bottom_y - note_end_h;
         let note_tail_y = top_y - note_end_h;
 
-        let note_head_img = Image::new().rect([note_x, note_head_y, note_w, note_end_h]);
-        let note_tail_img = Image::new().rect([note_x, note_tail_y, note_w, note_end_h]);
+        let note_head_img = Image::new().maybe_color(color).rect([note_x, note_head_y, note_w, note_end_h]);
+        let note_tail_img = Image::new().maybe_color(color).rect([note_x, note_tail_y, note_w, note_end_h]);
 
         match self.config.note_body_style[column_index] {
             // Note body image is stretched to the height of the note
             NoteBodyStyle::Stretch => {
                 let note_body_img = Image::new()
+                    .maybe_color(body_color)
                     .src_rect([
                         0.0,
                         0.0,
@@ -326,7 +488,7 @@ impl<G: Graphics> OsuSkin<G> {
             NoteBodyStyle::CascadeFromTop => {
                 let mut rect = [note_x, top_y - note_end_h / 2.0, note_w, note_body_h];
                 let mut i = 0.0;
-                let mut note_body_img = Image::new();
+                let mut note_body_img = Image::new().maybe_color(body_color);
                 while i < bottom_y - top_y - note_body_h {
                     note_body_img = note_body_img.rect(rect);
                     note_body_img.draw(note_body, draw_state, transform, g);
@@ -342,7 +504,7 @@ impl<G: Graphics> OsuSkin<G> {
             // Note body image is repeated, starting from the bottom
             NoteBodyStyle::CascadeFromBottom => {
                 let mut rect = [note_x, top_y - note_end_h / 2.0, note_w, note_body_h];
-                let mut note_body_img = Image::new();
+                let mut note_body_img = Image::new().maybe_color(body_color);
 
                 let offset = (real_bottom_y - top_y) % note_body_h;
 
@@ -357,7 +519,7 @@ impl<G: Graphics> OsuSkin<G> {
                 note_body_img = note_body_img.src_rect(src_rect).rect(mod_rect);
                 note_body_img.draw(note_body, draw_state, transform, g);
 
-                note_body_img = Image::new();
+                note_body_img = Image::new().maybe_color(body_color);
 
                 rect[1] += offset;
                 let mut i = offset;
@@ -389,8 +551,9 @@ impl<G: Graphics> OsuSkin<G> {
                 note_body_img = note_body_img.src_rect(src_rect).rect(mod_rect);
                 note_body_img.draw(note_body, draw_state, transform, g);
 
-                let note_body_img =
-                    Image::new().rect([note_x, top_y - note_end_h / 2.0, note_w, bottom_y - top_y]);
+                let note_body_img = Image::new()
+                    .maybe_color(body_color)
+                    .rect([note_x, top_y - note_end_h / 2.0, note_w, bottom_y - top_y]);
                 note_body_img.draw(note_body, draw_state, transform, g);
             }
         }
@@ -572,20 +735,36 @@ impl<G: Graphics> OsuSkin<G> {
         }
     }
 
-    fn draw_perfect(
+    /// Draw the hit image for `judgement` centered over the lanes at `ScorePosition`
+    fn draw_judgement_image(
         &self,
         draw_state: &DrawState,
         transform: math::Matrix2d,
-        size_scale: f64,
         g: &mut G,
         stage_h: f64,
+        judgement: Judgement,
         elapsed_time: time::Duration,
     ) {
-        let elapsed =
-            elapsed_time.as_secs() as f64 + elapsed_time.subsec_nanos() as f64 / 1e9;
-        let frame = (elapsed * 30.0) as usize % self.textures.hit300g.len();
+        let image = match judgement {
+            Judgement::Perfect => self.judgement_images.perfect,
+            Judgement::Good => self.judgement_images.good,
+            Judgement::Bad => self.judgement_images.bad,
+            Judgement::Miss => self.judgement_images.miss,
+        };
+        let animation = self.textures.hit_image(image);
+
+        // the "burst" animation, which misses don't get
+        let size_scale = if judgement == Judgement::Miss {
+            1.5
+        } else if elapsed_time <= time::Duration::from_millis(50) {
+            1.5 - elapsed_time.subsec_nanos() as f64 / 50_000_000.0 / 2.0
+        } else if elapsed_time <= time::Duration::from_millis(160) {
+            1.0
+        } else {
+            1.0 - (elapsed_time.subsec_nanos() - 160_000_000) as f64 / 150_000_000.0
+        };
 
-        let tx = &*self.textures.hit300g[frame];
+        let tx = animation.looped(elapsed_time);
 
         let scale = stage_h / 480.0;
         let scale2 = stage_h / 768.0;
@@ -603,31 +782,6 @@ impl<G: Graphics> OsuSkin<G> {
         img.draw(tx, draw_state, transform, g);
     }
 
-    fn draw_miss(
-        &self,
-        draw_state: &DrawState,
-        transform: math::Matrix2d,
-        g: &mut G,
-        stage_h: f64,
-    ) {
-        let tx = &*self.textures.miss[0];
-
-        let scale = stage_h / 480.0;
-        let scale2 = stage_h / 768.0;
-        let stage_width = (self.config.column_width.iter().sum::<u16>() as f64
-            + self.config.column_spacing.iter().sum::<u16>() as f64)
-            * scale;
-        let column_start = self.config.column_start as f64 * scale;
-
-        let tx_w = tx.get_width() as f64 * scale2;
-        let tx_h = tx.get_height() as f64 * scale2;
-        let tx_x = stage_width / 2.0 - tx_w / 2.0 + column_start;
-        let tx_y = self.config.score_position as f64 * scale - tx_h / 2.0;
-
-        let img = Image::new().rect([tx_x, tx_y, tx_w, tx_h]);
-        img.draw(tx, draw_state, transform, g);
-    }
-
     fn draw_hit_anims(
         &mut self,
         draw_state: &DrawState,
@@ -649,68 +803,55 @@ impl<G: Graphics> OsuSkin<G> {
 
             match *hit_anim {
                 HitAnimState::SingleNote(time) => {
-                    let frame = (time.elapsed() * 60).as_secs() as usize;
-                    if frame > self.textures.lighting_n.len() - 1 {
+                    if self.textures.lighting_n.finished(time.elapsed()) {
                         *hit_anim = HitAnimState::None;
                     } else {
-                        let hit_w = self.textures.lighting_n[frame].get_width() as f64 * scale2;
-                        let hit_h = self.textures.lighting_n[frame].get_height() as f64 * scale2;
+                        let tx = self.textures.lighting_n.once(time.elapsed());
+                        let hit_w = tx.get_width() as f64 * scale2;
+                        let hit_h = tx.get_height() as f64 * scale2;
                         let hit_img = Image::new().rect([
                             hit_x - hit_w / 2.0 + key_width / 2.0,
                             hit_p - hit_h / 2.0,
                             hit_w,
                             hit_h,
                         ]);
-                        hit_img.draw(
-                            &*self.textures.lighting_n[frame],
-                            draw_state,
-                            transform,
-                            g,
-                        );
+                        hit_img.draw(tx, draw_state, transform, g);
                     }
                 }
                 HitAnimState::LongNote(time) => {
-                    let frame = (time.elapsed() * 60).as_secs() as usize % self.textures.lighting_l.len();
-                    let hit_w = self.textures.lighting_l[frame].get_width() as f64 * scale2;
-                    let hit_h = self.textures.lighting_l[frame].get_height() as f64 * scale2;
+                    let tx = self.textures.lighting_l.looped(time.elapsed());
+                    let hit_w = tx.get_width() as f64 * scale2;
+                    let hit_h = tx.get_height() as f64 * scale2;
                     let hit_img = Image::new().rect([
                         hit_x - hit_w / 2.0 + key_width / 2.0,
                         hit_p - hit_h / 2.0,
                         hit_w,
                         hit_h,
                     ]);
-                    hit_img.draw(
-                        &*self.textures.lighting_l[frame],
-                        draw_state,
-                        transform,
-                        g,
-                    );
+                    hit_img.draw(tx, draw_state, transform, g);
                 }
                 HitAnimState::LongNoteFinal(start, end) => {
                     let diff = end - start;
                     let elapsed = start.elapsed();
+                    let frame_count = self.textures.lighting_l.frames.len() as u32;
 
-                    let anim_count1 = (diff * 60 / self.textures.lighting_l.len() as u32).as_secs();
-                    let anim_count2 = (elapsed * 60 / self.textures.lighting_l.len() as u32).as_secs();
+                    // Finish the loop the animation was on when the long note was let go
+                    let anim_count1 = (diff * 60 / frame_count).as_secs();
+                    let anim_count2 = (elapsed * 60 / frame_count).as_secs();
 
                     if anim_count2 > anim_count1 {
                         *hit_anim = HitAnimState::None;
                     } else {
-                        let frame = (elapsed * 60).as_secs() as usize % self.textures.lighting_l.len();
-                        let hit_w = self.textures.lighting_l[frame].get_width() as f64 * scale2;
-                        let hit_h = self.textures.lighting_l[frame].get_height() as f64 * scale2;
+                        let tx = self.textures.lighting_l.looped(elapsed);
+                        let hit_w = tx.get_width() as f64 * scale2;
+                        let hit_h = tx.get_height() as f64 * scale2;
                         let hit_img = Image::new().rect([
                             hit_x - hit_w / 2.0 + key_width / 2.0,
                             hit_p - hit_h / 2.0,
                             hit_w,
                             hit_h,
                         ]);
-                        hit_img.draw(
-                            &*self.textures.lighting_l[frame],
-                            draw_state,
-                            transform,
-                            g,
-                        );
+                        hit_img.draw(tx, draw_state, transform, g);
                     }
                 }
                 HitAnimState::None => (),
@@ -719,6 +860,28 @@ impl<G: Graphics> OsuSkin<G> {
     }
 }
 
+/// Draw `text` with `font`, skipping characters it doesn't have. `align` decides which part of
+/// the text goes at `pos[0]`: 0.0 is the left edge, 0.5 is the middle and 1.0 is the right edge.
+/// `pos[1]` is the top of the text.
+fn draw_text<G: Graphics>(
+    font: &OsuFont<G::Texture>,
+    text: &str,
+    pos: [f64; 2],
+    scale: f64,
+    align: f64,
+    transform: math::Matrix2d,
+    g: &mut G,
+) {
+    let draw_state = &DrawState::default();
+    let mut x = pos[0] - font.width(text, scale) * align;
+    for tx in text.chars().filter_map(|c| font.char_texture(c)) {
+        let w = tx.get_width() as f64 * scale;
+        let h = tx.get_height() as f64 * scale;
+        Image::new().rect([x, pos[1], w, h]).draw(tx, draw_state, transform, g);
+        x += w - font.overlap as f64 * scale;
+    }
+}
+
 #[derive(Debug)]
 enum OsuSkinParseError {
     NoDefaultTexture(String),
@@ -768,7 +931,7 @@ fn fix_alpha(img: &mut image::RgbaImage) {
     }
 }
 
-fn texture_from_path<F, T, P>(
+pub(super) fn texture_from_path<F, T, P>(
     factory: &mut F,
     path: P,
     texture_settings: &TextureSettings,
@@ -799,12 +962,17 @@ where
     })
 }
 
+/// Textures that were already loaded, by name, with where each frame was found so the report
+/// still lists them when they're used again
+type TextureCache<T> = HashMap<String, (Rc<[Rc<T>]>, Vec<TextureSource>)>;
+
 /// Load an animatable skin element's textures
 ///
 /// This function takes the basename and tries different paths until it finds one that exists
 fn load_texture_anim<F, T>(
     factory: &mut F,
-    cache: &mut HashMap<String, Rc<[Rc<T>]>>,
+    cache: &mut TextureCache<T>,
+    report: &mut SkinReport,
     dir: &path::Path,
     default_dir: &path::Path,
     names: &(&'static str, String),
@@ -815,14 +983,16 @@ where
     T::Error: ToString,
 {
     let mut textures = Vec::new();
+    let mut sources = Vec::new();
     let mut path;
 
     macro_rules! repetitive_code {
-        // $dir should be a path::Path
-        ($(($dir:ident, $name:expr)),*) => {$(
+        // $dir should be a path::Path, $source a TextureSource variant
+        ($(($dir:ident, $name:expr, $source:path)),*) => {$(
 
             // Check the cache
-            if let Some(texture) = cache.get(&$name) {
+            if let Some((texture, texture_sources)) = cache.get(&$name) {
+                report.textures.extend(texture_sources.iter().map(|s| (names.0.to_owned(), s.clone())));
                 return Ok(Rc::clone(texture));
             }
 
@@ -831,34 +1001,42 @@ where
             // Check for an animation sequence
             path = $dir.join($name + "-0.png");
             if path.exists() {
+                sources.push($source(path.clone()));
                 textures.push(Rc::new(texture_from_path(factory, &path, texture_settings)?));
                 let mut n = 1;
                 loop {
                     path = $dir.join(format!("{}-{}.png", $name, n));
                     if !path.exists() { break; }
+                    sources.push($source(path.clone()));
                     textures.push(Rc::new(texture_from_path(factory, &path, texture_settings)?));
                     n += 1;
                 }
+                report.textures.extend(sources.iter().map(|s| (names.0.to_owned(), s.clone())));
                 let anim = Rc::from(textures);
-                cache.insert($name, Rc::clone(&anim));
+                cache.insert($name, (Rc::clone(&anim), sources));
                 return Ok(anim);
             }
 
             // Check for static image
             path = $dir.join($name + ".png");
             if path.exists() {
+                report.textures.push((names.0.to_owned(), $source(path.clone())));
                 // help
                 let texture = Rc::new(texture_from_path(factory, &path, texture_settings)?);
                 let anim = Rc::from(&[texture][..]);
-                cache.insert($name, Rc::clone(&anim));
+                cache.insert($name, (Rc::clone(&anim), vec![$source(path)]));
                 return Ok(anim);
             }
         )*}
     }
 
     // Check the skin directory, then the default skin directory
-    repetitive_code!((dir, names.1.clone()), (default_dir, names.0.to_owned()));
+    repetitive_code!(
+        (dir, names.1.clone(), TextureSource::Skin),
+        (default_dir, names.0.to_owned(), TextureSource::Default)
+    );
 
+    report.textures.push((names.0.to_owned(), TextureSource::Missing));
     Err(OsuSkinParseError::NoDefaultTexture(String::from(names.0)).into())
 }
 
@@ -867,7 +1045,8 @@ where
 /// This function takes the basename and tries different paths until it finds one that exists
 fn load_texture<F, T>(
     factory: &mut F,
-    cache: &mut HashMap<String, Rc<[Rc<T>]>>,
+    cache: &mut TextureCache<T>,
+    report: &mut SkinReport,
     dir: &path::Path,
     default_dir: &path::Path,
     names: &(&'static str, String),
@@ -878,35 +1057,79 @@ where
     T::Error: ToString,
 {
     macro_rules! repetitive_code {
-        // $dir should be a path::Path
-        ($(($dir:ident, $name:expr)),*) => {$(
+        // $dir should be a path::Path, $source a TextureSource variant
+        ($(($dir:ident, $name:expr, $source:path)),*) => {$(
 
             // Check the cache
-            if let Some(texture) = cache.get(&$name) {
+            if let Some((texture, sources)) = cache.get(&$name) {
+                report.textures.push((names.0.to_owned(), sources[0].clone()));
                 return Ok(Rc::clone(&texture[0]));
             }
 
             // TODO can these join's be optimized? how much time does it take to allocate the pathbuf?
             let path = $dir.join($name + ".png");
             if path.exists() {
-                let texture = texture_from_path(factory, path, texture_settings)?;
+                report.textures.push((names.0.to_owned(), $source(path.clone())));
+                let texture = texture_from_path(factory, &path, texture_settings)?;
                 let rc = Rc::new(texture);
-                cache.insert($name, Rc::from(&[Rc::clone(&rc)][..]));
+                cache.insert($name, (Rc::from(&[Rc::clone(&rc)][..]), vec![$source(path)]));
                 return Ok(rc);
             }
         )*}
     }
 
     // Check the skin directory, then the default skin directory
-    repetitive_code!((dir, names.1.clone()), (default_dir, names.0.to_owned()));
+    repetitive_code!(
+        (dir, names.1.clone(), TextureSource::Skin),
+        (default_dir, names.0.to_owned(), TextureSource::Default)
+    );
 
+    report.textures.push((names.0.to_owned(), TextureSource::Missing));
     Err(OsuSkinParseError::NoDefaultTexture(String::from(names.0)).into())
 }
 
+/// The default skin's digit images, which every font falls back to
+const DEFAULT_DIGIT_NAMES: [&str; 10] = [
+    "score-0", "score-1", "score-2", "score-3", "score-4",
+    "score-5", "score-6", "score-7", "score-8", "score-9",
+];
+
+/// Load the images named `{prefix}-0` to `{prefix}-9`, `{prefix}-dot` and `{prefix}-percent`,
+/// as a font with no overlap
+fn load_font<F, T>(
+    factory: &mut F,
+    cache: &mut TextureCache<T>,
+    report: &mut SkinReport,
+    dir: &path::Path,
+    default_dir: &path::Path,
+    prefix: &str,
+    texture_settings: &TextureSettings,
+) -> Result<OsuFont<T>, ParseError>
+where
+    T: CreateTexture<F>,
+    T::Error: ToString,
+{
+    let mut digits = Vec::with_capacity(10);
+    for (n, &default_name) in DEFAULT_DIGIT_NAMES.iter().enumerate() {
+        let names = (default_name, format!("{}-{}", prefix, n));
+        digits.push(load_texture(factory, cache, report, dir, default_dir, &names, texture_settings)?);
+    }
+    let dot_names = ("score-dot", format!("{}-dot", prefix));
+    let percent_names = ("score-percent", format!("{}-percent", prefix));
+    Ok(OsuFont {
+        digits,
+        dot: load_texture(factory, cache, report, dir, default_dir, &dot_names, texture_settings)?,
+        percent: load_texture(factory, cache, report, dir, default_dir, &percent_names, texture_settings)?,
+        overlap: 0,
+    })
+}
+
 pub fn from_path<F, G>(
     factory: &mut F,
     dir: &path::Path,
     default_dir: &path::Path,
+    judgement_images: OsuJudgementImages,
+    report: &mut SkinReport,
 ) -> Result<Box<dyn GameSkin<G>>, ParseError>
 where
     G: Graphics + 'static,
@@ -983,6 +1206,11 @@ where
     let mut stage_left_name = double!("mania-stage-left");
     let mut stage_right_name = double!("mania-stage-right");
     let mut stage_bottom_name = double!("mania-stage-bottom");
+    let scorebar_bg_name = double!("scorebar-bg");
+    let scorebar_colour_name = double!("scorebar-colour");
+
+    // skin.ini image names are relative paths, which use backslashes since osu is a Windows game
+    let image_name = |value: &str| value.replace('\\', "/");
 
     // default values
     let mut column_start = 136;
@@ -991,14 +1219,22 @@ where
     let mut colour_column_line = [255; 4];
     let mut column_spacing = [0; 6];
     let mut colour_light = [[255, 255, 255]; 7];
+    let mut barline_height = 1.2;
+    let mut colour_barline = [255, 255, 255, 150];
+    let mut snap_palette = DEFAULT_SNAP_PALETTE;
     let mut hit_position = 402;
     let mut score_position = 240; // idk TODO
+    let mut combo_position = 111;
     let mut light_position = 413;
+    let mut score_prefix = String::from("score");
+    let mut combo_prefix = String::from("score");
+    let mut score_overlap = 0;
+    let mut combo_overlap = 0;
     let mut note_body_style = [NoteBodyStyle::CascadeFromTop; 7];
 
     // parse skin.ini
     if config_path.exists() {
-        let config_file = File::open(config_path)
+        let config_file = File::open(&config_path)
             .map_err(|e| ParseError::Io(String::from("Error opening config file"), e))?;
         let config_reader = BufReader::new(&config_file);
         let mut section = String::from("General");
@@ -1033,6 +1269,7 @@ where
                                 line_number,
                                 e
                             );
+                            report.malformed_values.push((config_path.clone(), line_number, format!("{}: {}", $value, e)));
                             continue;
                         }
                     }
@@ -1051,6 +1288,13 @@ where
             };
             match key {
                 "Keys" => keys = parse!(value),
+                _ if section == "Fonts" => match key {
+                    "ScorePrefix" => score_prefix = image_name(value),
+                    "ComboPrefix" => combo_prefix = image_name(value),
+                    "ScoreOverlap" => score_overlap = parse!(value),
+                    "ComboOverlap" => combo_overlap = parse!(value),
+                    _ => report.unrecognised_keys.push((config_path.clone(), line_number, key.to_owned())),
+                },
                 _ => {
                     if keys == 7 && section == "Mania" {
                         macro_rules! prop_name {
@@ -1081,7 +1325,7 @@ where
                                         prop_name!($name2, $baseidx2) => $varname2[0] = $value2,
                                         $(prop_name!($name2, $idx2) => $varname2[$idx2 - $baseidx2] = $value2,)*
                                     )*
-                                    _ => (),
+                                    _ => report.unrecognised_keys.push((config_path.clone(), line_number, key.to_owned())),
                                 }
                             };
                         }
@@ -1101,6 +1345,11 @@ where
                                         "Malformed value in line {} of skin.ini (not enough fields), ignoring",
                                         line_number
                                     );
+                                    report.malformed_values.push((
+                                        config_path.clone(),
+                                        line_number,
+                                        String::from("not enough fields"),
+                                    ));
                                     continue;
                                 } else {
                                     a
@@ -1111,33 +1360,53 @@ where
                             "ColumnStart" => column_start = parse!(value),
                             "HitPosition" => hit_position = parse!(value),
                             "ScorePosition" => score_position = parse!(value),
+                            "ComboPosition" => combo_position = parse!(value),
                             "LightPosition" => light_position = parse!(value),
                             "ColumnWidth" => column_width = csv![column_width; 7],
                             "ColumnLineWidth" => column_line_width = csv![column_line_width; 8],
                             "ColourColumnLine" => colour_column_line = csv![colour_column_line; 4],
                             "ColumnSpacing" => column_spacing = csv![column_spacing; 6],
+                            "BarlineHeight" => barline_height = parse!(value),
+                            "ColourBarline" => colour_barline = csv![colour_barline; 4],
                             "NoteBodyStyle" => note_body_style = [parse!(value); 7],
-                            "Hit0" => miss_name.1 = value.to_owned(),
-                            "Hit50" => hit50_name.1 = value.to_owned(),
-                            "Hit100" => hit100_name.1 = value.to_owned(),
-                            "Hit200" => hit200_name.1 = value.to_owned(),
-                            "Hit300" => hit300_name.1 = value.to_owned(),
-                            "Hit300g" => hit300g_name.1 = value.to_owned(),
-                            "StageHint" => stage_hint_name.1 = value.to_owned(),
-                            "StageLeft" => stage_left_name.1 = value.to_owned(),
-                            "StageRight" => stage_right_name.1 = value.to_owned(),
-                            "StageBottom" => stage_bottom_name.1 = value.to_owned(),
-                            "StageLight" => stage_light_name.1 = value.to_owned(),
-                            "LightingN" => lighting_n_name.1 = value.to_owned(),
-                            "LightingL" => lighting_l_name.1 = value.to_owned(),
+                            "Hit0" => miss_name.1 = image_name(value),
+                            "Hit50" => hit50_name.1 = image_name(value),
+                            "Hit100" => hit100_name.1 = image_name(value),
+                            "Hit200" => hit200_name.1 = image_name(value),
+                            "Hit300" => hit300_name.1 = image_name(value),
+                            "Hit300g" => hit300g_name.1 = image_name(value),
+                            "StageHint" => stage_hint_name.1 = image_name(value),
+                            "StageLeft" => stage_left_name.1 = image_name(value),
+                            "StageRight" => stage_right_name.1 = image_name(value),
+                            "StageBottom" => stage_bottom_name.1 = image_name(value),
+                            "StageLight" => stage_light_name.1 = image_name(value),
+                            "LightingN" => lighting_n_name.1 = image_name(value),
+                            "LightingL" => lighting_l_name.1 = image_name(value),
+
+                            // ColourSnap followed by a division like 4 or 16, or Other
+                            k if k.starts_with("ColourSnap") => {
+                                let division = &k["ColourSnap".len()..];
+                                let index = if division == "Other" {
+                                    Some(SNAP_DIVISIONS.len())
+                                } else {
+                                    division.parse().ok().and_then(|d| SNAP_DIVISIONS.iter().position(|&s| s == d))
+                                };
+                                match index {
+                                    Some(i) => {
+                                        let [r, g, b]: [u8; 3] = csv![[255; 3]; 3];
+                                        snap_palette[i] = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+                                    }
+                                    None => report.unrecognised_keys.push((config_path.clone(), line_number, key.to_owned())),
+                                }
+                            }
 
                             k => enumerate_match! { k,
-                                .{KeyImage#} => keys_name = value.to_owned(), [0 1 2 3 4 5 6],
-                                .{KeyImage#D} => keys_d_name = value.to_owned(), [0 1 2 3 4 5 6],
-                                .{NoteImage#} => notes_name = value.to_owned(), [0 1 2 3 4 5 6],
-                                .{NoteImage#H} => lns_head_name = value.to_owned(), [0 1 2 3 4 5 6],
-                                .{NoteImage#L} => lns_body_name = value.to_owned(), [0 1 2 3 4 5 6],
-                                .{NoteImage#T} => lns_tail_name = value.to_owned(), [0 1 2 3 4 5 6],
+                                .{KeyImage#} => keys_name = image_name(value), [0 1 2 3 4 5 6],
+                                .{KeyImage#D} => keys_d_name = image_name(value), [0 1 2 3 4 5 6],
+                                .{NoteImage#} => notes_name = image_name(value), [0 1 2 3 4 5 6],
+                                .{NoteImage#H} => lns_head_name = image_name(value), [0 1 2 3 4 5 6],
+                                .{NoteImage#L} => lns_body_name = image_name(value), [0 1 2 3 4 5 6],
+                                .{NoteImage#T} => lns_tail_name = image_name(value), [0 1 2 3 4 5 6],
                                 == // separator between the image file specifying properties above, and the other properties below
                                 .{ColourLight#} => colour_light = csv![[0; 3]; 3], [1 2 3 4 5 6 7],
                                 .{NoteBodyStyle#} => note_body_style = parse!(value), [0 1 2 3 4 5 6],
@@ -1153,67 +1422,77 @@ where
 
     // load all the textures into the gpu
 
-    let miss = load_texture_anim(factory, &mut cache, dir, default_dir, &miss_name, &texture_settings)?;
-    let hit50 = load_texture_anim(factory, &mut cache, dir, default_dir, &hit50_name, &texture_settings)?;
-    let hit100 = load_texture_anim(factory, &mut cache, dir, default_dir, &hit100_name, &texture_settings)?;
-    let hit200 = load_texture_anim(factory, &mut cache, dir, default_dir, &hit200_name, &texture_settings)?;
-    let hit300 = load_texture_anim(factory, &mut cache, dir, default_dir, &hit300_name, &texture_settings)?;
-    let hit300g = load_texture_anim(factory, &mut cache, dir, default_dir, &hit300g_name, &texture_settings)?;
-    let stage_light = load_texture_anim(factory, &mut cache, dir, default_dir, &stage_light_name, &texture_settings)?;
-    let lighting_n = load_texture_anim(factory, &mut cache, dir, default_dir, &lighting_n_name, &texture_settings)?;
-    let lighting_l = load_texture_anim(factory, &mut cache, dir, default_dir, &lighting_l_name, &texture_settings)?;
-    let keys = [load_texture(factory, &mut cache, dir, default_dir, &keys_name[0], &texture_settings)?,
-                load_texture(factory, &mut cache, dir, default_dir, &keys_name[1], &texture_settings)?,
-                load_texture(factory, &mut cache, dir, default_dir, &keys_name[2], &texture_settings)?,
-                load_texture(factory, &mut cache, dir, default_dir, &keys_name[3], &texture_settings)?,
-                load_texture(factory, &mut cache, dir, default_dir, &keys_name[4], &texture_settings)?,
-                load_texture(factory, &mut cache, dir, default_dir, &keys_name[5], &texture_settings)?,
-                load_texture(factory, &mut cache, dir, default_dir, &keys_name[6], &texture_settings)?];
-
-    let keys_d = [load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[0], &texture_settings)?,
-                  load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[1], &texture_settings)?,
-                  load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[2], &texture_settings)?,
-                  load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[3], &texture_settings)?,
-                  load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[4], &texture_settings)?,
-                  load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[5], &texture_settings)?,
-                  load_texture(factory, &mut cache, dir, default_dir, &keys_d_name[6], &texture_settings)?];
-
-    let notes = [load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[0], &texture_settings)?,
-                 load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[1], &texture_settings)?,
-                 load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[2], &texture_settings)?,
-                 load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[3], &texture_settings)?,
-                 load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[4], &texture_settings)?,
-                 load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[5], &texture_settings)?,
-                 load_texture_anim(factory, &mut cache, dir, default_dir, &notes_name[6], &texture_settings)?];
-
-    let long_notes_head = [load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[0], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[1], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[2], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[3], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[4], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[5], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_head_name[6], &texture_settings)?];
-
-    let long_notes_body = [load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[0], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[1], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[2], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[3], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[4], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[5], &texture_settings)?,
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_body_name[6], &texture_settings)?];
-
-    let long_notes_tail = [load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[0], &texture_settings).ok(),
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[1], &texture_settings).ok(),
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[2], &texture_settings).ok(),
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[3], &texture_settings).ok(),
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[4], &texture_settings).ok(),
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[5], &texture_settings).ok(),
-                           load_texture_anim(factory, &mut cache, dir, default_dir, &lns_tail_name[6], &texture_settings).ok()];
-
-    let stage_hint = load_texture_anim(factory, &mut cache, dir, default_dir, &stage_hint_name, &texture_settings)?;
-    let stage_left = load_texture(factory, &mut cache, dir, default_dir, &stage_left_name, &texture_settings)?;
-    let stage_right = load_texture(factory, &mut cache, dir, default_dir, &stage_right_name, &texture_settings)?;
-    let stage_bottom = load_texture_anim(factory, &mut cache, dir, default_dir, &stage_bottom_name, &texture_settings).ok();
+    let miss = load_texture_anim(factory, &mut cache, report, dir, default_dir, &miss_name, &texture_settings)?;
+    let hit50 = load_texture_anim(factory, &mut cache, report, dir, default_dir, &hit50_name, &texture_settings)?;
+    let hit100 = load_texture_anim(factory, &mut cache, report, dir, default_dir, &hit100_name, &texture_settings)?;
+    let hit200 = load_texture_anim(factory, &mut cache, report, dir, default_dir, &hit200_name, &texture_settings)?;
+    let hit300 = load_texture_anim(factory, &mut cache, report, dir, default_dir, &hit300_name, &texture_settings)?;
+    let hit300g = load_texture_anim(factory, &mut cache, report, dir, default_dir, &hit300g_name, &texture_settings)?;
+    let stage_light = load_texture_anim(factory, &mut cache, report, dir, default_dir, &stage_light_name, &texture_settings)?;
+    let lighting_n = load_texture_anim(factory, &mut cache, report, dir, default_dir, &lighting_n_name, &texture_settings)?;
+    let lighting_l = load_texture_anim(factory, &mut cache, report, dir, default_dir, &lighting_l_name, &texture_settings)?;
+    let keys = [load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[0], &texture_settings)?,
+                load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[1], &texture_settings)?,
+                load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[2], &texture_settings)?,
+                load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[3], &texture_settings)?,
+                load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[4], &texture_settings)?,
+                load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[5], &texture_settings)?,
+                load_texture(factory, &mut cache, report, dir, default_dir, &keys_name[6], &texture_settings)?];
+
+    let keys_d = [load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[0], &texture_settings)?,
+                  load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[1], &texture_settings)?,
+                  load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[2], &texture_settings)?,
+                  load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[3], &texture_settings)?,
+                  load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[4], &texture_settings)?,
+                  load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[5], &texture_settings)?,
+                  load_texture(factory, &mut cache, report, dir, default_dir, &keys_d_name[6], &texture_settings)?];
+
+    let notes = [load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[0], &texture_settings)?,
+                 load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[1], &texture_settings)?,
+                 load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[2], &texture_settings)?,
+                 load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[3], &texture_settings)?,
+                 load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[4], &texture_settings)?,
+                 load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[5], &texture_settings)?,
+                 load_texture_anim(factory, &mut cache, report, dir, default_dir, &notes_name[6], &texture_settings)?];
+
+    let long_notes_head = [load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[0], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[1], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[2], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[3], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[4], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[5], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_head_name[6], &texture_settings)?];
+
+    let long_notes_body = [load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[0], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[1], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[2], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[3], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[4], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[5], &texture_settings)?,
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_body_name[6], &texture_settings)?];
+
+    let long_notes_tail = [load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[0], &texture_settings).ok(),
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[1], &texture_settings).ok(),
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[2], &texture_settings).ok(),
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[3], &texture_settings).ok(),
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[4], &texture_settings).ok(),
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[5], &texture_settings).ok(),
+                           load_texture_anim(factory, &mut cache, report, dir, default_dir, &lns_tail_name[6], &texture_settings).ok()];
+
+    let stage_hint = load_texture_anim(factory, &mut cache, report, dir, default_dir, &stage_hint_name, &texture_settings)?;
+    let stage_left = load_texture(factory, &mut cache, report, dir, default_dir, &stage_left_name, &texture_settings)?;
+    let stage_right = load_texture(factory, &mut cache, report, dir, default_dir, &stage_right_name, &texture_settings)?;
+    let stage_bottom = load_texture_anim(factory, &mut cache, report, dir, default_dir, &stage_bottom_name, &texture_settings).ok();
+    let scorebar_bg = load_texture(factory, &mut cache, report, dir, default_dir, &scorebar_bg_name, &texture_settings)?;
+    let scorebar_colour = load_texture_anim(factory, &mut cache, report, dir, default_dir, &scorebar_colour_name, &texture_settings)?;
+    let score_font = OsuFont {
+        overlap: score_overlap,
+        ..load_font(factory, &mut cache, report, dir, default_dir, &score_prefix, &texture_settings)?
+    };
+    let combo_font = OsuFont {
+        overlap: combo_overlap,
+        ..load_font(factory, &mut cache, report, dir, default_dir, &combo_prefix, &texture_settings)?
+    };
 
     let smallest_note_width;
     let smallest_note_height;
@@ -1229,15 +1508,15 @@ where
         smallest_note_height / smallest_note_width * *column_width.iter().min().unwrap() as f64;
     Ok(Box::new(OsuSkin {
         textures: OsuSkinTextures {
-            miss,
-            hit50,
-            hit100,
-            hit200,
-            hit300,
-            hit300g,
+            miss: Animation::new(miss, JUDGEMENT_FPS),
+            hit50: Animation::new(hit50, JUDGEMENT_FPS),
+            hit100: Animation::new(hit100, JUDGEMENT_FPS),
+            hit200: Animation::new(hit200, JUDGEMENT_FPS),
+            hit300: Animation::new(hit300, JUDGEMENT_FPS),
+            hit300g: Animation::new(hit300g, JUDGEMENT_FPS),
             stage_light,
-            lighting_n,
-            lighting_l,
+            lighting_n: Animation::new(lighting_n, LIGHTING_FPS),
+            lighting_l: Animation::new(lighting_l, LIGHTING_FPS),
             keys,
             keys_d,
             notes,
@@ -1248,6 +1527,10 @@ where
             stage_left,
             stage_right,
             stage_bottom,
+            scorebar_bg,
+            scorebar_colour: Animation::new(scorebar_colour, SCOREBAR_FPS),
+            score_font,
+            combo_font,
         },
 
         anim_states: OsuAnimStates {
@@ -1263,11 +1546,17 @@ where
             colour_column_line,
             hit_position,
             score_position,
+            combo_position,
             light_position,
             width_for_note_height_scale,
             note_body_style,
             colour_light,
+            barline_height,
+            colour_barline,
+            snap_palette,
         },
+        judgement_images,
         judgement: None,
+        created: time::Instant::now(),
     }))
 }
@@ -0,0 +1,168 @@
+//! Notices when a skin's files change, so it can be loaded again while it's being edited
+
+use std::{fs, io, path, time};
+
+use crate::config;
+
+/// How often the skin's directory is looked at
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+/// Enough about a directory's contents to tell when something in it changed. Adding or removing
+/// a file changes the count, and most edits change the size or the modification time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Snapshot {
+    file_count: usize,
+    total_size: u64,
+    latest_modification: Option<time::SystemTime>,
+}
+
+impl Snapshot {
+    /// Look at every file in `dir` and its subdirectories
+    fn take(dir: &path::Path) -> io::Result<Self> {
+        let mut snapshot = Snapshot {
+            file_count: 0,
+            total_size: 0,
+            latest_modification: None,
+        };
+        snapshot.add_dir(dir)?;
+        Ok(snapshot)
+    }
+
+    fn add_dir(&mut self, dir: &path::Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            // Directories' modification times change when files are added or removed
+            if let Ok(modified) = metadata.modified() {
+                self.latest_modification = self.latest_modification.max(Some(modified));
+            }
+            if metadata.is_dir() {
+                self.add_dir(&entry.path())?;
+            } else {
+                self.file_count += 1;
+                self.total_size += metadata.len();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Watches a skin's directory, and the directories of the skins it falls back to, by looking at
+/// them every so often
+#[derive(Debug)]
+pub struct SkinWatcher {
+    skin_entry: config::SkinEntry,
+    /// Each directory and its snapshot, `None` if it couldn't be read
+    snapshots: Vec<(path::PathBuf, Option<Snapshot>)>,
+    last_poll: time::Instant,
+}
+
+impl SkinWatcher {
+    pub fn new(skin_entry: &config::SkinEntry, config: &config::Config) -> Self {
+        SkinWatcher {
+            skin_entry: skin_entry.clone(),
+            snapshots: snapshots(skin_entry, config),
+            last_poll: time::Instant::now(),
+        }
+    }
+
+    /// Whether anything in the skin's directories changed since the last time this returned true,
+    /// or since the watcher was created. Cheap to call on every update, the directories are only
+    /// looked at twice a second.
+    pub fn changed(&mut self, config: &config::Config) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = time::Instant::now();
+        self.poll(config)
+    }
+
+    /// Look at the directories right away. They're worked out again each time, since editing a
+    /// native skin's manifest can change what it falls back to.
+    fn poll(&mut self, config: &config::Config) -> bool {
+        let snapshots = snapshots(&self.skin_entry, config);
+        let changed = snapshots != self.snapshots;
+        self.snapshots = snapshots;
+        changed
+    }
+}
+
+fn snapshots(skin_entry: &config::SkinEntry, config: &config::Config) -> Vec<(path::PathBuf, Option<Snapshot>)> {
+    super::skin_dirs(skin_entry, config)
+        .into_iter()
+        .map(|dir| {
+            let snapshot = Snapshot::take(&dir).ok();
+            (dir, snapshot)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory that's deleted when the test ends
+    struct TempDir(path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("remani-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn notices_new_and_edited_files() {
+        let dir = TempDir::new("watcher");
+        fs::write(dir.0.join("skin.ini"), "[General]\n").unwrap();
+        let config = config::default_config();
+        let mut watcher = SkinWatcher::new(&config::SkinEntry::Osu(dir.0.clone()), &config);
+        assert!(!watcher.poll(&config));
+
+        fs::create_dir(dir.0.join("hit")).unwrap();
+        fs::write(dir.0.join("hit").join("mania-hit0.png"), [0; 4]).unwrap();
+        assert!(watcher.poll(&config));
+        assert!(!watcher.poll(&config));
+
+        fs::write(dir.0.join("skin.ini"), "[General]\nName: test\n").unwrap();
+        assert!(watcher.poll(&config));
+        assert!(!watcher.poll(&config));
+    }
+
+    #[test]
+    fn notices_directory_appearing() {
+        let dir = TempDir::new("watcher-missing");
+        let skin_dir = dir.0.join("skin");
+        let config = config::default_config();
+        let mut watcher = SkinWatcher::new(&config::SkinEntry::Native(skin_dir.clone()), &config);
+        assert!(!watcher.poll(&config));
+
+        fs::create_dir(&skin_dir).unwrap();
+        assert!(watcher.poll(&config));
+    }
+
+    #[test]
+    fn notices_fallback_skin_changing() {
+        let dir = TempDir::new("watcher-fallback");
+        let (skin_dir, fallback_dir) = (dir.0.join("skin"), dir.0.join("fallback"));
+        fs::create_dir(&skin_dir).unwrap();
+        fs::create_dir(&fallback_dir).unwrap();
+        fs::write(skin_dir.join("skin.toml"), "fallback = \"base\"\n").unwrap();
+        let mut config = config::default_config();
+        config.game.skins.push(("base".into(), config::SkinEntry::Osu(fallback_dir.clone())));
+        let mut watcher = SkinWatcher::new(&config::SkinEntry::Native(skin_dir), &config);
+        assert!(!watcher.poll(&config));
+
+        fs::write(fallback_dir.join("skin.ini"), "[General]\n").unwrap();
+        assert!(watcher.poll(&config));
+        assert!(!watcher.poll(&config));
+    }
+}
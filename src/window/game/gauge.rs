@@ -0,0 +1,192 @@
+//! The health gauge, which goes up and down with judgements and decides whether the player failed
+
+use std::time::{Duration, Instant};
+
+use crate::{config::GaugeType, judgement::Judgement};
+
+/// The normal gauge has to be at or above this at the end of the chart to clear it.
+const NORMAL_CLEAR_THRESHOLD: f64 = 0.8;
+
+/// The normal gauge never drops below this, so it can't fail before the chart ends.
+const NORMAL_MIN: f64 = 0.02;
+
+/// How much the whole chart played perfectly adds to the normal gauge. Spread out evenly over
+/// every note.
+const NORMAL_TOTAL_GAIN: f64 = 1.6;
+
+/// How long after the gauge runs out the result is shown
+const FAIL_DELAY: Duration = Duration::from_secs(2);
+
+pub struct Gauge {
+    gauge_type: GaugeType,
+    /// From 0.0 to 1.0
+    value: f64,
+    /// How much a perfect adds to the gauge
+    gain: f64,
+}
+
+impl Gauge {
+    /// `note_count` is used to scale how much the gauge goes up per note, so long charts don't
+    /// fill it too quickly.
+    pub fn new(gauge_type: GaugeType, note_count: usize) -> Self {
+        let note_count = note_count.max(1) as f64;
+        let (value, gain) = match gauge_type {
+            GaugeType::Normal => (0.2, NORMAL_TOTAL_GAIN / note_count),
+            GaugeType::Hard => (1.0, 0.0016),
+            GaugeType::NoFail => (1.0, NORMAL_TOTAL_GAIN / note_count),
+        };
+        Gauge { gauge_type, value, gain }
+    }
+
+    /// Called whenever a note is judged
+    pub fn update(&mut self, judgement: Judgement) {
+        let change = match (self.gauge_type, judgement) {
+            (_, Judgement::Perfect) => self.gain,
+            (_, Judgement::Good) => self.gain / 2.0,
+            (GaugeType::Hard, Judgement::Bad) => -0.05,
+            (GaugeType::Hard, Judgement::Miss) => -0.09,
+            (_, Judgement::Bad) => -0.02,
+            (_, Judgement::Miss) => -0.06,
+        };
+        let min = match self.gauge_type {
+            GaugeType::Normal => NORMAL_MIN,
+            _ => 0.0,
+        };
+        // once the hard gauge hits zero, it stays there
+        if !self.failed() {
+            self.value = (self.value + change).max(min).min(1.0);
+        }
+    }
+
+    /// From 0.0 to 1.0
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Whether the player failed in the middle of the chart
+    pub fn failed(&self) -> bool {
+        match self.gauge_type {
+            GaugeType::Hard => self.value <= 0.0,
+            GaugeType::Normal | GaugeType::NoFail => false,
+        }
+    }
+
+    /// Whether the player cleared the chart, assuming it has ended
+    pub fn cleared(&self) -> bool {
+        match self.gauge_type {
+            GaugeType::Normal => self.value >= NORMAL_CLEAR_THRESHOLD,
+            GaugeType::Hard => !self.failed(),
+            GaugeType::NoFail => true,
+        }
+    }
+}
+
+/// When the player failed. Timed with the wall clock rather than the game time, since the music is
+/// paused when the player fails and the game time stops with it.
+#[derive(Copy, Clone, Debug)]
+pub struct Failure {
+    at: Instant,
+}
+
+impl Failure {
+    pub fn new(at: Instant) -> Self {
+        Failure { at }
+    }
+
+    /// Whether it's time to stop and show the result
+    pub fn result_due(&self, now: Instant) -> bool {
+        now >= self.at + FAIL_DELAY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_value(gauge: &Gauge, value: f64) {
+        assert!((gauge.value() - value).abs() < 1e-9, "gauge is {}, expected {}", gauge.value(), value);
+    }
+
+    fn judge(gauge: &mut Gauge, judgement: Judgement, count: usize) {
+        for _ in 0..count {
+            gauge.update(judgement);
+        }
+    }
+
+    #[test]
+    fn test_normal_gauge() {
+        let mut gauge = Gauge::new(GaugeType::Normal, 100);
+        assert_value(&gauge, 0.2);
+        gauge.update(Judgement::Perfect);
+        assert_value(&gauge, 0.2 + 0.016);
+        gauge.update(Judgement::Good);
+        assert_value(&gauge, 0.2 + 0.024);
+        gauge.update(Judgement::Bad);
+        assert_value(&gauge, 0.2 + 0.004);
+        gauge.update(Judgement::Miss);
+        assert_value(&gauge, 0.2 - 0.056);
+        assert!(!gauge.cleared());
+
+        judge(&mut gauge, Judgement::Perfect, 100);
+        assert_value(&gauge, 1.0);
+        assert!(gauge.cleared());
+    }
+
+    #[test]
+    fn test_normal_gauge_never_fails() {
+        let mut gauge = Gauge::new(GaugeType::Normal, 100);
+        judge(&mut gauge, Judgement::Miss, 100);
+        assert_value(&gauge, NORMAL_MIN);
+        assert!(!gauge.failed());
+        assert!(!gauge.cleared());
+    }
+
+    #[test]
+    fn test_hard_gauge() {
+        let mut gauge = Gauge::new(GaugeType::Hard, 100);
+        assert_value(&gauge, 1.0);
+        gauge.update(Judgement::Bad);
+        assert_value(&gauge, 0.95);
+        gauge.update(Judgement::Miss);
+        assert_value(&gauge, 0.86);
+        gauge.update(Judgement::Perfect);
+        assert_value(&gauge, 0.8616);
+        gauge.update(Judgement::Good);
+        assert_value(&gauge, 0.8624);
+        assert!(gauge.cleared());
+    }
+
+    #[test]
+    fn test_hard_gauge_stays_failed() {
+        let mut gauge = Gauge::new(GaugeType::Hard, 100);
+        judge(&mut gauge, Judgement::Miss, 12);
+        assert_value(&gauge, 0.0);
+        assert!(gauge.failed());
+        judge(&mut gauge, Judgement::Perfect, 10);
+        assert_value(&gauge, 0.0);
+        assert!(gauge.failed());
+        assert!(!gauge.cleared());
+    }
+
+    #[test]
+    fn test_no_fail_gauge() {
+        let mut gauge = Gauge::new(GaugeType::NoFail, 100);
+        assert_value(&gauge, 1.0);
+        judge(&mut gauge, Judgement::Miss, 100);
+        // Clamped at empty instead of going negative, and can still go back up
+        assert_value(&gauge, 0.0);
+        assert!(!gauge.failed());
+        assert!(gauge.cleared());
+        gauge.update(Judgement::Perfect);
+        assert_value(&gauge, 0.016);
+    }
+
+    #[test]
+    fn test_failure_result_delay() {
+        let at = Instant::now();
+        let failure = Failure::new(at);
+        assert!(!failure.result_due(at));
+        assert!(!failure.result_due(at + Duration::from_millis(1999)));
+        assert!(failure.result_due(at + Duration::from_secs(2)));
+    }
+}
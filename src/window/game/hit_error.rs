@@ -0,0 +1,235 @@
+//! The hit error bar and FAST/SLOW indicator, which show how early or late hits are for tuning the
+//! offset. Drawn over the skin.
+
+use std::collections::VecDeque;
+
+use conrod_core::{Colorable, Positionable, Widget, widget_ids};
+use graphics::{math, Graphics};
+use piston::window::Window;
+use texture::CreateTexture;
+
+use super::super::WindowContext;
+use crate::config::Config;
+
+/// Colours of the judgement windows on the bar, from the best window to the worst. Windows past
+/// the end use the last colour.
+const WINDOW_COLORS: [[f32; 3]; 3] = [[0.4, 0.8, 1.0], [0.4, 1.0, 0.4], [1.0, 0.7, 0.3]];
+
+/// How much of the lanes' width the bar takes up
+const BAR_WIDTH: f64 = 0.8;
+
+/// Sizes on a stage 480 pixels tall
+const BAR_HEIGHT: f64 = 4.0;
+const TICK_HEIGHT: f64 = 12.0;
+const TICK_WIDTH: f64 = 2.0;
+const MARKER_SIZE: f64 = 5.0;
+/// Space between the bottom of the lanes and the bar
+const BAR_GAP: f64 = 10.0;
+const FAST_SLOW_FONT_SIZE: f64 = 18.0;
+
+/// Where the FAST/SLOW text is centered, as a note position
+const FAST_SLOW_POSITION: f64 = 0.3;
+
+/// How long FAST/SLOW stays on the screen, in seconds
+const FAST_SLOW_TIME: f64 = 0.5;
+
+widget_ids! {
+    struct Ids {
+        fast_slow_text,
+    }
+}
+
+pub struct HitErrorMeter {
+    /// (how many seconds early, game time of the hit), oldest first
+    hits: VecDeque<(f64, f64)>,
+    /// The last hit outside the best window: (whether it was early, game time of the hit)
+    last_off_hit: Option<(bool, f64)>,
+
+    ui: conrod_core::Ui,
+    ids: Ids,
+    map: conrod_core::image::Map<opengl_graphics::Texture>,
+    glyph_cache: conrod_core::text::GlyphCache<'static>,
+    glyph_cache_texture: opengl_graphics::Texture,
+}
+
+impl HitErrorMeter {
+    pub fn new(window_context: &mut WindowContext) -> Self {
+        let size = window_context.window.size();
+        let mut ui = conrod_core::UiBuilder::new([size.width, size.height]).build();
+        ui.theme.font_id = Some(ui.fonts.insert(window_context.font.clone()));
+        let ids = Ids::new(ui.widget_id_generator());
+        let glyph_cache = conrod_core::text::GlyphCache::builder()
+            .dimensions(256, 256)
+            .build();
+        let vec = vec![0; 256*256*4];
+        let glyph_cache_texture = opengl_graphics::Texture::create(
+            &mut (),
+            texture::Format::Rgba8,
+            &vec,
+            [256, 256],
+            &texture::TextureSettings::new(),
+        ).expect("failed to create texture");
+        HitErrorMeter {
+            hits: VecDeque::with_capacity(64),
+            last_off_hit: None,
+            ui,
+            ids,
+            map: conrod_core::image::Map::new(),
+            glyph_cache,
+            glyph_cache_texture,
+        }
+    }
+
+    /// Forget every hit, e.g. when the chart starts over
+    pub fn clear(&mut self) {
+        self.hits.clear();
+        self.last_off_hit = None;
+    }
+
+    /// Called when a note is hit `timing` seconds early (negative if late) at game time `time`.
+    /// Hits outside every judgement window aren't shown.
+    pub fn hit(&mut self, config: &Config, timing: f64, time: f64) {
+        let windows = &config.game.current_judge().1.windows;
+        match window_index(windows, timing) {
+            Some(0) => (),
+            Some(_) => self.last_off_hit = Some((timing > 0.0, time)),
+            None => return,
+        }
+        self.hits.push_back((timing, time));
+        let hit_error = &config.game.hit_error;
+        while self.hits.len() > hit_error.average_count
+            && self.hits.front().filter(|&&(_, t)| time - t >= hit_error.fade_time).is_some()
+        {
+            self.hits.pop_front();
+        }
+    }
+
+    /// Pass a window event to the UI, so it knows when the window is resized
+    pub fn handle_event(&mut self, e: &piston::input::Event, window_context: &WindowContext) {
+        let size = window_context.window.size();
+        if let Some(e) = conrod_piston::event::convert(e.clone(), size.width, size.height) {
+            self.ui.handle_event(e);
+        }
+    }
+
+    /// Called when an update event occurs. `lane_area` is the skin's lane area on a stage
+    /// `stage_height` tall.
+    pub fn update(&mut self, config: &Config, lane_area: [f64; 4], stage_height: f64, time: f64) {
+        let ui = &mut self.ui.set_widgets();
+        if !config.game.hit_error.fast_slow {
+            return;
+        }
+        let early = match self.last_off_hit {
+            Some((early, t)) if time - t < FAST_SLOW_TIME => early,
+            _ => return,
+        };
+        let (text, color) = if early {
+            ("FAST", conrod_core::color::rgb(0.4, 0.7, 1.0))
+        } else {
+            ("SLOW", conrod_core::color::rgb(1.0, 0.5, 0.4))
+        };
+        let [x, y, w, h] = lane_area;
+        // conrod's origin is in the middle of the window, with y going up
+        let center_x = x + w / 2.0 - ui.win_w / 2.0;
+        let center_y = ui.win_h / 2.0 - (y + h * (1.0 - FAST_SLOW_POSITION));
+        conrod_core::widget::Text::new(text)
+            .color(color)
+            .font_size((FAST_SLOW_FONT_SIZE * stage_height / 480.0) as conrod_core::FontSize)
+            .x_y(center_x, center_y)
+            .set(self.ids.fast_slow_text, ui);
+    }
+
+    /// Draw the bar and FAST/SLOW on top of whatever has already been drawn
+    pub fn render(
+        &mut self,
+        c: graphics::Context,
+        gl: &mut opengl_graphics::GlGraphics,
+        config: &Config,
+        lane_area: [f64; 4],
+        stage_height: f64,
+        time: f64,
+    ) {
+        if config.game.hit_error.bar {
+            self.draw_bar(c.transform, gl, config, lane_area, stage_height, time);
+        }
+        conrod_piston::draw::primitives(
+            self.ui.draw(),
+            c,
+            gl,
+            &mut self.glyph_cache_texture,
+            &mut self.glyph_cache,
+            &self.map,
+            super::super::cache_glyphs,
+            |t| t,
+        );
+    }
+
+    fn draw_bar<G: Graphics>(
+        &self,
+        transform: math::Matrix2d,
+        g: &mut G,
+        config: &Config,
+        lane_area: [f64; 4],
+        stage_height: f64,
+        time: f64,
+    ) {
+        let windows = &config.game.current_judge().1.windows;
+        let range = windows.iter().map(|w| w[0].max(-w[1])).fold(0.0, f64::max);
+        if range <= 0.0 {
+            return;
+        }
+
+        let scale = stage_height / 480.0;
+        let [x, y, w, h] = lane_area;
+        let center_x = x + w / 2.0;
+        let half_width = w * BAR_WIDTH / 2.0;
+        let bar_y = (y + h + BAR_GAP * scale).min(stage_height - TICK_HEIGHT * scale);
+        // Early hits go on the left
+        let x_of = |timing: f64| center_x - timing / range * half_width;
+        let tick = |x: f64| {
+            let (w, h) = (TICK_WIDTH * scale, TICK_HEIGHT * scale);
+            [x - w / 2.0, bar_y - h / 2.0, w, h]
+        };
+
+        // Widest window first, so the better ones are drawn over it
+        for (i, &[early, late]) in windows.iter().enumerate().rev() {
+            let (left, right) = (x_of(early), x_of(late));
+            let rect = [left, bar_y - BAR_HEIGHT * scale / 2.0, right - left, BAR_HEIGHT * scale];
+            graphics::rectangle(window_color(i, 0.4), rect, transform, g);
+        }
+        graphics::rectangle([1.0, 1.0, 1.0, 0.8], tick(center_x), transform, g);
+
+        let fade_time = config.game.hit_error.fade_time;
+        for &(timing, hit_time) in &self.hits {
+            let age = time - hit_time;
+            if age < fade_time {
+                let color = window_color(window_index(windows, timing).unwrap_or(0), (1.0 - age / fade_time) as f32);
+                graphics::rectangle(color, tick(x_of(timing)), transform, g);
+            }
+        }
+
+        let average_count = config.game.hit_error.average_count.min(self.hits.len());
+        if average_count > 0 {
+            let sum: f64 = self.hits.iter().rev().take(average_count).map(|&(timing, _)| timing).sum();
+            let marker_x = x_of(sum / average_count as f64);
+            let marker_bottom = bar_y - TICK_HEIGHT * scale / 2.0;
+            let marker_size = MARKER_SIZE * scale;
+            let triangle = [
+                [marker_x - marker_size, marker_bottom - marker_size],
+                [marker_x + marker_size, marker_bottom - marker_size],
+                [marker_x, marker_bottom],
+            ];
+            graphics::polygon([1.0, 1.0, 1.0, 1.0], &triangle, transform, g);
+        }
+    }
+}
+
+/// Which of the judge's windows a hit `timing` seconds early falls in, best first
+fn window_index(windows: &[[f64; 2]], timing: f64) -> Option<usize> {
+    windows.iter().position(|&[early, late]| late <= timing && timing <= early)
+}
+
+fn window_color(index: usize, alpha: f32) -> [f32; 4] {
+    let [r, g, b] = WINDOW_COLORS[index.min(WINDOW_COLORS.len() - 1)];
+    [r, g, b, alpha]
+}
@@ -4,121 +4,317 @@ use std::time;
 
 use piston::{
     self,
-    input::{PressEvent, ReleaseEvent, RenderEvent, UpdateEvent},
+    input::{keyboard::Key, Button, PressEvent, ReleaseEvent, RenderEvent, UpdateEvent},
     window::Window,
 };
 
+mod gauge;
+mod hit_error;
 mod model;
+mod pause;
+mod practice;
+mod score;
 mod view;
 
-use self::{model::Model, view::View};
+use self::{
+    gauge::Failure,
+    hit_error::HitErrorMeter,
+    model::Model,
+    pause::{PauseAction, PauseMenu},
+    practice::PracticeSection,
+    view::View,
+};
 use super::{song_select::SongSelect, WindowContext};
 
 use crate::{audio, chart::Chart, config::Config, judgement::Judgement, gameskin};
 
+/// How many seconds ahead of the music autoplay sounds are sent to the audio thread. The audio
+/// thread starts each one on its exact frame, so this only has to cover the time between updates
+/// and audio buffers.
+const AUTOPLAY_LOOKAHEAD: f64 = 0.25;
+
+/// How a play of a chart went, shown on the song select screen afterwards
+#[derive(Copy, Clone, Debug)]
+pub(super) struct PlayResult {
+    pub cleared: bool,
+    pub score: u32,
+    /// From 0.0 to 1.0
+    pub accuracy: f64,
+    pub max_combo: u32,
+}
+
 pub struct GameScene {
     chart: Box<dyn Chart>,
     music: Option<audio::MusicStream>,
     view: View<opengl_graphics::GlGraphics>,
     model: Model,
     time: f64,
+    /// Where the music is, once the audio thread has told us
+    clock: audio::Clock,
+    /// When `time` was last updated, for advancing it before the clock knows the time
     last_instant: time::Instant,
-    first_playhead_received: bool,
-    first_playhead_request: bool,
     current_autoplay_sound_index: usize,
     chart_end_time: Option<f64>,
+    /// When the gauge ran out, if it did
+    failure: Option<Failure>,
+    /// Only exists while the game is paused
+    pause_menu: Option<PauseMenu>,
+    /// The section being played in practice mode
+    practice: Option<PracticeSection>,
+    /// For loading the skin again when it's edited, if `watch_skin` is on
+    skin_watcher: Option<gameskin::SkinWatcher>,
+    hit_error: HitErrorMeter,
 }
 
 impl GameScene {
-    /// Allocate and initialize everything
-    pub fn new(mut chart: Box<dyn Chart>, config: &Config, audio: &audio::Audio) -> Self {
-        let music = match chart.music(audio.format()) {
-            Ok(m) => Some(m),
-            Err(e) => {
-                remani_warn!("Error loading chart music `{}'", e);
-                Some(audio::MusicStream::zero())
-            }
-        };
-        audio.get_playhead(); // clear the channel
+    /// Allocate and initialize everything. Fails if the skin can't be loaded.
+    pub fn new(
+        mut chart: Box<dyn Chart>,
+        config: &Config,
+        audio: &audio::Audio,
+        window: &mut WindowContext,
+    ) -> Result<Self, gameskin::ParseError> {
+        let the_skin = gameskin::from_path(&mut (), &config.game.current_skin().1, config)?;
         chart.load_sounds(audio.format(), config);
-        let the_skin = gameskin::from_path(&mut (), &config.game.current_skin().1, config).unwrap();
 
-        let model = Model::new();
-        let view = View::new(the_skin);
+        let model = Model::new(config, &*chart);
+        let view = View::new(the_skin, config, &*chart);
+        let practice = PracticeSection::new(&config.game.practice, &*chart);
+        let music = load_music(&mut *chart, config, audio);
+        let skin_watcher = if config.game.watch_skin {
+            Some(gameskin::SkinWatcher::new(&config.game.current_skin().1, config))
+        } else {
+            None
+        };
 
-        GameScene {
+        let mut game_scene = GameScene {
             chart,
-            music,
+            music: Some(music),
             view,
             model,
             time: config.game.offset,
+            clock: audio::Clock::new(audio.latency()),
             last_instant: time::Instant::now(),
-            first_playhead_received: false,
-            first_playhead_request: false,
             current_autoplay_sound_index: 0,
             chart_end_time: None,
+            failure: None,
+            pause_menu: None,
+            practice,
+            skin_watcher,
+            hit_error: HitErrorMeter::new(window),
+        };
+        game_scene.restart(config, audio);
+        Ok(game_scene)
+    }
+
+    /// Start the chart over from the beginning, or from the start of the section in practice mode.
+    /// Notes in the lead-in before the section are skipped, so only the section is judged.
+    fn restart(&mut self, config: &Config, audio: &audio::Audio) {
+        let start = self.practice.map(|p| p.playback_start()).unwrap_or(0.0);
+        match self.music.as_mut() {
+            // It hasn't been sent to the audio thread yet
+            Some(music) => {
+                if let Err(e) = music.seek(start) {
+                    remani_warn!("Error seeking chart music: {}", e);
+                }
+            }
+            None => {
+                if !audio.seek_music(start) {
+                    remani_warn!("Failed to seek chart music");
+                }
+                // It's paused if the game was restarted from the pause menu
+                audio.resume_music();
+            }
         }
+        self.clock.reset();
+
+        let section_start = self.practice.map(|p| p.start).unwrap_or(0.0);
+        self.model = Model::new(config, &*self.chart);
+        self.model.seek(&*self.chart, section_start);
+        self.view.seek(&*self.chart, section_start);
+        self.hit_error.clear();
+        self.time = start + config.game.offset;
+        self.last_instant = time::Instant::now();
+        self.current_autoplay_sound_index = self.chart.autoplay_sounds()
+            .iter()
+            .position(|s| s.time >= start)
+            .unwrap_or(self.chart.autoplay_sounds().len());
+        self.chart_end_time = None;
+        self.failure = None;
+        self.pause_menu = None;
     }
 
-    /// Called everytime there is a window event
-    pub(super) fn event(
+    /// Load the skin again and swap it in, keeping the old one if the new one can't be loaded
+    fn reload_skin(&mut self, config: &Config) {
+        match gameskin::from_path(&mut (), &config.game.current_skin().1, config) {
+            Ok(skin) => self.view.set_skin(skin, &self.model.keys_down),
+            Err(e) => remani_warn!("Error reloading skin: {}", e),
+        }
+    }
+
+    /// Called instead of the usual event handling while the game is paused. The game clock doesn't
+    /// advance and only the pause menu takes input.
+    fn paused_event(
         &mut self,
         e: piston::input::Event,
         config: &Config,
         audio: &audio::Audio,
         window: &mut WindowContext,
     ) {
-        self.music.take()
-            .map(|m| audio.play_music(m) || panic!("Failed to play music"));
+        let pause_menu = self.pause_menu.as_mut().unwrap();
+        pause_menu.handle_event(&e, window);
+        self.hit_error.handle_event(&e, window);
+        if e.press_args() == Some(Button::Keyboard(Key::Escape)) {
+            pause_menu.resume();
+        }
 
-        if !self.first_playhead_request {
-            if let Err(e) = audio.request_playhead() {
-                remani_warn!("Error requesting audio playhead: {}", e);
+        if e.update_args().is_some() {
+            if self.skin_watcher.as_mut().map_or(false, |w| w.changed(config)) {
+                self.reload_skin(config);
+            }
+            match self.pause_menu.as_mut().unwrap().update() {
+                Some(PauseAction::Resume) => {
+                    self.pause_menu = None;
+                    audio.resume_music();
+                    // Any playhead sent while paused is stale now
+                    self.clock.reset();
+                    self.last_instant = time::Instant::now();
+                }
+                Some(PauseAction::Retry) => self.restart(config, audio),
+                Some(PauseAction::Quit) => {
+                    audio.stop_music();
+                    let song_select_scene = SongSelect::new(window, config);
+                    window.change_scene(song_select_scene);
+                }
+                None => (),
             }
-            self.first_playhead_request = true;
-        } else if let Some((instant, playhead)) = audio.get_playhead() {
-            let d = instant.elapsed();
-            let new_time =
-                playhead + d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
-                    + config.game.offset;
-            if !self.first_playhead_received {
-                self.time = new_time;
-                self.first_playhead_received = true;
-            } else {
-                self.time = (self.time + new_time) / 2.0;
+        }
+
+        // Keys released while paused shouldn't stay held down
+        if let Some(i) = e.release_args() {
+            let view = &mut self.view;
+            self.model
+                .release(&i, config, &*self.chart, self.time, |k| view.key_up(k));
+        }
+
+        if let Some(r) = e.render_args() {
+            let lane_area = self.view.skin.lane_area(r.window_size[1]);
+            window.gl.draw(r.viewport(), |c, gl| {
+                self.view
+                    .render(c, gl, &r, config, &*self.chart, &self.model, self.time);
+                self.hit_error.render(c, gl, config, lane_area, r.window_size[1], self.time);
+                if let Some(pause_menu) = &mut self.pause_menu {
+                    pause_menu.render(c, gl);
+                }
+            });
+            window.window.swap_buffers();
+        }
+    }
+
+    /// How the play has gone so far
+    fn result(&self) -> PlayResult {
+        let score = &self.model.score;
+        PlayResult {
+            cleared: self.model.gauge.cleared(),
+            score: score.score(),
+            accuracy: score.accuracy(),
+            max_combo: score.max_combo(),
+        }
+    }
+
+    /// Called everytime there is a window event
+    pub(super) fn event(
+        &mut self,
+        e: piston::input::Event,
+        config: &Config,
+        audio: &audio::Audio,
+        window: &mut WindowContext,
+    ) {
+        if let Some(m) = self.music.take() {
+            if !audio.play_music(m) {
+                remani_warn!("Failed to play chart music");
             }
-            self.last_instant = time::Instant::now();
+        }
+
+        if self.pause_menu.is_some() {
+            self.paused_event(e, config, audio, window);
+            return;
+        }
 
-            if let Err(e) = audio.request_playhead() {
-                remani_warn!("Error requesting audio playhead: {}", e);
+        self.clock.update(audio);
+        match self.clock.time() {
+            Some(t) => self.time = t + config.game.offset,
+            None => {
+                let d = self.last_instant.elapsed();
+                self.time += d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9;
             }
-        } else {
-            let d = self.last_instant.elapsed();
-            self.time += d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9;
-            self.last_instant = time::Instant::now();
         }
+        self.last_instant = time::Instant::now();
+        self.hit_error.handle_event(&e, window);
 
         if let Some(u) = e.update_args() {
+            if self.skin_watcher.as_mut().map_or(false, |w| w.changed(config)) {
+                self.reload_skin(config);
+            }
+            let stage_height = window.window.size().height;
+            let lane_area = self.view.skin.lane_area(stage_height);
+            self.hit_error.update(config, lane_area, stage_height, self.time);
+            if let Some(failure) = self.failure {
+                if failure.result_due(time::Instant::now()) {
+                    audio.stop_music();
+                    let song_select_scene = SongSelect::new(window, config);
+                    window.change_scene(song_select_scene);
+                }
+                return;
+            }
             let view = &mut self.view;
             // Update notes in model, draw any misses that occurred
             self.model.update(u, config, &*self.chart, self.time, |k| {
                 view.draw_judgement(k, Judgement::Miss, false)
             });
-            // Play the autoplay sounds if one needs to be played
-            if let Some(autoplay_sound) = self.chart.autoplay_sounds().get(self.current_autoplay_sound_index) {
-                // Unapply the offset to make sure the autoplay sound lines up with the music
-                if self.time - config.game.offset >= autoplay_sound.time {
-                    self.current_autoplay_sound_index += 1;
-                    self.chart
-                        .get_sound(autoplay_sound.sound_index)
-                        .map(|s|
-                            audio.play_effect(s.with_volume(autoplay_sound.volume))
-                            || panic!("Failed to play effect")
-                        );
+            // Schedule the autoplay sounds that are coming up. Unapply the offset, since they're
+            // lined up with the music rather than the notes.
+            let schedule_until = self.time - config.game.offset + AUTOPLAY_LOOKAHEAD;
+            while let Some(autoplay_sound) = self.chart.autoplay_sounds().get(self.current_autoplay_sound_index) {
+                if autoplay_sound.time >= schedule_until {
+                    break;
+                }
+                if let Some(s) = self.chart.get_sound(autoplay_sound.sound_index) {
+                    let effect = s.with_volume(autoplay_sound.volume)
+                        .with_pan(autoplay_sound.pan)
+                        .with_choke_group(choke_group(config, autoplay_sound.sound_index));
+                    if !audio.schedule_effect(effect, audio::Bus::Autoplay, autoplay_sound.time) {
+                        // The audio thread is behind, try again next update
+                        break;
+                    }
                 }
+                self.current_autoplay_sound_index += 1;
+            }
+            if let Some(practice) = self.practice {
+                if practice.finished(self.time - config.game.offset) {
+                    if practice.looping {
+                        self.restart(config, audio);
+                        return;
+                    } else if self.chart_end_time.is_none() {
+                        self.chart_end_time = Some(self.time);
+                    }
+                }
+            }
+            // Practice mode can't fail
+            if self.model.gauge.failed() && self.practice.is_none() {
+                // Pausing rather than stopping keeps the playhead, and so the game time, where it is
+                audio.pause_music();
+                self.failure = Some(Failure::new(time::Instant::now()));
+                window.resources.last_result = Some(self.result());
+                return;
             }
-            if view.chart_ended(&*self.chart) && self.chart_end_time.is_none() {
+            // In practice mode, the section decides when the chart ends
+            if self.practice.is_none()
+                && self.view.chart_ended(&*self.chart)
+                && self.chart_end_time.is_none()
+            {
                 self.chart_end_time = Some(self.time);
+                window.resources.last_result = Some(self.result());
             }
 
             if let Some(chart_end_time) = self.chart_end_time {
@@ -129,18 +325,42 @@ impl GameScene {
             }
         }
 
-        if let Some(i) = e.press_args() {
+        if let Some(i) = e.press_args().filter(|_| self.failure.is_none()) {
+            if i == Button::Keyboard(Key::Escape) {
+                audio.pause_music();
+                self.pause_menu = Some(PauseMenu::new(window));
+                return;
+            } else if i == config.game.lane_cover_up_key {
+                self.view.move_lane_cover(config, 1.0);
+            } else if i == config.game.lane_cover_down_key {
+                self.view.move_lane_cover(config, -1.0);
+            } else if i == config.game.reload_skin_key {
+                self.reload_skin(config);
+            }
+
             let view = &mut self.view;
+            let hit_error = &mut self.hit_error;
             let chart = &*self.chart;
+            let time = self.time;
             self.model
-                .press(&i, config, chart, self.time, |k, j, note_index, is_long_note| {
-                    if let Some(j) = j {
+                .press(&i, config, chart, time, |k, j, note_index, is_long_note| {
+                    if let Some((j, timing)) = j {
                         view.draw_judgement(k, j, is_long_note);
+                        hit_error.hit(config, timing, time);
+                    }
+                    let sound = note_index
+                        .map(|i| &chart.notes()[i])
+                        .and_then(|n| n.sound_index.and_then(|i| chart.get_sound(i).map(|s| {
+                            s.with_volume(n.volume)
+                                .with_pan(n.pan)
+                                .with_choke_group(choke_group(config, i))
+                        })));
+                    if let Some(s) = sound {
+                        if !audio.play_effect(s, audio::Bus::Keysound) {
+                            // The audio thread is behind, drop the sound rather than the game
+                            remani_warn!("Failed to play keysound");
+                        }
                     }
-                    note_index
-                        .and_then(|i| chart.notes()[i].sound_index)
-                        .and_then(|i| chart.get_sound(i))
-                        .map(|s| audio.play_effect(s) || panic!("Failed to play effect"));
                     view.key_down(k);
                 });
         }
@@ -152,11 +372,33 @@ impl GameScene {
         }
 
         if let Some(r) = e.render_args() {
+            let lane_area = self.view.skin.lane_area(r.window_size[1]);
             window.gl.draw(r.viewport(), |c, mut gl| {
                 self.view
                     .render(c, &mut gl, &r, config, &*self.chart, &self.model, self.time);
+                self.hit_error.render(c, &mut gl, config, lane_area, r.window_size[1], self.time);
             });
             window.window.swap_buffers();
         }
     }
 }
+
+/// Load the chart's music, falling back to silence if it can't be loaded
+fn load_music(chart: &mut dyn Chart, config: &Config, audio: &audio::Audio) -> audio::MusicStream {
+    match chart.music(audio.format(), config) {
+        Ok(m) => m,
+        Err(e) => {
+            remani_warn!("Error loading chart music `{}'", e);
+            audio::MusicStream::zero()
+        }
+    }
+}
+
+/// Every sound in a chart is its own choke group if keysounds should cut themselves off
+fn choke_group(config: &Config, sound_index: usize) -> Option<usize> {
+    if config.general.polyphony.choke_keysounds {
+        Some(sound_index)
+    } else {
+        None
+    }
+}
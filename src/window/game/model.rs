@@ -4,6 +4,7 @@ use std::collections::VecDeque;
 
 use piston::input::{Button, UpdateArgs};
 
+use super::{gauge::Gauge, score::Score};
 use crate::{chart::Chart, config::Config, judgement::Judgement};
 
 /// Holds game states needed by the logic and renderer. Also does timing judgements.
@@ -28,11 +29,14 @@ pub struct Model {
     /// Whether the column is currently holding a long note, and if so, contains the index of the
     /// note
     long_notes_held: [Option<usize>; 7],
+
+    pub gauge: Gauge,
+    pub score: Score,
 }
 
 impl Model {
     /// Create a model for the game controller
-    pub fn new() -> Model {
+    pub fn new(config: &Config, chart: &dyn Chart) -> Model {
         Model {
             keys_down: [false; 7],
             current_note_index: 0,
@@ -47,7 +51,23 @@ impl Model {
             ],
             notes_for_hitsound: [None; 7],
             long_notes_held: [None; 7],
+            gauge: Gauge::new(config.game.modifiers.gauge, chart.notes().len()),
+            score: Score::new(chart.notes().len()),
+        }
+    }
+
+    /// Forget about every note before `time`, as if the chart started there. Long notes that
+    /// start before `time` are skipped too.
+    pub fn seek(&mut self, chart: &dyn Chart, time: f64) {
+        self.current_note_index = chart.notes()
+            .iter()
+            .position(|n| n.time >= time)
+            .unwrap_or(chart.notes().len());
+        for column in self.next_notes.iter_mut() {
+            column.clear();
         }
+        self.notes_for_hitsound = [None; 7];
+        self.long_notes_held = [None; 7];
     }
 
     /// Called by `GameScene` when an update event occurs
@@ -69,6 +89,8 @@ impl Model {
             for &note_index in note_vec {
                 let note = &chart.notes()[note_index];
                 if note.end_time.unwrap_or(note.time) - time < config.game.current_judge().1.windows.last().unwrap()[1] {
+                    self.gauge.update(Judgement::Miss);
+                    self.score.update(Judgement::Miss);
                     miss_callback(column);
                     to_be_removed[column] += 1;
                 }
@@ -105,12 +127,13 @@ impl Model {
     ///
     /// `column`: Which column was pressed.
     ///
-    /// `judgement`: If a note was hit, this contains the `Judgement`.
+    /// `judgement`: If a note was hit, this contains the `Judgement` and how many seconds early
+    /// it was hit, negative if it was late.
     ///
     /// `hitsound_index`: Index into `chart.get_sound(i)` for which sound should be played.
     ///
     /// `is_long_note`: True if the player pressed a long note, false otherwise.
-    pub fn press<F: FnMut(usize, Option<Judgement>, Option<usize>, bool)>(
+    pub fn press<F: FnMut(usize, Option<(Judgement, f64)>, Option<usize>, bool)>(
         &mut self,
         args: &Button,
         config: &Config,
@@ -121,6 +144,8 @@ impl Model {
         let next_notes = &mut self.next_notes;
         let notes_for_hitsound = &mut self.notes_for_hitsound;
         let long_notes_held = &mut self.long_notes_held;
+        let gauge = &mut self.gauge;
+        let score = &mut self.score;
 
         config.game.key_bindings
             .iter()
@@ -142,14 +167,18 @@ impl Model {
 
                         // TODO dont hardcode timing windows
                         if timing.abs() < 0.1 {
-                            Some(Judgement::Perfect)
+                            Some((Judgement::Perfect, timing))
                         } else {
-                            Some(Judgement::Miss)
+                            Some((Judgement::Miss, timing))
                         }
                     } else {
                         None
                     };
 
+                    if let Some((j, _)) = judgement {
+                        gauge.update(j);
+                        score.update(j);
+                    }
                     *key_down = true;
 
                     callback(key_index, judgement, notes_for_hitsound[key_index], is_long_note);
@@ -0,0 +1,166 @@
+//! The menu shown when the game is paused
+
+use std::time;
+
+use piston::window::Window;
+use texture::CreateTexture;
+use conrod_core::{
+    Labelable,
+    Positionable,
+    Sizeable,
+    Widget,
+    widget_ids,
+};
+
+use super::super::WindowContext;
+
+/// How long the countdown before resuming lasts, in seconds
+const COUNTDOWN_LENGTH: u64 = 3;
+
+widget_ids! {
+    struct Ids {
+        background,
+        resume_button,
+        retry_button,
+        quit_button,
+        countdown_text,
+    }
+}
+
+/// What the player chose in the pause menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseAction {
+    /// The countdown finished and the game should continue
+    Resume,
+    Retry,
+    Quit,
+}
+
+pub struct PauseMenu {
+    ui: conrod_core::Ui,
+    ids: Ids,
+    map: conrod_core::image::Map<opengl_graphics::Texture>,
+    glyph_cache: conrod_core::text::GlyphCache<'static>,
+    glyph_cache_texture: opengl_graphics::Texture,
+    /// When the player chose to resume, if they did
+    countdown_start: Option<time::Instant>,
+}
+
+impl PauseMenu {
+    pub fn new(window_context: &mut WindowContext) -> Self {
+        let size = window_context.window.size();
+        let mut ui = conrod_core::UiBuilder::new([size.width, size.height]).build();
+        ui.handle_event(
+            conrod_core::event::Input::Motion(
+                conrod_core::input::Motion::MouseCursor {
+                    x: window_context.mouse_position[0],
+                    y: window_context.mouse_position[1],
+                }
+            )
+        );
+        ui.theme.font_id = Some(ui.fonts.insert(window_context.font.clone()));
+        ui.theme.shape_color = conrod_core::color::CHARCOAL;
+        ui.theme.label_color = conrod_core::color::WHITE;
+        let ids = Ids::new(ui.widget_id_generator());
+        let map = conrod_core::image::Map::new();
+        let glyph_cache = conrod_core::text::GlyphCache::builder()
+            .dimensions(1024, 1024)
+            .build();
+        let vec = vec![0; 1024*1024*4];
+        let glyph_cache_texture = opengl_graphics::Texture::create(
+            &mut (),
+            texture::Format::Rgba8,
+            &vec,
+            [1024, 1024],
+            &texture::TextureSettings::new(),
+        ).expect("failed to create texture");
+        PauseMenu {
+            ui,
+            ids,
+            map,
+            glyph_cache,
+            glyph_cache_texture,
+            countdown_start: None,
+        }
+    }
+
+    /// Start the countdown to resume the game, if it hasn't been started already
+    pub fn resume(&mut self) {
+        if self.countdown_start.is_none() {
+            self.countdown_start = Some(time::Instant::now());
+        }
+    }
+
+    /// Pass a window event to the UI
+    pub fn handle_event(&mut self, e: &piston::input::Event, window_context: &WindowContext) {
+        let size = window_context.window.size();
+        if let Some(e) = conrod_piston::event::convert(e.clone(), size.width, size.height) {
+            self.ui.handle_event(e);
+        }
+    }
+
+    /// Called when an update event occurs. Returns what the player chose, if anything.
+    pub fn update(&mut self) -> Option<PauseAction> {
+        let ui = &mut self.ui.set_widgets();
+
+        if let Some(countdown_start) = self.countdown_start {
+            let elapsed = countdown_start.elapsed().as_secs();
+            if elapsed >= COUNTDOWN_LENGTH {
+                return Some(PauseAction::Resume);
+            }
+            conrod_core::widget::Text::new(&(COUNTDOWN_LENGTH - elapsed).to_string())
+                .middle_of(ui.window)
+                .font_size(60)
+                .set(self.ids.countdown_text, ui);
+            return None;
+        }
+
+        conrod_core::widget::Rectangle::fill_with([ui.win_w, ui.win_h], conrod_core::color::BLACK.alpha(0.6))
+            .middle_of(ui.window)
+            .set(self.ids.background, ui);
+
+        let mut action = None;
+        if conrod_core::widget::Button::new()
+            .mid_top_with_margin_on(ui.window, ui.win_h / 2.0 - 70.0)
+            .w_h(200.0, 40.0)
+            .label("Resume")
+            .set(self.ids.resume_button, ui)
+            .was_clicked()
+        {
+            self.countdown_start = Some(time::Instant::now());
+        }
+        if conrod_core::widget::Button::new()
+            .down(10.0)
+            .w_h(200.0, 40.0)
+            .label("Retry")
+            .set(self.ids.retry_button, ui)
+            .was_clicked()
+        {
+            action = Some(PauseAction::Retry);
+        }
+        if conrod_core::widget::Button::new()
+            .down(10.0)
+            .w_h(200.0, 40.0)
+            .label("Quit")
+            .set(self.ids.quit_button, ui)
+            .was_clicked()
+        {
+            action = Some(PauseAction::Quit);
+        }
+        action
+    }
+
+    /// Draw the menu on top of whatever has already been drawn
+    pub fn render(&mut self, c: graphics::Context, gl: &mut opengl_graphics::GlGraphics) {
+        conrod_piston::draw::primitives(
+            self.ui.draw(),
+            c,
+            gl,
+            &mut self.glyph_cache_texture,
+            &mut self.glyph_cache,
+            &self.map,
+            super::super::cache_glyphs,
+            |t| t,
+        );
+    }
+}
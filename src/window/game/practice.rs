@@ -0,0 +1,69 @@
+//! Practice mode, which plays a section of the chart over and over
+
+use crate::{
+    chart::{self, Chart},
+    config::{Practice, PracticePosition},
+};
+
+/// How long to keep playing after the end of the section before looping, so the last notes can
+/// still be judged.
+const LOOP_DELAY: f64 = 1.0;
+
+/// The part of the chart being practiced, with positions converted to seconds
+#[derive(Debug, Clone, Copy)]
+pub struct PracticeSection {
+    pub start: f64,
+    pub end: f64,
+    lead_in: f64,
+    pub looping: bool,
+}
+
+impl PracticeSection {
+    /// Returns `None` if practice mode isn't enabled or the section is empty
+    pub fn new(practice: &Practice, chart: &dyn Chart) -> Option<Self> {
+        if !practice.enabled {
+            return None;
+        }
+        let start = position_time(practice.start, chart)?;
+        let end = match practice.end {
+            Some(end) => position_time(end, chart)?,
+            None => chart.notes()
+                .iter()
+                .map(|n| n.end_time.unwrap_or(n.time))
+                .fold(0.0, f64::max),
+        };
+        if end <= start {
+            remani_warn!("Practice section ends before it starts, playing the whole chart");
+            return None;
+        }
+        Some(PracticeSection {
+            start,
+            end,
+            lead_in: practice.lead_in.max(0.0),
+            looping: practice.looping,
+        })
+    }
+
+    /// Where the music should start playing from
+    pub fn playback_start(&self) -> f64 {
+        (self.start - self.lead_in).max(0.0)
+    }
+
+    /// Whether `time` is far enough past the end of the section to stop or loop
+    pub fn finished(&self, time: f64) -> bool {
+        time > self.end + LOOP_DELAY
+    }
+}
+
+fn position_time(position: PracticePosition, chart: &dyn Chart) -> Option<f64> {
+    match position {
+        PracticePosition::Time(t) => Some(t),
+        PracticePosition::Measure(m) => {
+            let time = chart::measure_time(chart.timing_points(), m);
+            if time.is_none() {
+                remani_warn!("Couldn't find measure {} in the chart, playing the whole chart", m);
+            }
+            time
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Combo, score and accuracy, which go up and down with judgements like the gauge
+
+use crate::judgement::Judgement;
+
+/// The score for playing the whole chart perfectly
+const MAX_SCORE: f64 = 1_000_000.0;
+
+/// How much a judgement is worth, as a fraction of a perfect. Same ratios as osu's 300, 200 and
+/// 50.
+fn weight(judgement: Judgement) -> f64 {
+    match judgement {
+        Judgement::Perfect => 1.0,
+        Judgement::Good => 2.0 / 3.0,
+        Judgement::Bad => 1.0 / 6.0,
+        Judgement::Miss => 0.0,
+    }
+}
+
+pub struct Score {
+    note_count: usize,
+    /// How many notes have been judged so far
+    judged: usize,
+    /// The sum of the weights of every judgement so far
+    weight_sum: f64,
+    combo: u32,
+    max_combo: u32,
+}
+
+impl Score {
+    /// `note_count` is how many notes it takes to reach the max score
+    pub fn new(note_count: usize) -> Self {
+        Score {
+            note_count: note_count.max(1),
+            judged: 0,
+            weight_sum: 0.0,
+            combo: 0,
+            max_combo: 0,
+        }
+    }
+
+    /// Called whenever a note is judged. Only misses break the combo.
+    pub fn update(&mut self, judgement: Judgement) {
+        self.judged += 1;
+        self.weight_sum += weight(judgement);
+        if judgement == Judgement::Miss {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+    }
+
+    /// How many notes in a row have been hit
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    /// The longest combo so far
+    pub fn max_combo(&self) -> u32 {
+        self.max_combo
+    }
+
+    /// From 0 to 1,000,000
+    pub fn score(&self) -> u32 {
+        (MAX_SCORE * self.weight_sum / self.note_count as f64).round() as u32
+    }
+
+    /// From 0.0 to 1.0, based only on the notes judged so far
+    pub fn accuracy(&self) -> f64 {
+        if self.judged == 0 {
+            1.0
+        } else {
+            self.weight_sum / self.judged as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weights() {
+        let mut score = Score::new(6);
+        for &judgement in &[Judgement::Perfect, Judgement::Good, Judgement::Bad] {
+            score.update(judgement);
+        }
+        // (1 + 2/3 + 1/6) / 6 of the max score
+        assert_eq!(305_556, score.score());
+        assert!((score.accuracy() - 11.0 / 18.0).abs() < 1e-9);
+
+        score.update(Judgement::Miss);
+        assert_eq!(305_556, score.score());
+        assert!((score.accuracy() - 11.0 / 24.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_all_perfect() {
+        let mut score = Score::new(7);
+        for _ in 0..7 {
+            score.update(Judgement::Perfect);
+        }
+        assert_eq!(1_000_000, score.score());
+        assert_eq!(1.0, score.accuracy());
+        assert_eq!(7, score.combo());
+    }
+
+    #[test]
+    fn test_combo() {
+        let mut score = Score::new(10);
+        for &judgement in &[Judgement::Perfect, Judgement::Bad, Judgement::Good, Judgement::Miss, Judgement::Perfect] {
+            score.update(judgement);
+        }
+        // Only the miss broke the combo
+        assert_eq!(1, score.combo());
+        assert_eq!(3, score.max_combo());
+
+        for _ in 0..4 {
+            score.update(Judgement::Good);
+        }
+        assert_eq!(5, score.combo());
+        assert_eq!(5, score.max_combo());
+    }
+
+    #[test]
+    fn test_nothing_judged() {
+        let score = Score::new(10);
+        assert_eq!(0, score.score());
+        assert_eq!(1.0, score.accuracy());
+        assert_eq!(0, score.combo());
+        assert_eq!(0, score.max_combo());
+    }
+}
@@ -4,42 +4,110 @@ use graphics::{self, Context, Graphics};
 use piston::input::RenderArgs;
 
 use super::Model;
-use crate::{chart, config::Config, judgement::Judgement, gameskin::GameSkin};
+use crate::{
+    chart,
+    config::{Config, LaneCover, Visibility},
+    judgement::Judgement,
+    gameskin::{GameSkin, NotePosition},
+};
+
+/// (start, end) positions of the fade used by the Hidden modifier. Notes are invisible below the
+/// start and fully visible above the end.
+const HIDDEN_FADE: (f64, f64) = (0.15, 0.45);
+
+/// (start, end) positions of the fade used by the FadeIn modifier. Notes are fully visible below
+/// the start and invisible above the end.
+const FADE_IN_FADE: (f64, f64) = (0.55, 0.85);
+
+/// Notes above this position are hidden by the Sudden modifier.
+const SUDDEN_POSITION: f64 = 0.6;
+
+/// How much one press of the lane cover keys moves the lane cover.
+const LANE_COVER_STEP: f64 = 0.025;
+
+/// The line drawn where the lane cover ends, so it can be moved to the right place
+const LANE_COVER_EDGE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+/// On a stage 480 pixels tall
+const LANE_COVER_EDGE_HEIGHT: f64 = 2.0;
 
 /// Holds values and resources needed by the window to do drawing stuff
 pub struct View<G: Graphics> {
     pub skin: Box<dyn GameSkin<G>>,
 
-    /// Index of the next note that isn't on the screen yet
+    /// Index of the first note that hasn't been put on the screen yet
     next_note_index: usize,
 
-    /// Used to optimize the calc_pos function. Points to the timing point that the judgement
-    /// line is in
-    current_timing_point_index: usize,
+    scroll_map: chart::ScrollMap,
+    /// Where every note starts and ends on `scroll_map`, in the same order as the chart's notes
+    note_positions: Vec<(f64, Option<f64>)>,
+    /// The lowest of each note's position and every later note's, so looking for notes that came
+    /// onto the screen can stop early even if the chart scrolls backwards
+    lowest_note_position_from: Vec<f64>,
+    /// Whether each note has been put on the screen since the last seek
+    notes_shown: Vec<bool>,
 
     notes_on_screen_indices: Vec<usize>,
     /// Indices of the notes in notes_on_screen that are actually below the screen and need to be
     /// removed
     notes_below_screen_indices: Vec<usize>,
 
-    /// (index, start_pos, end_pos)
-    notes_pos: Vec<(usize, f64, Option<f64>)>,
+    notes_pos: Vec<NotePosition>,
+
+    /// The beat division of every note in the chart, in the same order
+    note_snaps: Vec<chart::Snap>,
+
+    bar_lines: Vec<chart::BarLine>,
+    /// Where every bar line is on `scroll_map`
+    bar_line_positions: Vec<f64>,
+    /// Like `lowest_note_position_from`, for bar lines
+    lowest_bar_line_position_from: Vec<f64>,
+    /// Index of the first bar line that hasn't gone below the judgement line yet
+    next_bar_line_index: usize,
+    /// (pos, whether the line starts a measure)
+    bar_lines_pos: Vec<(f64, bool)>,
 
     // TODO get rid of this (related to display hit animation if the player successfully hits the note)
     long_notes_held: [bool; 7],
+
+    /// How much of the lanes the lane cover hides, from 0.0 to 1.0
+    lane_cover_size: f64,
 }
 
 impl<G: Graphics> View<G> {
     /// Create a view with some hardcoded defaults and stuffs
-    pub fn new(skin: Box<dyn GameSkin<G>>) -> Self {
+    pub fn new(skin: Box<dyn GameSkin<G>>, config: &Config, chart: &dyn chart::Chart) -> Self {
+        let note_snaps = chart.notes()
+            .iter()
+            .map(|n| chart::note_snap(chart.timing_points(), n.time))
+            .collect();
+        let scroll_map = chart::ScrollMap::new(chart.timing_points(), chart.primary_bpm());
+        let note_positions: Vec<_> = chart.notes()
+            .iter()
+            .map(|n| (scroll_map.position(n.time), n.end_time.map(|t| scroll_map.position(t))))
+            .collect();
+        let lowest_note_position_from =
+            lowest_from(note_positions.iter().map(|&(start, end)| lowest(start, end)).collect());
+        let bar_lines = chart.bar_lines();
+        let bar_line_positions: Vec<_> = bar_lines.iter().map(|l| scroll_map.position(l.time)).collect();
+        let lowest_bar_line_position_from = lowest_from(bar_line_positions.clone());
         View {
             skin,
             next_note_index: 0,
-            current_timing_point_index: 0,
+            scroll_map,
+            note_positions,
+            lowest_note_position_from,
+            notes_shown: vec![false; chart.notes().len()],
             notes_on_screen_indices: Vec::with_capacity(128),
             notes_below_screen_indices: Vec::with_capacity(128),
             notes_pos: Vec::with_capacity(128),
+            note_snaps,
+            bar_lines,
+            bar_line_positions,
+            lowest_bar_line_position_from,
+            next_bar_line_index: 0,
+            bar_lines_pos: Vec::with_capacity(64),
             long_notes_held: [false; 7],
+            lane_cover_size: config.game.modifiers.lane_cover_size.max(0.0).min(1.0),
         }
     }
 
@@ -56,42 +124,33 @@ impl<G: Graphics> View<G> {
     ) {
         graphics::clear([0.0, 0.0, 0.0, 1.0], g);
 
-        // manage self.current_timing_point_index
-        //
-        // from the future: this is bugged, the calc_pos function needs the most recent bpm change
-        // timing point as well as the most recent sv timing point, this code only gives the most
-        // recent timing point, whichever it is, which is wrong.
-        //
-        // TODO fix (we only need to check the timing points between current_timing_point_index
-        // and the current time and take the minimum of the most recent bpm change and sv change
-        //
-        // while chart.timing_points()
-        //     .get(self.current_timing_point_index + 2)
-        //     .filter(|tp| time > tp.offset)
-        //     .is_some()
-        // {
-        //     self.current_timing_point_index += 1;
-        // }
-
-        let mut add_next_note_index = 0;
-
-        for (index, note) in chart.notes()[self.next_note_index..].iter().enumerate() {
-            let note_pos = calc_pos(
-                time,
-                note.time,
-                chart,
-                config.game.scroll_speed,
-                self.current_timing_point_index,
-            );
-            if note_pos > 1.0 {
-                break;
-            }
+        let current_position = self.scroll_map.position(time);
+        let scroll_speed = config.game.scroll_speed;
+        // How far above the judgement line something at `position` is, 1.0 being the top of the
+        // stage
+        let pos = |position: f64| (position - current_position) * scroll_speed;
 
-            self.notes_on_screen_indices
-                .push(index + self.next_note_index);
-            add_next_note_index += 1;
+        // Notes that have already been reached are put on the screen even if they never scrolled
+        // onto it, so they still get removed once they're done
+        let notes = chart.notes();
+        let lowest_note_position_from = &self.lowest_note_position_from;
+        let note_positions = &self.note_positions;
+        let any_from_on_screen = |i: usize| pos(lowest_note_position_from[i]) <= 1.0 || notes[i].time <= time;
+        let on_screen = |i: usize| {
+            let (start, end) = note_positions[i];
+            pos(lowest(start, end)) <= 1.0 || notes[i].time <= time
+        };
+        let mut index = self.next_note_index;
+        while index < notes.len() && any_from_on_screen(index) {
+            if !self.notes_shown[index] && on_screen(index) {
+                self.notes_shown[index] = true;
+                self.notes_on_screen_indices.push(index);
+            }
+            index += 1;
+        }
+        while self.notes_shown.get(self.next_note_index) == Some(&true) {
+            self.next_note_index += 1;
         }
-        self.next_note_index += add_next_note_index;
 
         for (index, &note_index) in self.notes_on_screen_indices.iter().enumerate() {
             let note = &chart.notes()[note_index];
@@ -114,38 +173,110 @@ impl<G: Graphics> View<G> {
         }
         self.notes_below_screen_indices.clear();
         self.notes_pos.clear();
-        let current_timing_point_index = self.current_timing_point_index; // rust pls fix closures
+        let note_snaps = &self.note_snaps;
+        // Notes the visibility modifier or the lane cover hides aren't drawn, and long notes are cut
+        // off where they go under them
+        let (bottom, top) = self.visible_range(config);
+        let visibility = config.game.modifiers.visibility;
+        let clip = |p: f64| p.max(bottom).min(top);
         self.notes_pos
-            .extend(self.notes_on_screen_indices.iter().map(|&i| {
-                let note = &chart.notes()[i];
-
-                let pos = calc_pos(
-                    time,
-                    note.time,
-                    chart,
-                    config.game.scroll_speed,
-                    current_timing_point_index,
-                );
-                let end_pos = note.end_time.map(|t| {
-                    calc_pos(
-                        time,
-                        t,
-                        chart,
-                        config.game.scroll_speed,
-                        current_timing_point_index,
-                    )
-                });
-
-                (note.column, pos, end_pos)
+            .extend(self.notes_on_screen_indices.iter().filter_map(|&i| {
+                let (start, end) = note_positions[i];
+                let (start, end) = (pos(start), end.map(pos));
+                let highest = end.map(|e| e.max(start)).unwrap_or(start);
+                if highest < bottom || lowest(start, end) > top {
+                    return None;
+                }
+                let (start, end) = (clip(start), end.map(clip));
+                // Long notes are as visible as their most visible end
+                let alpha = fade_alpha(visibility, start).max(end.map_or(0.0, |e| fade_alpha(visibility, e)));
+                if alpha <= 0.0 {
+                    return None;
+                }
+                let snap = if config.game.snap_colors { Some(note_snaps[i]) } else { None };
+                Some((notes[i].column, start, end, snap, alpha))
             }));
 
+        while self.bar_lines.get(self.next_bar_line_index).filter(|l| l.time < time).is_some() {
+            self.next_bar_line_index += 1;
+        }
+        self.bar_lines_pos.clear();
+        for i in self.next_bar_line_index..self.bar_lines.len() {
+            if pos(self.lowest_bar_line_position_from[i]) > 1.0 {
+                break;
+            }
+            let line_pos = pos(self.bar_line_positions[i]);
+            if line_pos <= 1.0 && bottom <= line_pos && line_pos <= top {
+                self.bar_lines_pos.push((line_pos, self.bar_lines[i].measure));
+            }
+        }
+
         self.skin.draw_play_scene(
             c.transform,
             g,
             args.window_size[1],
             &model.keys_down,
             &*self.notes_pos,
+            &*self.bar_lines_pos,
         );
+
+        self.draw_lane_cover_edge(c, g, args.window_size[1], config);
+        self.skin.draw_gauge(c.transform, g, args.window_size[1], model.gauge.value());
+        self.skin.draw_combo(c.transform, g, args.window_size[1], model.score.combo());
+        self.skin.draw_score(c.transform, g, args.window_size, model.score.score());
+        self.skin.draw_accuracy(c.transform, g, args.window_size, model.score.accuracy());
+    }
+
+    /// The (lowest, highest) positions notes can be seen at with the visibility modifier and the
+    /// lane cover
+    fn visible_range(&self, config: &Config) -> (f64, f64) {
+        let modifiers = &config.game.modifiers;
+        let (mut bottom, mut top) = match modifiers.visibility {
+            Visibility::Normal => (f64::NEG_INFINITY, f64::INFINITY),
+            Visibility::Hidden => (HIDDEN_FADE.0, f64::INFINITY),
+            Visibility::Sudden => (f64::NEG_INFINITY, SUDDEN_POSITION),
+            Visibility::FadeIn => (f64::NEG_INFINITY, FADE_IN_FADE.1),
+        };
+        if self.lane_cover_size > 0.0 {
+            match modifiers.lane_cover {
+                LaneCover::None => (),
+                LaneCover::Top => top = top.min(1.0 - self.lane_cover_size),
+                LaneCover::Bottom => bottom = bottom.max(self.lane_cover_size),
+            }
+        }
+        (bottom, top)
+    }
+
+    /// Draw a line across the lanes where the lane cover ends
+    fn draw_lane_cover_edge(&self, c: Context, g: &mut G, stage_height: f64, config: &Config) {
+        let edge = match config.game.modifiers.lane_cover {
+            LaneCover::None => return,
+            LaneCover::Top => 1.0 - self.lane_cover_size,
+            LaneCover::Bottom => self.lane_cover_size,
+        };
+        let [x, y, w, h] = self.skin.lane_area(stage_height);
+        let line_h = LANE_COVER_EDGE_HEIGHT * stage_height / 480.0;
+        let rect = [x, y + h * (1.0 - edge) - line_h / 2.0, w, line_h];
+        graphics::rectangle(LANE_COVER_EDGE_COLOR, rect, c.transform, g);
+    }
+
+    /// Move the edge of the lane cover up if `direction` is positive, or down if it's negative.
+    pub fn move_lane_cover(&mut self, config: &Config, direction: f64) {
+        let step = match config.game.modifiers.lane_cover {
+            // the bottom lane cover grows upwards
+            LaneCover::Bottom => -LANE_COVER_STEP,
+            _ => LANE_COVER_STEP,
+        };
+        self.lane_cover_size = (self.lane_cover_size - step * direction).max(0.0).min(1.0);
+    }
+
+    /// Swap in a different skin, e.g. after the skin's files changed. The new skin is told which
+    /// keys are being held.
+    pub fn set_skin(&mut self, skin: Box<dyn GameSkin<G>>, keys_down: &[bool; 7]) {
+        self.skin = skin;
+        for (column, _) in keys_down.iter().enumerate().filter(|&(_, &down)| down) {
+            self.skin.key_down(column);
+        }
     }
 
     pub fn draw_judgement(&mut self, column: usize, judgement: Judgement, is_long_note: bool) {
@@ -167,6 +298,29 @@ impl<G: Graphics> View<G> {
         self.skin.long_note_hit_anim_stop(column);
     }
 
+    /// Forget every note that has been shown and start showing notes from `time`. Used to play
+    /// the chart again or to start from the middle of it.
+    pub fn seek(&mut self, chart: &dyn chart::Chart, time: f64) {
+        self.next_note_index = chart.notes()
+            .iter()
+            .position(|n| n.time >= time)
+            .unwrap_or(chart.notes().len());
+        self.next_bar_line_index = self.bar_lines
+            .iter()
+            .position(|l| l.time >= time)
+            .unwrap_or(self.bar_lines.len());
+        for shown in &mut self.notes_shown {
+            *shown = false;
+        }
+        self.notes_on_screen_indices.clear();
+        self.notes_below_screen_indices.clear();
+        self.notes_pos.clear();
+        for column in 0..7 {
+            self.key_up(column);
+        }
+        self.long_notes_held = [false; 7];
+    }
+
     pub fn chart_ended(&self, chart: &dyn chart::Chart) -> bool {
         self.next_note_index >= chart.notes().len()
             && self.notes_on_screen_indices.len() == 0
@@ -174,82 +328,25 @@ impl<G: Graphics> View<G> {
     }
 }
 
-/// Given the time in seconds from the start of the song, calculate the position, taking into
-/// account SV changes. Return value is an f64 between 0.0 and 1.0, 0.0 being at the judgement
-/// line, and 1.0 being at the top of the stage.
-///
-/// Used to calculate note position.
-fn calc_pos(
-    current_time: f64,
-    time: f64,
-    chart: &dyn chart::Chart,
-    scroll_speed: f64,
-    current_timing_point_index: usize,
-) -> f64 {
-    let mut iterator = chart.timing_points()[current_timing_point_index..]
-        .iter()
-        .take_while(|tp| tp.offset < time)
-        .peekable();
-
-    let mut last_sv_tp: Option<&chart::TimingPoint> = None;
-    let mut last_bpm_tp: Option<&chart::TimingPoint> = {
-        // it should be the first timing point, but if it's not, the map is still playable
-        match chart.timing_points().first() {
-            Some(tp) if tp.is_bpm() => Some(tp),
-            Some(_) => None,
-            None => {
-                remani_warn!("Osu chart has no timing points!");
-                None
-            }
-        }
+/// How visible a note at `pos` is with the visibility modifier's fade, from 0.0 to 1.0
+fn fade_alpha(visibility: Visibility, pos: f64) -> f32 {
+    let alpha = match visibility {
+        Visibility::Hidden => (pos - HIDDEN_FADE.0) / (HIDDEN_FADE.1 - HIDDEN_FADE.0),
+        Visibility::FadeIn => (FADE_IN_FADE.1 - pos) / (FADE_IN_FADE.1 - FADE_IN_FADE.0),
+        Visibility::Normal | Visibility::Sudden => 1.0,
     };
-    // get the last timing point before the current time, if one exists.
-    while iterator.peek().is_some() {
-        if iterator.peek().unwrap().offset < current_time {
-            let tp = iterator.next().unwrap();
-            if tp.is_sv() {
-                last_sv_tp = Some(tp);
-            } else {
-                last_sv_tp = None;
-                last_bpm_tp = Some(tp);
-            }
-        } else {
-            break;
-        }
-    }
-
-    let mut pos: f64;
-
-    let value = last_bpm_tp
-        .map(|t| t.value.inner() / chart.primary_bpm())
-        .unwrap_or(1.0)
-        * last_sv_tp.map(|t| t.value.inner()).unwrap_or(1.0);
-
-    if let Some(tp) = iterator.peek() {
-        pos = (tp.offset - current_time) * value;
-    } else {
-        return (time - current_time) * value * scroll_speed;
-    }
+    alpha.max(0.0).min(1.0) as f32
+}
 
-    while let Some(tp) = iterator.next() {
-        let value = if tp.is_sv() {
-            last_bpm_tp
-                .map(|t| t.value.inner() / chart.primary_bpm())
-                .unwrap_or(1.0)
-                * tp.value.inner()
-        } else {
-            // bpm timing point
-            last_bpm_tp = Some(tp);
-            tp.value.inner() / chart.primary_bpm()
-        };
+/// The lower end of a note, which is the end of a long note if the chart scrolls backwards
+fn lowest(start: f64, end: Option<f64>) -> f64 {
+    end.map(|e| e.min(start)).unwrap_or(start)
+}
 
-        if let Some(ntp) = iterator.peek() {
-            pos += (ntp.offset - tp.offset) * value;
-        } else {
-            // if last
-            pos += (time - tp.offset) * value;
-            break;
-        }
+/// Replace every position with the lowest of it and every position after it
+fn lowest_from(mut positions: Vec<f64>) -> Vec<f64> {
+    for i in (1..positions.len()).rev() {
+        positions[i - 1] = positions[i - 1].min(positions[i]);
     }
-    pos * scroll_speed
+    positions
 }
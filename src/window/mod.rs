@@ -9,6 +9,7 @@ use crate::{audio, chart, config::Config};
 mod game;
 mod main_menu;
 mod options;
+mod skin_preview;
 mod song_select;
 
 enum Scene {
@@ -101,6 +102,8 @@ impl From<Scene> for Option<song_select::SongSelect> {
 struct SceneResources {
     song_list: Option<Vec<chart::ChartSet>>,
     last_selected_song_index: usize,
+    /// How the last chart that was played to the end (or failed) went
+    last_result: Option<game::PlayResult>,
 }
 
 enum NextScene {
@@ -164,6 +167,13 @@ pub fn start(mut config: Config) {
         }
     };
 
+    let volume = config.general.volume;
+    audio.set_volume(audio::Bus::Master, volume.master);
+    audio.set_volume(audio::Bus::Music, volume.music);
+    audio.set_volume(audio::Bus::Keysound, volume.keysound);
+    audio.set_volume(audio::Bus::Autoplay, volume.autoplay);
+    audio.set_polyphony(config.general.polyphony.max_voices, config.general.polyphony.stealing);
+
     let mut window = WindowContext {
         gl,
         next_scene: None,
@@ -1,3 +1,4 @@
+use graphics::Transformed;
 use piston::{
     input::{self, ButtonEvent, PressEvent, RenderEvent, UpdateEvent},
     window::Window,
@@ -13,7 +14,7 @@ use conrod_core::{
     widget_ids,
 };
 
-use super::{main_menu::MainMenu, WindowContext};
+use super::{main_menu::MainMenu, skin_preview::SkinPreview, WindowContext};
 use crate::{audio, config::{self, Config}};
 
 widget_ids! {
@@ -34,9 +35,24 @@ widget_ids! {
         scroll_speed_canvas,
         scroll_speed_text,
         scroll_speed_input,
+        skin_canvas,
+        skin_text,
+        skin_list,
         enable_osu_hit_sounds_canvas,
         enable_osu_hit_sounds_text,
         enable_osu_hit_sounds_toggle,
+        master_volume_canvas,
+        master_volume_text,
+        master_volume_slider,
+        music_volume_canvas,
+        music_volume_text,
+        music_volume_slider,
+        keysound_volume_canvas,
+        keysound_volume_text,
+        keysound_volume_slider,
+        autoplay_volume_canvas,
+        autoplay_volume_text,
+        autoplay_volume_slider,
         keybindings_canvas,
         keybindings_text,
         keybindings_buttons_canvas,
@@ -69,13 +85,36 @@ pub struct Options {
     audio_buf_size_input_text: String,
     audio_offset_input_text: String,
     scroll_speed_input_text: String,
+    skin_names: Vec<String>,
+    skin_index: usize,
+    /// The selected skin playing a demo pattern, if it could be loaded
+    skin_preview: Option<SkinPreview>,
     enable_osu_hit_sounds_toggle_value: bool,
+    /// Volume levels, sent to the audio thread as soon as a slider moves
+    volume: config::Volume,
     keybinding_values: [input::Button; 7],
     /// Button state for flashing the keybinding button when the corresponding button is pressed.
     buttons_pressed: [bool; 7],
     keybindings_key_capture: Option<usize>,
 }
 
+/// Space between the options and the skin preview, and between the preview and the window edges
+const PREVIEW_MARGIN: f64 = 20.0;
+
+/// How wide the column of options is
+const MAIN_CANVAS_WIDTH: f64 = 640.0;
+
+/// Load a skin for previewing, warning if it can't be loaded
+fn load_skin_preview(config: &Config, skin_index: usize) -> Option<SkinPreview> {
+    match SkinPreview::new(config, skin_index) {
+        Ok(preview) => Some(preview),
+        Err(e) => {
+            remani_warn!("Error loading skin {}: {}", config.game.skins[skin_index].0, e);
+            None
+        }
+    }
+}
+
 fn button_name(button: input::Button) -> &'static str {
     use piston::input::{
         Button::*,
@@ -366,7 +405,11 @@ impl Options {
         };
         let audio_offset_input_text = config.game.offset.to_string();
         let scroll_speed_input_text = config.game.scroll_speed.to_string();
+        let skin_names = config.game.skins.iter().map(|(name, _)| name.clone()).collect();
+        let skin_index = config.game.current_skin_index();
+        let skin_preview = load_skin_preview(config, skin_index);
         let enable_osu_hit_sounds_toggle_value = config.game.osu_hitsound_enable;
+        let volume = config.general.volume;
         let keybinding_values = config.game.key_bindings;
         let buttons_pressed = [false; 7];
         let keybindings_key_capture = None;
@@ -381,7 +424,11 @@ impl Options {
             audio_buf_size_input_text,
             audio_offset_input_text,
             scroll_speed_input_text,
+            skin_names,
+            skin_index,
+            skin_preview,
             enable_osu_hit_sounds_toggle_value,
+            volume,
             keybinding_values,
             buttons_pressed,
             keybindings_key_capture,
@@ -391,7 +438,7 @@ impl Options {
         &mut self,
         e: piston::input::Event,
         config: &mut Config,
-        _audio: &audio::Audio,
+        audio: &audio::Audio,
         window_context: &mut WindowContext,
     ) {
         let size = window_context.window.size();
@@ -413,38 +460,57 @@ impl Options {
             self.keybindings_key_capture = None;
         }
         if let Some(_) = e.update_args() {
-            self.set_ui(config, window_context);
+            self.set_ui(config, audio, window_context);
+            if let Some(skin_preview) = &mut self.skin_preview {
+                skin_preview.reload_if_changed(config);
+            }
         }
         if let Some(r) = e.render_args() {
-            if let Some(primitives) = self.ui.draw_if_changed() {
-                let self_glyph_cache_texture = &mut self.glyph_cache_texture;
-                let self_glyph_cache = &mut self.glyph_cache;
-                let self_map = &self.map;
-                window_context.gl.draw(r.viewport(), |c, gl| {
-                    graphics::clear([0.0, 0.0, 0.0, 1.0], gl);
-                    conrod_piston::draw::primitives(
-                        primitives,
-                        c,
-                        gl,
-                        self_glyph_cache_texture,
-                        self_glyph_cache,
-                        self_map,
-                        super::cache_glyphs,
-                        |t| t,
-                    );
-                });
-                window_context.window.swap_buffers();
-            }
+            // The skin preview is animated, so everything gets redrawn every frame
+            let primitives = self.ui.draw();
+            let self_glyph_cache_texture = &mut self.glyph_cache_texture;
+            let self_glyph_cache = &mut self.glyph_cache;
+            let self_map = &self.map;
+            let self_skin_preview = &mut self.skin_preview;
+            window_context.gl.draw(r.viewport(), |c, gl| {
+                graphics::clear([0.0, 0.0, 0.0, 1.0], gl);
+                conrod_piston::draw::primitives(
+                    primitives,
+                    c,
+                    gl,
+                    self_glyph_cache_texture,
+                    self_glyph_cache,
+                    self_map,
+                    super::cache_glyphs,
+                    |t| t,
+                );
+
+                // To the right of the options, as tall as it can be while fitting
+                if let Some(skin_preview) = self_skin_preview {
+                    let [window_w, window_h] = r.window_size;
+                    let available_w = window_w - MAIN_CANVAS_WIDTH - 2.0 * PREVIEW_MARGIN;
+                    let stage_height = (window_h - 2.0 * PREVIEW_MARGIN)
+                        .min(available_w / skin_preview.width(1.0));
+                    if stage_height > 0.0 {
+                        let x = window_w - PREVIEW_MARGIN - skin_preview.width(stage_height);
+                        let y = (window_h - stage_height) / 2.0;
+                        skin_preview.render(c.transform.trans(x, y), gl, stage_height);
+                    }
+                }
+            });
+            window_context.window.swap_buffers();
         }
     }
-    fn set_ui(&mut self, config: &mut Config, window_context: &mut WindowContext) {
+    fn set_ui(&mut self, config: &mut Config, audio: &audio::Audio, window_context: &mut WindowContext) {
         let back_button;
         {
             let ui = &mut self.ui.set_widgets();
 
             // Container for all the options
+            // On the left, leaving room for the skin preview
             conrod_core::widget::Canvas::new()
-                .w(640.0)
+                .w(MAIN_CANVAS_WIDTH)
+                .mid_left_of(ui.window)
                 .border(0.0)
                 .pad(50.0)
                 .scroll_kids_vertically()
@@ -616,6 +682,36 @@ impl Options {
                     .map(|s| *self_scroll_speed_input_text = s);
             }
 
+            { // Skin setting
+                // Invisible container around the whole setting to simplify positioning
+                conrod_core::widget::Canvas::new()
+                    .kid_area_w_of(self.ids.main_canvas)
+                    .h(20.0)
+                    .top_right_of(self.ids.main_canvas) // align to inner right side of main canvas (inside the padding)
+                    .down(20.0) // 20 pixels down from the previous widget
+                    .border(0.0)
+                    .set(self.ids.skin_canvas, ui);
+
+                // Text description
+                conrod_core::widget::Text::new("Skin")
+                    .font_size(ui.theme().font_size_small)
+                    .top_left_of(self.ids.skin_canvas)
+                    .set(self.ids.skin_text, ui);
+
+                // Drop down list of every skin in the config
+                let selected = conrod_core::widget::DropDownList::new(&self.skin_names, Some(self.skin_index))
+                    .w_h(150.0, 20.0)
+                    .top_right_of(self.ids.skin_canvas)
+                    .max_visible_items(6)
+                    .border_color(conrod_core::color::WHITE)
+                    .label_font_size(ui.theme().font_size_small)
+                    .set(self.ids.skin_list, ui);
+                if let Some(index) = selected.filter(|&i| i != self.skin_index) {
+                    self.skin_index = index;
+                    self.skin_preview = load_skin_preview(config, index);
+                }
+            }
+
             { // Enable osu hitsounds setting
                 // Invisible container around the whole setting to simplify positioning
                 conrod_core::widget::Canvas::new()
@@ -647,6 +743,51 @@ impl Options {
                     .map(|v| *self_enable_osu_hit_sounds_toggle_value = v);
             }
 
+            { // Volume settings
+                let sliders = [
+                    ("Master volume", audio::Bus::Master, self.ids.master_volume_canvas, self.ids.master_volume_text, self.ids.master_volume_slider),
+                    ("Music volume", audio::Bus::Music, self.ids.music_volume_canvas, self.ids.music_volume_text, self.ids.music_volume_slider),
+                    ("Keysound volume", audio::Bus::Keysound, self.ids.keysound_volume_canvas, self.ids.keysound_volume_text, self.ids.keysound_volume_slider),
+                    ("Autoplay volume", audio::Bus::Autoplay, self.ids.autoplay_volume_canvas, self.ids.autoplay_volume_text, self.ids.autoplay_volume_slider),
+                ];
+                for &(description, bus, canvas_id, text_id, slider_id) in sliders.iter() {
+                    // Invisible container around the whole setting to simplify positioning
+                    conrod_core::widget::Canvas::new()
+                        .kid_area_w_of(self.ids.main_canvas)
+                        .h(20.0)
+                        .top_right_of(self.ids.main_canvas) // align to inner right side of main canvas (inside the padding)
+                        .down(20.0) // 20 pixels down from the previous widget
+                        .border(0.0)
+                        .set(canvas_id, ui);
+
+                    // Text description
+                    conrod_core::widget::Text::new(description)
+                        .font_size(ui.theme().font_size_small)
+                        .top_left_of(canvas_id)
+                        .set(text_id, ui);
+
+                    // Slider
+                    let value = match bus {
+                        audio::Bus::Master => &mut self.volume.master,
+                        audio::Bus::Music => &mut self.volume.music,
+                        audio::Bus::Keysound => &mut self.volume.keysound,
+                        audio::Bus::Autoplay => &mut self.volume.autoplay,
+                    };
+                    let label = format!("{:.0}%", *value * 100.0);
+                    if let Some(v) = conrod_core::widget::Slider::new(*value, 0.0, 1.0)
+                        .w_h(150.0, 20.0)
+                        .top_right_of(canvas_id)
+                        .border_color(conrod_core::color::WHITE)
+                        .label(&label)
+                        .label_font_size(ui.theme().font_size_small)
+                        .set(slider_id, ui)
+                    {
+                        *value = v;
+                        audio.set_volume(bus, v);
+                    }
+                }
+            }
+
             { // Keybindings
                 // Invisible container around the whole setting to simplify positioning
                 conrod_core::widget::Canvas::new()
@@ -762,7 +903,9 @@ impl Options {
             Err(_) => remani_warn!("Failed to parse scroll speed, ignoring..."),
         }
 
+        config.game.set_current_skin_index(self.skin_index);
         config.game.osu_hitsound_enable = self.enable_osu_hit_sounds_toggle_value;
+        config.general.volume = self.volume;
         config.game.key_bindings = self.keybinding_values;
     }
 }
@@ -0,0 +1,186 @@
+//! Plays a short demo pattern with a skin, so skins can be compared without playing a chart
+
+use graphics::{math, Transformed};
+use opengl_graphics::GlGraphics;
+
+use std::time;
+
+use crate::{
+    chart::{self, Snap, TimingPoint, TimingPointValue},
+    config::Config,
+    gameskin::{self, GameSkin, NotePosition},
+    judgement::Judgement,
+};
+
+/// Seconds per beat of the pattern
+const BEAT_LENGTH: f64 = 0.25;
+
+/// How many beats before the pattern starts over
+const PATTERN_BEATS: f64 = 16.0;
+
+/// (column, start beat, end beat for long notes)
+const PATTERN: [(usize, f64, Option<f64>); 16] = [
+    (0, 0.0, None),
+    (2, 1.0, None),
+    (4, 2.0, None),
+    (6, 3.0, None),
+    (1, 4.0, None),
+    (3, 5.0, None),
+    (5, 6.0, None),
+    (3, 7.0, None),
+    (0, 8.0, None),
+    (6, 8.0, None),
+    (1, 9.0, Some(12.0)),
+    (5, 10.0, Some(13.0)),
+    (3, 12.0, None),
+    (2, 13.0, None),
+    (4, 14.0, None),
+    (3, 15.0, None),
+];
+
+/// Each pattern beat is a 16th note, so the notes get a few different snap colours
+fn pattern_snap(start: f64) -> Snap {
+    let timing_point = TimingPoint {
+        offset: 0.0,
+        value: TimingPointValue::BPM(60.0 / (4.0 * BEAT_LENGTH)),
+    };
+    chart::note_snap(&[timing_point], start * BEAT_LENGTH)
+}
+
+/// Pattern beats per beat line, the pattern starts a measure every time it starts over
+const BAR_LINE_BEATS: f64 = 4.0;
+
+/// Lane heights per second
+const SCROLL_SPEED: f64 = 1.5;
+
+/// How long a key stays down for a single note, in seconds
+const PRESS_LENGTH: f64 = 0.08;
+
+/// When a pattern note is pressed and released, in seconds from the start of the pattern
+fn press_times(start: f64, end: Option<f64>) -> (f64, f64) {
+    let press = start * BEAT_LENGTH;
+    (press, end.map(|e| e * BEAT_LENGTH).unwrap_or(press + PRESS_LENGTH))
+}
+
+pub(super) struct SkinPreview {
+    skin: Box<dyn GameSkin<GlGraphics>>,
+    /// Only there if `watch_skin` is on
+    watcher: Option<gameskin::SkinWatcher>,
+    skin_index: usize,
+
+    start: time::Instant,
+    /// Seconds into the pattern at the last render, for noticing which notes got hit since
+    last_time: f64,
+    keys_down: [bool; 7],
+    combo: u32,
+    /// Whether notes are coloured by snap, like in the game
+    snap_colors: bool,
+    notes_pos: Vec<NotePosition>,
+    /// (pos, whether the line starts a measure)
+    bar_lines_pos: Vec<(f64, bool)>,
+}
+
+impl SkinPreview {
+    /// Load skin number `skin_index` from the config
+    pub fn new(config: &Config, skin_index: usize) -> Result<Self, gameskin::ParseError> {
+        let entry = &config.game.skins[skin_index].1;
+        Ok(SkinPreview {
+            skin: gameskin::from_path(&mut (), entry, config)?,
+            watcher: if config.game.watch_skin { Some(gameskin::SkinWatcher::new(entry, config)) } else { None },
+            skin_index,
+            start: time::Instant::now(),
+            // So the notes at the very start get hit the first time through
+            last_time: -1.0,
+            keys_down: [false; 7],
+            combo: 0,
+            snap_colors: config.game.snap_colors,
+            notes_pos: Vec::with_capacity(PATTERN.len() * 2),
+            bar_lines_pos: Vec::with_capacity(8),
+        })
+    }
+
+    /// Load the skin again if its files changed, keeping the old one if the new one can't be
+    /// loaded
+    pub fn reload_if_changed(&mut self, config: &Config) {
+        if !self.watcher.as_mut().map_or(false, |w| w.changed(config)) {
+            return;
+        }
+        match gameskin::from_path(&mut (), &config.game.skins[self.skin_index].1, config) {
+            Ok(skin) => {
+                self.skin = skin;
+                for (column, _) in self.keys_down.iter().enumerate().filter(|&(_, &down)| down) {
+                    self.skin.key_down(column);
+                }
+            }
+            Err(e) => remani_warn!("Error reloading skin: {}", e),
+        }
+    }
+
+    /// How wide the preview is when it's `stage_height` tall
+    pub fn width(&self, stage_height: f64) -> f64 {
+        self.skin.lane_area(stage_height)[2]
+    }
+
+    /// Press and release keys for every note hit between `from` and `to` seconds into the pattern
+    fn play(&mut self, from: f64, to: f64) {
+        for &(column, start, end) in PATTERN.iter() {
+            let (press, release) = press_times(start, end);
+            if from < press && press <= to {
+                self.keys_down[column] = true;
+                self.combo += 1;
+                self.skin.key_down(column);
+                self.skin.draw_judgement(column, Judgement::Perfect);
+                if end.is_some() {
+                    self.skin.long_note_hit_anim_start(column);
+                } else {
+                    self.skin.single_note_hit_anim(column);
+                }
+            }
+            if from < release && release <= to {
+                self.keys_down[column] = false;
+                self.skin.key_up(column);
+                self.skin.long_note_hit_anim_stop(column);
+            }
+        }
+    }
+
+    /// Draw the stage `stage_height` tall with its top left corner at the origin of `transform`
+    pub fn render(&mut self, transform: math::Matrix2d, g: &mut GlGraphics, stage_height: f64) {
+        let length = PATTERN_BEATS * BEAT_LENGTH;
+        let time = self.start.elapsed().as_secs_f64() % length;
+        if time < self.last_time {
+            self.play(self.last_time, length);
+            self.play(-1.0, time);
+        } else {
+            self.play(self.last_time, time);
+        }
+        self.last_time = time;
+
+        // The start of the next loop comes up from the top while this one is ending
+        self.notes_pos.clear();
+        self.bar_lines_pos.clear();
+        for &loop_start in &[0.0, length] {
+            for i in 0..(PATTERN_BEATS / BAR_LINE_BEATS) as usize {
+                let pos = (loop_start + i as f64 * BAR_LINE_BEATS * BEAT_LENGTH - time) * SCROLL_SPEED;
+                if (0.0..=1.0).contains(&pos) {
+                    self.bar_lines_pos.push((pos, i == 0));
+                }
+            }
+            for &(column, start, end) in PATTERN.iter() {
+                let (press, release) = press_times(start, end);
+                let pos = (loop_start + press - time) * SCROLL_SPEED;
+                let end_pos = end.map(|_| (loop_start + release - time) * SCROLL_SPEED);
+                let snap = if self.snap_colors { Some(pattern_snap(start)) } else { None };
+                if pos <= 1.0 && end_pos.unwrap_or(pos) >= 0.0 {
+                    self.notes_pos.push((column, pos, end_pos, snap, 1.0));
+                }
+            }
+        }
+
+        let [x, y, _, _] = self.skin.lane_area(stage_height);
+        let transform = transform.trans(-x, -y);
+        self.skin.draw_play_scene(transform, g, stage_height, &self.keys_down, &self.notes_pos, &self.bar_lines_pos);
+        self.skin.draw_gauge(transform, g, stage_height, 1.0);
+        self.skin.draw_combo(transform, g, stage_height, self.combo);
+    }
+}
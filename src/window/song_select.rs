@@ -5,6 +5,7 @@ use piston::{
 use texture::CreateTexture;
 use conrod_core::{
     Borderable,
+    Colorable,
     Labelable,
     Positionable,
     Sizeable,
@@ -23,9 +24,16 @@ widget_ids! {
         artist_text,
         chart_by_text,
         creator_text,
+        result_text,
+        skin_error_text,
         diff_list_canvas,
         diff_list,
         back_button,
+        practice_toggle,
+        practice_text,
+        practice_start_input,
+        practice_to_text,
+        practice_end_input,
     }
 }
 
@@ -38,10 +46,14 @@ pub struct SongSelect {
     song_list: Vec<chart::ChartSet>,
     /// Index into song_list
     selected_song_index: usize,
+    practice_start_text: String,
+    practice_end_text: String,
+    /// Why the skin couldn't be loaded the last time a chart was picked
+    skin_error: Option<String>,
 }
 
 impl SongSelect {
-    pub(super) fn new(window_context: &mut WindowContext, _config: &Config) -> Self {
+    pub(super) fn new(window_context: &mut WindowContext, config: &Config) -> Self {
         let song_list = window_context.resources.song_list
             .take()
             .unwrap_or_else(||
@@ -81,12 +93,15 @@ impl SongSelect {
             glyph_cache_texture,
             song_list,
             selected_song_index: window_context.resources.last_selected_song_index, // default is 0
+            practice_start_text: config.game.practice.start.to_string(),
+            practice_end_text: config.game.practice.end.map(|p| p.to_string()).unwrap_or_default(),
+            skin_error: None,
         }
     }
     pub(super) fn event(
         &mut self,
         e: piston::input::Event,
-        config: &Config,
+        config: &mut Config,
         audio: &audio::Audio,
         window_context: &mut WindowContext,
     ) {
@@ -119,7 +134,7 @@ impl SongSelect {
             }
         }
     }
-    fn set_ui(&mut self, config: &Config, audio: &audio::Audio, window_context: &mut WindowContext) {
+    fn set_ui(&mut self, config: &mut Config, audio: &audio::Audio, window_context: &mut WindowContext) {
         let ui = &mut self.ui.set_widgets();
 
         { // Song list
@@ -194,6 +209,90 @@ impl SongSelect {
                 .right(0.0)
                 .font_size(15)
                 .set(self.ids.creator_text, ui);
+
+            if let Some(result) = window_context.resources.last_result {
+                let text = format!(
+                    "Last play: {}, score {}, {:.2}% accuracy, max combo {}",
+                    if result.cleared { "cleared" } else { "failed" },
+                    result.score,
+                    result.accuracy * 100.0,
+                    result.max_combo,
+                );
+                conrod_core::widget::Text::new(&text)
+                    .down_from(self.ids.chart_by_text, 5.0)
+                    .font_size(15)
+                    .set(self.ids.result_text, ui);
+            }
+        }
+
+        { // Practice mode settings
+            let practice = &mut config.game.practice;
+            let toggle = conrod_core::widget::Toggle::new(practice.enabled)
+                .w_h(20.0, 20.0)
+                .top_left_with_margins_on(ui.window, ui.win_h/2.0-30.0, 30.0)
+                .border_color(conrod_core::color::WHITE);
+            if practice.enabled {
+                toggle.color(conrod_core::color::WHITE)
+            } else {
+                toggle
+            }.set(self.ids.practice_toggle, ui)
+                .last()
+                .map(|v| practice.enabled = v);
+
+            conrod_core::widget::Text::new("Practice from")
+                .right(10.0)
+                .font_size(15)
+                .set(self.ids.practice_text, ui);
+
+            // Start of the section, in seconds or as a measure like "m16"
+            let self_practice_start_text = &mut self.practice_start_text;
+            let color = match self_practice_start_text.parse() {
+                Ok(p) => {
+                    practice.start = p;
+                    ui.theme().shape_color
+                }
+                Err(()) => conrod_core::color::RED,
+            };
+            conrod_core::widget::TextBox::new(self_practice_start_text)
+                .font_size(15)
+                .w_h(60.0, 20.0)
+                .right(10.0)
+                .color(color)
+                .border_color(conrod_core::color::WHITE)
+                .set(self.ids.practice_start_input, ui)
+                .into_iter()
+                .fold(None, |a, e| if let conrod_core::widget::text_box::Event::Update(s) = e { Some(s) } else { a })
+                .map(|s| *self_practice_start_text = s);
+
+            conrod_core::widget::Text::new("to")
+                .right(10.0)
+                .font_size(15)
+                .set(self.ids.practice_to_text, ui);
+
+            // End of the section, empty means the end of the chart
+            let self_practice_end_text = &mut self.practice_end_text;
+            let color = if self_practice_end_text.trim().is_empty() {
+                practice.end = None;
+                ui.theme().shape_color
+            } else {
+                match self_practice_end_text.parse() {
+                    Ok(p) => {
+                        practice.end = Some(p);
+                        ui.theme().shape_color
+                    }
+                    Err(()) => conrod_core::color::RED,
+                }
+            };
+            conrod_core::widget::TextBox::new(self_practice_end_text)
+                .font_size(15)
+                .w_h(60.0, 20.0)
+                .right(10.0)
+                .color(color)
+                .border_color(conrod_core::color::WHITE)
+                .set(self.ids.practice_end_input, ui)
+                .into_iter()
+                .fold(None, |a, e| if let conrod_core::widget::text_box::Event::Update(s) = e { Some(s) } else { a })
+                .map(|s| *self_practice_end_text = s);
         }
 
         { // Current song difficulty list
@@ -216,13 +315,31 @@ impl SongSelect {
                     .label_font_size(15);
                 if item.set(button, ui).was_clicked() {
                     match chart::osu::from_path(difficulty.path.clone()) {
-                        Ok(x) => Self::change_scene(game::GameScene::new(Box::new(x), config, audio), window_context),
+                        Ok(x) => {
+                            let chart = chart::modifier::apply(Box::new(x), config);
+                            match game::GameScene::new(chart, config, audio, window_context) {
+                                Ok(game_scene) => Self::change_scene(game_scene, window_context),
+                                Err(e) => {
+                                    remani_warn!("Error loading skin: {}", e);
+                                    self.skin_error = Some(format!("Error loading skin: {}", e));
+                                }
+                            }
+                        }
                         Err(e) => println!("{}", e),
                     }
                 }
             }
         }
 
+        if let Some(skin_error) = &self.skin_error {
+            conrod_core::widget::Text::new(skin_error)
+                .bottom_left_with_margins_on(ui.window, 10.0, 30.0)
+                .w(ui.win_w/2.0-60.0)
+                .font_size(15)
+                .color(conrod_core::color::RED)
+                .set(self.ids.skin_error_text, ui);
+        }
+
         // back button
         if conrod_core::widget::Button::new()
             .top_left_of(ui.window)